@@ -0,0 +1,19 @@
+use std::process::Command;
+
+fn main() {
+    // Plugins should be built with the same compiler, see `src/plugin.rs`
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_owned());
+
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+
+    println!(
+        "cargo:rustc-env=NIX_PLUGIN_RUSTC_VERSION={}",
+        version.trim()
+    );
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
@@ -0,0 +1,192 @@
+/*
+ * C API of nix-compiler, a Nix evaluator.
+ *
+ * Link with `libnixc.so` (or `libnixc.a`), built by `cargo build -p nix-compiler-capi`.
+ *
+ * Ownership rules:
+ *
+ *  - Every `nixc_evaluator *`, `nixc_value *` and `nixc_error *` returned by
+ *    this API is owned by the caller, and must be released with
+ *    `nixc_evaluator_free`, `nixc_value_free` and `nixc_error_free`.
+ *  - Every `char *` returned by this API is owned by the caller, and must be
+ *    released with `nixc_string_free`.
+ *  - Every `char **` returned by this API is owned by the caller, and must be
+ *    released with `nixc_strings_free`.
+ *  - Every `const char *` returned by this API is borrowed from the object
+ *    that it came from, and is valid until that object is freed.
+ *  - Arguments are only borrowed during the call, strings must be valid
+ *    NUL-terminated UTF-8.
+ *  - Passing NULL to any of the `*_free` functions does nothing. Passing NULL
+ *    as any other pointer, like `value` or `out`, is an error, except for
+ *    `error` itself.
+ *
+ * Errors:
+ *
+ *  Functions that can fail take a `nixc_error **error` as their last
+ *  argument. On failure they return NULL, false or NIXC_TYPE_ERROR, and if
+ *  `error` is not NULL, `*error` is set to a new error that the caller owns.
+ *  On success `*error` is not modified.
+ *
+ * Threads:
+ *
+ *  Values are not thread-safe: an evaluator and its values must only be used
+ *  from the thread that created the evaluator, and there should be a single
+ *  evaluator per thread. The only exception is `nixc_evaluator_interrupt`,
+ *  which can be called from any thread.
+ *
+ *  Evaluation is recursive, the default maximum depth needs a stack of 8 MiB,
+ *  like the one of the main thread. Threads with a smaller stack should use a
+ *  smaller depth with `nixc_evaluator_set_max_depth`, and a bigger stack
+ *  allows a bigger depth, like 10000 for 64 MiB.
+ *
+ * Laziness:
+ *
+ *  Values are only evaluated when they are inspected. Getting an attribute
+ *  or a list item doesn't evaluate it, so an error in one of them is only
+ *  reported when it's inspected.
+ */
+
+#ifndef NIXC_H
+#define NIXC_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct NixCEvaluator nixc_evaluator;
+typedef struct NixCValue nixc_value;
+typedef struct NixCError nixc_error;
+
+typedef enum {
+    NIXC_TYPE_ERROR = -1,
+    NIXC_TYPE_NULL = 0,
+    NIXC_TYPE_BOOL = 1,
+    NIXC_TYPE_INT = 2,
+    NIXC_TYPE_FLOAT = 3,
+    NIXC_TYPE_STRING = 4,
+    NIXC_TYPE_PATH = 5,
+    NIXC_TYPE_ATTRS = 6,
+    NIXC_TYPE_LIST = 7,
+    NIXC_TYPE_FUNCTION = 8,
+} nixc_type;
+
+/* Evaluator */
+
+/* Creates an evaluator with the default settings. */
+nixc_evaluator *nixc_evaluator_new(void);
+
+/* Destroys the evaluator, its values should be freed before. */
+void nixc_evaluator_free(nixc_evaluator *evaluator);
+
+/* Only allow reading the paths added with `nixc_evaluator_allow_path`. */
+void nixc_evaluator_set_restrict_eval(nixc_evaluator *evaluator, bool restrict_eval);
+
+/* Allow reading `path` and everything inside of it in restricted mode. */
+bool nixc_evaluator_allow_path(nixc_evaluator *evaluator, const char *path, nixc_error **error);
+
+/* Maximum depth of nested expressions, 0 means unlimited. */
+void nixc_evaluator_set_max_depth(nixc_evaluator *evaluator, size_t max_depth);
+
+/* Stops the running evaluation with an error, can be called from any thread. */
+void nixc_evaluator_interrupt(const nixc_evaluator *evaluator);
+
+/* Allows evaluating again after `nixc_evaluator_interrupt`. */
+void nixc_evaluator_reset_interrupt(const nixc_evaluator *evaluator);
+
+/* Evaluation */
+
+/* Evaluates the file at `path`, a directory evaluates its `default.nix`. */
+nixc_value *nixc_eval_file(nixc_evaluator *evaluator, const char *path, nixc_error **error);
+
+/*
+ * Evaluates the expression `expr`, relative paths are resolved from
+ * `base_dir`, or from the current directory if it's NULL. The result is
+ * evaluated, but not its attributes or items.
+ */
+nixc_value *nixc_eval_string(nixc_evaluator *evaluator, const char *expr, const char *base_dir,
+                             nixc_error **error);
+
+/* Values */
+
+void nixc_value_free(nixc_value *value);
+
+/* Evaluates the value and returns its type. */
+nixc_type nixc_value_type(const nixc_value *value, nixc_error **error);
+
+bool nixc_value_get_bool(const nixc_value *value, bool *out, nixc_error **error);
+
+bool nixc_value_get_int(const nixc_value *value, int64_t *out, nixc_error **error);
+
+/* Integers are converted to floats. */
+bool nixc_value_get_float(const nixc_value *value, double *out, nixc_error **error);
+
+/*
+ * Returns a new string, free it with `nixc_string_free`. Strings with a NUL
+ * byte are an error.
+ */
+char *nixc_value_get_string(const nixc_value *value, nixc_error **error);
+
+/* Returns a new string, free it with `nixc_string_free`. */
+char *nixc_value_get_path(const nixc_value *value, nixc_error **error);
+
+/* Amount of attributes of a set, or items of a list. */
+bool nixc_value_len(const nixc_value *value, size_t *out, nixc_error **error);
+
+/*
+ * Names of the attributes of a set sorted by name, followed by NULL. Returns a
+ * new array, free it with `nixc_strings_free`.
+ */
+char **nixc_value_attr_names(const nixc_value *value, nixc_error **error);
+
+/* Attribute `name` of a set, without evaluating it. */
+nixc_value *nixc_value_attr_get(const nixc_value *value, const char *name, nixc_error **error);
+
+/* Item at `index` of a list, without evaluating it. */
+nixc_value *nixc_value_list_get(const nixc_value *value, size_t index, nixc_error **error);
+
+/* Calls `function` with `argument`. */
+nixc_value *nixc_value_call(const nixc_value *function, const nixc_value *argument,
+                            nixc_error **error);
+
+void nixc_string_free(char *string);
+
+/* Frees an array ending with NULL and its strings. */
+void nixc_strings_free(char **strings);
+
+/* Errors */
+
+void nixc_error_free(nixc_error *error);
+
+/* Message of the error, without terminal colors. */
+const char *nixc_error_message(const nixc_error *error);
+
+/* The error as it's printed by the CLI, with the source code and terminal colors. */
+const char *nixc_error_rendered(const nixc_error *error);
+
+/* Amount of labels, which point to the Nix source code related to the error. */
+size_t nixc_error_label_count(const nixc_error *error);
+
+/* Message of the label, can be empty. NULL if `index` is out of bounds. */
+const char *nixc_error_label_message(const nixc_error *error, size_t index);
+
+/* "error", "help" or "todo". NULL if `index` is out of bounds. */
+const char *nixc_error_label_kind(const nixc_error *error, size_t index);
+
+/* File of the label. NULL if `index` is out of bounds. */
+const char *nixc_error_label_file(const nixc_error *error, size_t index);
+
+/* Line of the label, starting at 1. 0 if `index` is out of bounds. */
+size_t nixc_error_label_line(const nixc_error *error, size_t index);
+
+/* Column of the label, starting at 1. 0 if `index` is out of bounds. */
+size_t nixc_error_label_column(const nixc_error *error, size_t index);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NIXC_H */
@@ -0,0 +1,152 @@
+use std::ffi::{c_char, CString};
+use std::ptr;
+
+use nix_compiler::NixError;
+
+/// Error returned to C, the strings are kept alive until it's freed
+pub struct NixCError {
+    message: CString,
+    rendered: CString,
+    labels: Vec<NixCLabel>,
+}
+
+struct NixCLabel {
+    message: CString,
+    kind: CString,
+    file: CString,
+    line: usize,
+    column: usize,
+}
+
+impl NixCError {
+    pub fn from_message(message: impl ToString) -> Self {
+        let message = c_string(message.to_string());
+
+        Self {
+            rendered: message.clone(),
+            message,
+            labels: Vec::new(),
+        }
+    }
+
+    fn label(&self, index: usize) -> Option<&NixCLabel> {
+        self.labels.get(index)
+    }
+}
+
+impl From<NixError> for NixCError {
+    fn from(error: NixError) -> Self {
+        let labels = error
+            .labels
+            .iter()
+            .map(|label| NixCLabel {
+                message: c_string(strip_ansi(&label.label.to_string())),
+                kind: c_string(label.kind.text().to_owned()),
+                file: c_string(label.span.file.path.display().to_string()),
+                line: label.span.start.0,
+                column: label.span.start.1 + 1,
+            })
+            .collect();
+
+        Self {
+            message: c_string(strip_ansi(&error.message)),
+            rendered: c_string(error.to_string()),
+            labels,
+        }
+    }
+}
+
+fn c_string(string: String) -> CString {
+    CString::new(string.replace('\0', "")).unwrap()
+}
+
+/// Remove the terminal colors used in the messages
+fn strip_ansi(string: &str) -> String {
+    let mut out = String::with_capacity(string.len());
+    let mut chars = string.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_free(error: *mut NixCError) {
+    if !error.is_null() {
+        drop(Box::from_raw(error));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_message(error: *const NixCError) -> *const c_char {
+    error
+        .as_ref()
+        .map_or(ptr::null(), |error| error.message.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_rendered(error: *const NixCError) -> *const c_char {
+    error
+        .as_ref()
+        .map_or(ptr::null(), |error| error.rendered.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_label_count(error: *const NixCError) -> usize {
+    error.as_ref().map_or(0, |error| error.labels.len())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_label_message(
+    error: *const NixCError,
+    index: usize,
+) -> *const c_char {
+    error
+        .as_ref()
+        .and_then(|error| error.label(index))
+        .map_or(ptr::null(), |label| label.message.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_label_kind(
+    error: *const NixCError,
+    index: usize,
+) -> *const c_char {
+    error
+        .as_ref()
+        .and_then(|error| error.label(index))
+        .map_or(ptr::null(), |label| label.kind.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_label_file(
+    error: *const NixCError,
+    index: usize,
+) -> *const c_char {
+    error
+        .as_ref()
+        .and_then(|error| error.label(index))
+        .map_or(ptr::null(), |label| label.file.as_ptr())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_label_line(error: *const NixCError, index: usize) -> usize {
+    error
+        .as_ref()
+        .and_then(|error| error.label(index))
+        .map_or(0, |label| label.line)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_error_label_column(error: *const NixCError, index: usize) -> usize {
+    error
+        .as_ref()
+        .and_then(|error| error.label(index))
+        .map_or(0, |label| label.column)
+}
@@ -0,0 +1,561 @@
+//! C ABI of the evaluator, the functions are documented in `include/nixc.h`
+
+#![allow(clippy::missing_safety_doc)]
+
+mod error;
+
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+
+use nix_compiler::{
+    flake, FileScope, LazyNixValue, NixBacktrace, NixLabelKind, NixLabelMessage, NixSettings,
+    NixValue, NixValueWrapped, NixVar,
+};
+
+pub use error::NixCError;
+
+pub struct NixCEvaluator {
+    settings: NixSettings,
+}
+
+/// A value that may not be evaluated yet
+pub struct NixCValue {
+    backtrace: NixBacktrace,
+    var: NixVar,
+}
+
+#[repr(C)]
+pub enum NixCType {
+    Error = -1,
+    Null = 0,
+    Bool = 1,
+    Int = 2,
+    Float = 3,
+    String = 4,
+    Path = 5,
+    Attrs = 6,
+    List = 7,
+    Function = 8,
+}
+
+impl NixCEvaluator {
+    fn install(&self) {
+        self.settings.clone().install();
+    }
+}
+
+impl NixCValue {
+    fn new(backtrace: NixBacktrace, var: NixVar) -> *mut NixCValue {
+        Box::into_raw(Box::new(NixCValue { backtrace, var }))
+    }
+
+    fn resolve(&self) -> Result<NixValueWrapped, NixCError> {
+        Ok(self.var.resolve(&self.backtrace)?)
+    }
+
+    fn type_error(&self, expected: &str, value: &NixValue) -> NixCError {
+        self.backtrace
+            .to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!("expected {expected} but got {}", value.as_type()),
+            )
+            .into()
+    }
+}
+
+/// Runs `f` catching its errors and panics, which are stored in `error`
+unsafe fn ffi<T>(
+    error: *mut *mut NixCError,
+    default: T,
+    f: impl FnOnce() -> Result<T, NixCError>,
+) -> T {
+    let result = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic".to_owned());
+
+        Err(NixCError::from_message(format!("panic: {message}")))
+    });
+
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            if !error.is_null() {
+                *error = Box::into_raw(Box::new(err));
+            }
+
+            default
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(arg: *const c_char, name: &str) -> Result<&'a str, NixCError> {
+    if arg.is_null() {
+        return Err(NixCError::from_message(format!("'{name}' is NULL")));
+    }
+
+    CStr::from_ptr(arg)
+        .to_str()
+        .map_err(|_| NixCError::from_message(format!("'{name}' is not valid UTF-8")))
+}
+
+unsafe fn value_arg<'a>(value: *const NixCValue) -> Result<&'a NixCValue, NixCError> {
+    value
+        .as_ref()
+        .ok_or_else(|| NixCError::from_message("'value' is NULL"))
+}
+
+unsafe fn out_arg<'a, T>(out: *mut T) -> Result<&'a mut T, NixCError> {
+    out.as_mut()
+        .ok_or_else(|| NixCError::from_message("'out' is NULL"))
+}
+
+fn c_string(string: String) -> Result<CString, NixCError> {
+    CString::new(string).map_err(|err| {
+        NixCError::from_message(format!(
+            "The string has a NUL byte at {}, it can't be a C string",
+            err.nul_position()
+        ))
+    })
+}
+
+fn into_c_string(string: String) -> Result<*mut c_char, NixCError> {
+    c_string(string).map(CString::into_raw)
+}
+
+#[no_mangle]
+pub extern "C" fn nixc_evaluator_new() -> *mut NixCEvaluator {
+    let evaluator = NixCEvaluator {
+        settings: NixSettings::default(),
+    };
+
+    evaluator.install();
+
+    Box::into_raw(Box::new(evaluator))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_evaluator_free(evaluator: *mut NixCEvaluator) {
+    if !evaluator.is_null() {
+        drop(Box::from_raw(evaluator));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_evaluator_set_restrict_eval(
+    evaluator: *mut NixCEvaluator,
+    restrict_eval: bool,
+) {
+    if let Some(evaluator) = evaluator.as_mut() {
+        evaluator.settings.restrict_eval = restrict_eval;
+        evaluator.install();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_evaluator_allow_path(
+    evaluator: *mut NixCEvaluator,
+    path: *const c_char,
+    error: *mut *mut NixCError,
+) -> bool {
+    ffi(error, false, || {
+        let evaluator = evaluator
+            .as_mut()
+            .ok_or_else(|| NixCError::from_message("'evaluator' is NULL"))?;
+
+        let path = str_arg(path, "path")?;
+
+        evaluator.settings.allowed_paths.push(PathBuf::from(path));
+        evaluator.install();
+
+        Ok(true)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_evaluator_set_max_depth(
+    evaluator: *mut NixCEvaluator,
+    max_depth: usize,
+) {
+    if let Some(evaluator) = evaluator.as_mut() {
+        evaluator.settings.limits.max_depth = (max_depth != 0).then_some(max_depth);
+        evaluator.install();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_evaluator_interrupt(evaluator: *const NixCEvaluator) {
+    if let Some(evaluator) = evaluator.as_ref() {
+        evaluator.settings.interrupt.interrupt();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_evaluator_reset_interrupt(evaluator: *const NixCEvaluator) {
+    if let Some(evaluator) = evaluator.as_ref() {
+        evaluator.settings.interrupt.reset();
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_eval_file(
+    evaluator: *mut NixCEvaluator,
+    path: *const c_char,
+    error: *mut *mut NixCError,
+) -> *mut NixCValue {
+    ffi(error, ptr::null_mut(), || {
+        let evaluator = evaluator
+            .as_ref()
+            .ok_or_else(|| NixCError::from_message("'evaluator' is NULL"))?;
+
+        let path = str_arg(path, "path")?;
+
+        evaluator.install();
+
+        if evaluator
+            .settings
+            .allowed_path(FileScope::normalize_path(path))
+            .is_none()
+        {
+            return Err(NixCError::from_message(format!(
+                "Access to path '{path}' is forbidden in restricted mode"
+            )));
+        }
+
+        let (backtrace, value) = FileScope::get_file(None, path)?;
+
+        let value = if path.ends_with("flake.nix") {
+            flake::resolve_flake(&backtrace, value)?
+        } else {
+            value
+        };
+
+        Ok(NixCValue::new(
+            backtrace,
+            LazyNixValue::Concrete(value).wrap_var(),
+        ))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_eval_string(
+    evaluator: *mut NixCEvaluator,
+    expr: *const c_char,
+    base_dir: *const c_char,
+    error: *mut *mut NixCError,
+) -> *mut NixCValue {
+    ffi(error, ptr::null_mut(), || {
+        let evaluator = evaluator
+            .as_ref()
+            .ok_or_else(|| NixCError::from_message("'evaluator' is NULL"))?;
+
+        let expr = str_arg(expr, "expr")?;
+
+        let base_dir = if base_dir.is_null() {
+            std::env::current_dir().map_err(|err| NixCError::from_message(err.to_string()))?
+        } else {
+            PathBuf::from(str_arg(base_dir, "base_dir")?)
+        };
+
+        evaluator.install();
+
+        // Relative paths are resolved from the directory of the file
+        let (backtrace, value) = FileScope::repl_file(base_dir.join("«string»"), expr.to_owned())?;
+
+        Ok(NixCValue::new(
+            backtrace,
+            LazyNixValue::Concrete(value).wrap_var(),
+        ))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_free(value: *mut NixCValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_type(
+    value: *const NixCValue,
+    error: *mut *mut NixCError,
+) -> NixCType {
+    ffi(error, NixCType::Error, || {
+        let value = value_arg(value)?.resolve()?;
+        let value = value.borrow();
+
+        Ok(match *value {
+            NixValue::AttrSet(_) => NixCType::Attrs,
+            NixValue::Bool(_) => NixCType::Bool,
+            NixValue::Float(_) => NixCType::Float,
+            NixValue::Int(_) => NixCType::Int,
+            NixValue::Lambda(_) => NixCType::Function,
+            NixValue::List(_) => NixCType::List,
+            NixValue::Null => NixCType::Null,
+            NixValue::Path(_) => NixCType::Path,
+            NixValue::String(_) => NixCType::String,
+        })
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_get_bool(
+    value: *const NixCValue,
+    out: *mut bool,
+    error: *mut *mut NixCError,
+) -> bool {
+    ffi(error, false, || {
+        let value = value_arg(value)?;
+        let out = out_arg(out)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let Some(bool) = resolved.as_bool() else {
+            return Err(value.type_error("a bool", &resolved));
+        };
+
+        *out = bool;
+
+        Ok(true)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_get_int(
+    value: *const NixCValue,
+    out: *mut i64,
+    error: *mut *mut NixCError,
+) -> bool {
+    ffi(error, false, || {
+        let value = value_arg(value)?;
+        let out = out_arg(out)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let Some(int) = resolved.as_int() else {
+            return Err(value.type_error("an integer", &resolved));
+        };
+
+        *out = int;
+
+        Ok(true)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_get_float(
+    value: *const NixCValue,
+    out: *mut f64,
+    error: *mut *mut NixCError,
+) -> bool {
+    ffi(error, false, || {
+        let value = value_arg(value)?;
+        let out = out_arg(out)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        *out = match *resolved {
+            NixValue::Float(float) => float,
+            NixValue::Int(int) => int as f64,
+            _ => return Err(value.type_error("a float", &resolved)),
+        };
+
+        Ok(true)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_get_string(
+    value: *const NixCValue,
+    error: *mut *mut NixCError,
+) -> *mut c_char {
+    ffi(error, ptr::null_mut(), || {
+        let value = value_arg(value)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let Some(string) = resolved.as_string() else {
+            return Err(value.type_error("a string", &resolved));
+        };
+
+        into_c_string(string.clone())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_get_path(
+    value: *const NixCValue,
+    error: *mut *mut NixCError,
+) -> *mut c_char {
+    ffi(error, ptr::null_mut(), || {
+        let value = value_arg(value)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let NixValue::Path(ref path) = *resolved else {
+            return Err(value.type_error("a path", &resolved));
+        };
+
+        into_c_string(path.display().to_string())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_len(
+    value: *const NixCValue,
+    out: *mut usize,
+    error: *mut *mut NixCError,
+) -> bool {
+    ffi(error, false, || {
+        let value = value_arg(value)?;
+        let out = out_arg(out)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        *out = match *resolved {
+            NixValue::AttrSet(ref set) => set.len(),
+            NixValue::List(ref list) => list.0.len(),
+            _ => return Err(value.type_error("a set or a list", &resolved)),
+        };
+
+        Ok(true)
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_attr_names(
+    value: *const NixCValue,
+    error: *mut *mut NixCError,
+) -> *mut *mut c_char {
+    ffi(error, ptr::null_mut(), || {
+        let value = value_arg(value)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let Some(set) = resolved.as_attr_set() else {
+            return Err(value.type_error("a set", &resolved));
+        };
+
+        // Converted before any of them is leaked, so nothing leaks on errors
+        let names = set
+            .keys()
+            .map(|name| c_string(name.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let names = names
+            .into_iter()
+            .map(CString::into_raw)
+            .chain([ptr::null_mut()])
+            .collect::<Box<[_]>>();
+
+        Ok(Box::into_raw(names).cast())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_attr_get(
+    value: *const NixCValue,
+    name: *const c_char,
+    error: *mut *mut NixCError,
+) -> *mut NixCValue {
+    ffi(error, ptr::null_mut(), || {
+        let value = value_arg(value)?;
+        let name = str_arg(name, "name")?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let Some(set) = resolved.as_attr_set() else {
+            return Err(value.type_error("a set", &resolved));
+        };
+
+        let var = set.get(name).cloned().ok_or_else(|| {
+            NixCError::from(value.backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::AttributeMissing,
+                format!("Attribute '{name}' missing"),
+            ))
+        })?;
+
+        Ok(NixCValue::new(value.backtrace.clone(), var))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_list_get(
+    value: *const NixCValue,
+    index: usize,
+    error: *mut *mut NixCError,
+) -> *mut NixCValue {
+    ffi(error, ptr::null_mut(), || {
+        let value = value_arg(value)?;
+        let resolved = value.resolve()?;
+        let resolved = resolved.borrow();
+
+        let Some(list) = resolved.as_list() else {
+            return Err(value.type_error("a list", &resolved));
+        };
+
+        let var = list.0.get(index).cloned().ok_or_else(|| {
+            NixCError::from_message(format!(
+                "Index {index} out of bounds, the list has {} items",
+                list.0.len()
+            ))
+        })?;
+
+        Ok(NixCValue::new(value.backtrace.clone(), var))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_value_call(
+    function: *const NixCValue,
+    argument: *const NixCValue,
+    error: *mut *mut NixCError,
+) -> *mut NixCValue {
+    ffi(error, ptr::null_mut(), || {
+        let function = value_arg(function)?;
+        let argument = value_arg(argument)?;
+        let resolved = function.resolve()?;
+
+        let Some(lambda) = resolved.borrow().as_lambda().cloned() else {
+            return Err(function.type_error("a function", &resolved.borrow()));
+        };
+
+        let var = lambda.call(&function.backtrace, argument.var.clone())?;
+
+        Ok(NixCValue::new(function.backtrace.clone(), var))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn nixc_strings_free(strings: *mut *mut c_char) {
+    if strings.is_null() {
+        return;
+    }
+
+    let mut len = 0;
+
+    while !(*strings.add(len)).is_null() {
+        nixc_string_free(*strings.add(len));
+        len += 1;
+    }
+
+    // With the NULL at the end
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+        strings,
+        len + 1,
+    )));
+}
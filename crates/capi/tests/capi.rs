@@ -0,0 +1,53 @@
+//! Builds `test_capi.c` against the library built by cargo and runs it, like
+//! `make -C crates/capi test`. `cc` (or `$CC`) should be a C compiler.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn test_capi() {
+    // Built next to the test binary, in `target/<profile>/deps`, because the
+    // crate is also an `rlib`
+    let deps_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("capi");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let binary = out_dir.join("test_capi");
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    let output = Command::new(cc)
+        .args(["-Wall", "-Wextra", "-Werror", "-g"])
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-o")
+        .arg(&binary)
+        .arg(manifest_dir.join("tests/test_capi.c"))
+        .arg("-L")
+        .arg(&deps_dir)
+        .arg("-lnixc")
+        .arg(format!("-Wl,-rpath,{}", deps_dir.display()))
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let files_dir = out_dir.join("files");
+    fs::create_dir_all(&files_dir).unwrap();
+
+    let output = Command::new(&binary).arg(&files_dir).output().unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
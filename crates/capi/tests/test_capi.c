@@ -0,0 +1,330 @@
+/*
+ * Exercises the whole C API, run it with `make -C crates/capi test` or with
+ * `cargo test -p nix-compiler-capi`.
+ */
+
+#include <assert.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "nixc.h"
+
+static nixc_evaluator *evaluator;
+
+#define check_error(error) check_error_at(error, __LINE__)
+
+static void check_error_at(nixc_error *error, int line) {
+    if (error != NULL) {
+        fprintf(stderr, "test_capi.c:%d: unexpected error\n%s\n", line, nixc_error_rendered(error));
+        exit(1);
+    }
+}
+
+static nixc_value *eval(const char *expr) {
+    nixc_error *error = NULL;
+    nixc_value *value = nixc_eval_string(evaluator, expr, NULL, &error);
+    check_error(error);
+    assert(value != NULL);
+    return value;
+}
+
+static void test_scalars(void) {
+    nixc_error *error = NULL;
+
+    nixc_value *value = eval("null");
+    assert(nixc_value_type(value, &error) == NIXC_TYPE_NULL);
+    nixc_value_free(value);
+
+    bool b = false;
+    value = eval("1 < 2");
+    assert(nixc_value_type(value, &error) == NIXC_TYPE_BOOL);
+    assert(nixc_value_get_bool(value, &b, &error) && b);
+    nixc_value_free(value);
+
+    int64_t i = 0;
+    double f = 0;
+    value = eval("40 + 2");
+    assert(nixc_value_type(value, &error) == NIXC_TYPE_INT);
+    assert(nixc_value_get_int(value, &i, &error) && i == 42);
+    assert(nixc_value_get_float(value, &f, &error) && f == 42.0);
+    nixc_value_free(value);
+
+    value = eval("1.5");
+    assert(nixc_value_type(value, &error) == NIXC_TYPE_FLOAT);
+    assert(nixc_value_get_float(value, &f, &error) && f == 1.5);
+    nixc_value_free(value);
+
+    value = eval("\"hello ${toString 42}\"");
+    assert(nixc_value_type(value, &error) == NIXC_TYPE_STRING);
+    char *string = nixc_value_get_string(value, &error);
+    assert(string != NULL && strcmp(string, "hello 42") == 0);
+    nixc_string_free(string);
+    nixc_value_free(value);
+
+    nixc_value *path = nixc_eval_string(evaluator, "./file.nix", "/base", &error);
+    check_error(error);
+    assert(nixc_value_type(path, &error) == NIXC_TYPE_PATH);
+    string = nixc_value_get_path(path, &error);
+    assert(string != NULL && strcmp(string, "/base/file.nix") == 0);
+    nixc_string_free(string);
+    nixc_value_free(path);
+
+    check_error(error);
+}
+
+static void test_attrs(void) {
+    nixc_error *error = NULL;
+    nixc_value *set = eval("{ b = [ 1 2 3 ]; a = \"x\"; broken = throw \"lazy\"; }");
+
+    size_t len = 0;
+    assert(nixc_value_type(set, &error) == NIXC_TYPE_ATTRS);
+    assert(nixc_value_len(set, &len, &error) && len == 3);
+
+    const char *expected[] = {"a", "b", "broken"};
+    char **names = nixc_value_attr_names(set, &error);
+    assert(names != NULL);
+    for (size_t index = 0; index < len; index++) {
+        assert(strcmp(names[index], expected[index]) == 0);
+    }
+    assert(names[len] == NULL);
+    nixc_strings_free(names);
+
+    nixc_value *list = nixc_value_attr_get(set, "b", &error);
+    assert(nixc_value_type(list, &error) == NIXC_TYPE_LIST);
+    assert(nixc_value_len(list, &len, &error) && len == 3);
+
+    for (size_t index = 0; index < len; index++) {
+        int64_t item = 0;
+        nixc_value *value = nixc_value_list_get(list, index, &error);
+        assert(nixc_value_get_int(value, &item, &error) && item == (int64_t)index + 1);
+        nixc_value_free(value);
+    }
+
+    check_error(error);
+
+    /* Out of bounds */
+    assert(nixc_value_list_get(list, 3, &error) == NULL);
+    assert(error != NULL);
+    nixc_error_free(error);
+    error = NULL;
+
+    /* Missing attribute */
+    assert(nixc_value_attr_get(set, "c", &error) == NULL);
+    assert(error != NULL && strcmp(nixc_error_message(error), "Attribute 'c' missing") == 0);
+    nixc_error_free(error);
+    error = NULL;
+
+    /* Attributes are lazy, the error is only reported when it's inspected */
+    nixc_value *broken = nixc_value_attr_get(set, "broken", &error);
+    check_error(error);
+    assert(nixc_value_type(broken, &error) == NIXC_TYPE_ERROR);
+    assert(error != NULL && strstr(nixc_error_message(error), "lazy") != NULL);
+    nixc_error_free(error);
+
+    nixc_value_free(broken);
+    nixc_value_free(list);
+    nixc_value_free(set);
+}
+
+static void test_call(void) {
+    nixc_error *error = NULL;
+    nixc_value *function = eval("a: b: a + b");
+    nixc_value *two = eval("2");
+    nixc_value *three = eval("3");
+
+    assert(nixc_value_type(function, &error) == NIXC_TYPE_FUNCTION);
+
+    nixc_value *partial = nixc_value_call(function, two, &error);
+    nixc_value *result = nixc_value_call(partial, three, &error);
+    check_error(error);
+
+    int64_t sum = 0;
+    assert(nixc_value_get_int(result, &sum, &error) && sum == 5);
+
+    /* Builtins can be called too */
+    nixc_value *to_string = eval("builtins.toString");
+    nixc_value *string = nixc_value_call(to_string, result, &error);
+    char *chars = nixc_value_get_string(string, &error);
+    check_error(error);
+    assert(strcmp(chars, "5") == 0);
+
+    /* Calling something that isn't a function */
+    assert(nixc_value_call(two, three, &error) == NULL);
+    assert(error != NULL);
+    nixc_error_free(error);
+
+    nixc_string_free(chars);
+    nixc_value_free(string);
+    nixc_value_free(to_string);
+    nixc_value_free(result);
+    nixc_value_free(partial);
+    nixc_value_free(three);
+    nixc_value_free(two);
+    nixc_value_free(function);
+}
+
+static void test_errors(void) {
+    nixc_error *error = NULL;
+
+    /* Syntax error */
+    assert(nixc_eval_string(evaluator, "{ a = 1 }", NULL, &error) == NULL);
+    assert(error != NULL);
+    assert(nixc_error_label_count(error) > 0);
+    nixc_error_free(error);
+    error = NULL;
+
+    /* Evaluation error, with a label pointing to the source */
+    nixc_value *value = nixc_eval_string(evaluator, "let\n  x = { };\nin\n  x.missing", "/base", &error);
+    assert(value == NULL && error != NULL);
+    assert(strcmp(nixc_error_message(error), "Attribute 'missing' missing") == 0);
+    assert(strstr(nixc_error_rendered(error), "x.missing") != NULL);
+    assert(nixc_error_label_count(error) == 1);
+    assert(strcmp(nixc_error_label_kind(error, 0), "error") == 0);
+    assert(strcmp(nixc_error_label_message(error, 0), "Attribute missing") == 0);
+    assert(strstr(nixc_error_label_file(error, 0), "/base/") == nixc_error_label_file(error, 0));
+    assert(nixc_error_label_line(error, 0) == 4);
+    assert(nixc_error_label_column(error, 0) == 5);
+
+    size_t count = nixc_error_label_count(error);
+    assert(nixc_error_label_kind(error, count) == NULL);
+    assert(nixc_error_label_line(error, count) == 0);
+    nixc_error_free(error);
+    error = NULL;
+
+    /* Wrong type */
+    value = eval("42");
+    assert(nixc_value_get_string(value, &error) == NULL);
+    assert(error != NULL && strcmp(nixc_error_message(error), "expected a string but got int") == 0);
+    nixc_error_free(error);
+    error = NULL;
+    nixc_value_free(value);
+
+    /* Strings with a NUL byte can't be C strings */
+    value = eval("builtins.fromJSON ''\"a\\u0000b\"''");
+    assert(nixc_value_get_string(value, &error) == NULL);
+    assert(error != NULL && strstr(nixc_error_message(error), "NUL") != NULL);
+    nixc_error_free(error);
+    error = NULL;
+
+    /* NULL arguments are reported as errors */
+    assert(nixc_value_attr_get(value, NULL, &error) == NULL);
+    assert(error != NULL && strcmp(nixc_error_message(error), "'name' is NULL") == 0);
+    nixc_error_free(error);
+    error = NULL;
+
+    assert(!nixc_value_get_int(value, NULL, &error));
+    assert(error != NULL && strcmp(nixc_error_message(error), "'out' is NULL") == 0);
+    nixc_error_free(error);
+    nixc_value_free(value);
+
+    /* The error can be ignored */
+    assert(nixc_eval_string(evaluator, "throw \"ignored\"", NULL, NULL) == NULL);
+}
+
+static void test_files(const char *dir) {
+    nixc_error *error = NULL;
+    char path[4096];
+
+    snprintf(path, sizeof(path), "%s/main.nix", dir);
+    FILE *file = fopen(path, "w");
+    assert(file != NULL);
+    fputs("{ value = import ./other.nix; }\n", file);
+    fclose(file);
+
+    snprintf(path, sizeof(path), "%s/other.nix", dir);
+    file = fopen(path, "w");
+    assert(file != NULL);
+    fputs("[ \"from\" \"file\" ]\n", file);
+    fclose(file);
+
+    snprintf(path, sizeof(path), "%s/main.nix", dir);
+    nixc_value *set = nixc_eval_file(evaluator, path, &error);
+    nixc_value *list = nixc_value_attr_get(set, "value", &error);
+    nixc_value *item = nixc_value_list_get(list, 1, &error);
+    char *string = nixc_value_get_string(item, &error);
+    check_error(error);
+    assert(strcmp(string, "file") == 0);
+
+    nixc_string_free(string);
+    nixc_value_free(item);
+    nixc_value_free(list);
+    nixc_value_free(set);
+
+    /* Restricted mode */
+    nixc_evaluator_set_restrict_eval(evaluator, true);
+
+    assert(nixc_eval_file(evaluator, path, &error) == NULL);
+    assert(error != NULL && strstr(nixc_error_message(error), "forbidden") != NULL);
+    nixc_error_free(error);
+    error = NULL;
+
+    assert(nixc_evaluator_allow_path(evaluator, dir, &error));
+    set = nixc_eval_file(evaluator, path, &error);
+    check_error(error);
+    assert(set != NULL);
+    nixc_value_free(set);
+
+    nixc_value *value = eval("{ content = builtins.readFile /etc/hostname; }");
+    nixc_value *content = nixc_value_attr_get(value, "content", &error);
+    assert(nixc_value_type(content, &error) == NIXC_TYPE_ERROR);
+    assert(error != NULL && strstr(nixc_error_message(error), "forbidden") != NULL);
+    nixc_error_free(error);
+    nixc_value_free(content);
+    nixc_value_free(value);
+
+    nixc_evaluator_set_restrict_eval(evaluator, false);
+}
+
+static void test_limits(void) {
+    nixc_error *error = NULL;
+
+    nixc_value *value = eval("[ (let f = x: f x; in f 1) (1 + 1) ]");
+    nixc_value *item = nixc_value_list_get(value, 0, &error);
+    assert(nixc_value_type(item, &error) == NIXC_TYPE_ERROR);
+    assert(error != NULL && strstr(nixc_error_message(error), "stack overflow") != NULL);
+    nixc_error_free(error);
+    error = NULL;
+    nixc_value_free(item);
+
+    nixc_evaluator_interrupt(evaluator);
+    item = nixc_value_list_get(value, 1, &error);
+    assert(nixc_value_type(item, &error) == NIXC_TYPE_ERROR);
+    assert(error != NULL && strstr(nixc_error_message(error), "interrupted") != NULL);
+    nixc_error_free(error);
+    error = NULL;
+
+    nixc_evaluator_reset_interrupt(evaluator);
+    assert(nixc_value_type(item, &error) == NIXC_TYPE_INT);
+    check_error(error);
+    nixc_value_free(item);
+    nixc_value_free(value);
+}
+
+int main(int argc, char **argv) {
+    const char *dir = argc > 1 ? argv[1] : "/tmp";
+
+    evaluator = nixc_evaluator_new();
+
+    /* The main thread has a small stack */
+    nixc_evaluator_set_max_depth(evaluator, 1000);
+
+    test_scalars();
+    test_attrs();
+    test_call();
+    test_errors();
+    test_files(dir);
+    test_limits();
+
+    nixc_evaluator_free(evaluator);
+
+    /* Freeing NULL does nothing */
+    nixc_evaluator_free(NULL);
+    nixc_value_free(NULL);
+    nixc_error_free(NULL);
+    nixc_string_free(NULL);
+
+    printf("All tests passed\n");
+
+    return 0;
+}
@@ -1,32 +1,29 @@
 use convert_case::{Case, Casing};
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, TokenStream, TokenTree};
 use quote::{format_ident, quote, quote_spanned};
-use venial::{Error, Function};
+use venial::{AttributeValue, Error, Function};
 
 use crate::params::NixBuiltinParams;
 
-const REFLECTION_BUILTIN: &str = "__rust_reflection__nix-macros__builtins";
-
-pub fn get_builtins() -> Result<String, Error> {
-    std::env::var(REFLECTION_BUILTIN).map_err(|_| Error::new("Set at least one builtin"))
-}
-
-fn append_builtin(name: String) {
-    if let Ok(old) = std::env::var(REFLECTION_BUILTIN) {
-        std::env::set_var(REFLECTION_BUILTIN, format!("{old};{name}"))
-    } else {
-        std::env::set_var(REFLECTION_BUILTIN, name)
-    }
-}
-
 pub struct Builtin {
     func: Function,
     params: NixBuiltinParams,
     struct_name: Ident,
+    /// Name given in `#[builtin("name")]`
+    name: Option<String>,
 }
 
 impl Builtin {
-    pub fn new(func: Function) -> Result<Self, Error> {
+    pub fn new(func: Function, attribute: TokenStream) -> Result<Self, Error> {
+        let name = match attribute.into_iter().collect::<Vec<_>>().as_slice() {
+            [] => None,
+            [TokenTree::Literal(literal)] => match unescape_string(&literal.to_string()) {
+                Some(name) => Some(name),
+                None => return Err(Error::new_at_span(literal.span(), "Expected a string")),
+            },
+            [token, ..] => return Err(Error::new_at_span(token.span(), "Expected a string")),
+        };
+
         let func_name = func.name.to_string();
         let struct_name = func_name
             .strip_prefix("r#")
@@ -35,19 +32,20 @@ impl Builtin {
 
         let struct_name = format_ident!("{struct_name}", span = func.name.span());
 
-        append_builtin(struct_name.to_string());
-
         let params = NixBuiltinParams::new(&struct_name, &func.params)?;
 
         Ok(Self {
             func,
             struct_name,
             params,
+            name,
         })
     }
 
     fn nix_ident(&self) -> String {
-        self.struct_name.to_string().to_case(Case::Camel)
+        self.name
+            .clone()
+            .unwrap_or_else(|| self.struct_name.to_string().to_case(Case::Camel))
     }
 
     fn generate_builtin(&self) -> TokenStream {
@@ -59,10 +57,25 @@ impl Builtin {
 
         quote_spanned! { self.func.tk_params_parens.span =>
             impl crate::builtins::NixBuiltin for #struct_name {
-                fn get_name(&self) -> &'static str {
+                fn get_name(&self) -> &str {
                     #nix_ident
                 }
 
+                fn arguments(&self) -> &[crate::builtins::NixBuiltinArgument] {
+                    <Self as crate::builtins::NixBuiltinInfo>::ARGUMENTS
+                }
+
+                fn applied(&self) -> usize {
+                    let Self(#(#params_list),*) = self;
+                    let applied: &[bool] = &[#(#params_list.is_some()),*];
+
+                    applied.iter().filter(|applied| **applied).count()
+                }
+
+                fn doc(&self) -> &str {
+                    <Self as crate::builtins::NixBuiltinInfo>::DOC
+                }
+
                 fn run(
                     &self,
                     backtrace: &crate::NixBacktrace,
@@ -97,8 +110,8 @@ impl Builtin {
             .ok_or_else(|| Error::new_at_span(self.func.span(), "Function should have body"))?;
         let func_body = quote_spanned! {func_body.span() => #func_body};
 
-        let func_params = &self.func.params;
-        let func_params = quote_spanned! {self.func.tk_params_parens.span => #func_params};
+        let func_params = &self.params.run_params;
+        let func_params = quote_spanned! {self.func.tk_params_parens.span => #(#func_params),*};
 
         Ok(quote_spanned! { self.func.span() =>
             impl #struct_name {
@@ -113,22 +126,70 @@ impl Builtin {
         })
     }
 
+    /// Doc comments of the function, without the space after `///`
+    fn doc(&self) -> String {
+        let lines = self
+            .func
+            .attributes
+            .iter()
+            .filter(|attribute| {
+                attribute
+                    .get_single_path_segment()
+                    .is_some_and(|ident| ident == "doc")
+            })
+            .filter_map(|attribute| match &attribute.value {
+                AttributeValue::Equals(_, tokens) => match tokens.as_slice() {
+                    [TokenTree::Literal(literal)] => unescape_string(&literal.to_string()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .map(|line| line.strip_prefix(' ').map(str::to_owned).unwrap_or(line))
+            .collect::<Vec<_>>();
+
+        lines.join("\n").trim().to_owned()
+    }
+
     fn generate_info(&self) -> TokenStream {
         let nix_ident = self.nix_ident();
         let struct_name = &self.struct_name;
+        let arguments = &self.params.arguments;
+        let doc = self.doc();
 
         quote_spanned! { self.struct_name.span() =>
             impl crate::builtins::NixBuiltinInfo for #struct_name {
                 const NAME: &str = #nix_ident;
+                const ARGUMENTS: &[crate::builtins::NixBuiltinArgument] = &[#(#arguments),*];
+                const DOC: &str = #doc;
             }
         }
     }
 
+    /// Adds the builtin to `crate::builtins::BUILTINS` at link time, which is
+    /// read by `get_builtins()`
+    fn generate_registration(&self) -> TokenStream {
+        let struct_name = &self.struct_name;
+        let static_name = format_ident!(
+            "__BUILTIN_{}",
+            struct_name.to_string().to_case(Case::UpperSnake),
+            span = struct_name.span()
+        );
+
+        quote_spanned! { self.struct_name.span() =>
+            #[::linkme::distributed_slice(crate::builtins::BUILTINS)]
+            static #static_name: (&str, fn() -> crate::value::NixValue) = (
+                <#struct_name as crate::builtins::NixBuiltinInfo>::NAME,
+                #struct_name::generate,
+            );
+        }
+    }
+
     pub fn generate(self) -> Result<TokenStream, Error> {
         let decl = self.generate_declaration();
         let def_impl = self.generate_impl()?;
         let builtin = self.generate_builtin();
         let builtin_info = self.generate_info();
+        let registration = self.generate_registration();
 
         Ok(quote! {
             #decl
@@ -138,6 +199,39 @@ impl Builtin {
             #builtin
 
             #builtin_info
+
+            #registration
         })
     }
 }
+
+/// Value of a string literal like `"a \"b\""`, `None` if it isn't one
+fn unescape_string(literal: &str) -> Option<String> {
+    let literal = literal.strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut out = String::with_capacity(literal.len());
+    let mut chars = literal.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next()? {
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            '0' => out.push('\0'),
+            'u' => {
+                let code = chars.by_ref().skip(1).take_while(|c| *c != '}');
+                let code = u32::from_str_radix(&code.collect::<String>(), 16).ok()?;
+
+                out.push(char::from_u32(code)?);
+            }
+            c => out.push(c),
+        }
+    }
+
+    Some(out)
+}
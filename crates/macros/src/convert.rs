@@ -0,0 +1,422 @@
+use convert_case::{Case, Casing};
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use quote::{quote, quote_spanned};
+use venial::{Attribute, AttributeValue, Enum, Error, Fields, Item, NamedField, Struct};
+
+/// Options of `#[nix(...)]`
+#[derive(Default)]
+struct NixAttrs {
+    rename: Option<String>,
+    rename_all: Option<Case>,
+    /// `Some(None)` for `Default::default`
+    default: Option<Option<TokenStream>>,
+}
+
+impl NixAttrs {
+    fn parse(attributes: &[Attribute]) -> Result<Self, Error> {
+        let mut attrs = NixAttrs::default();
+
+        for attribute in attributes {
+            if attribute
+                .get_single_path_segment()
+                .map_or(true, |ident| ident != "nix")
+            {
+                continue;
+            }
+
+            let AttributeValue::Group(_, ref tokens) = attribute.value else {
+                return Err(Error::new_at_tokens(
+                    attribute,
+                    "Expected #[nix(...)] with options",
+                ));
+            };
+
+            for option in
+                tokens.split(|token| matches!(token, TokenTree::Punct(p) if p.as_char() == ','))
+            {
+                attrs.parse_option(option)?;
+            }
+        }
+
+        Ok(attrs)
+    }
+
+    fn parse_option(&mut self, option: &[TokenTree]) -> Result<(), Error> {
+        let (name, value) = match option {
+            [] => return Ok(()),
+            [TokenTree::Ident(name)] => (name, None),
+            [TokenTree::Ident(name), TokenTree::Punct(eq), TokenTree::Literal(literal)]
+                if eq.as_char() == '=' =>
+            {
+                let value = literal.to_string();
+
+                let Some(value) = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                else {
+                    return Err(Error::new_at_span(literal.span(), "Expected a string"));
+                };
+
+                (name, Some((value.to_owned(), literal.span())))
+            }
+            _ => {
+                return Err(Error::new_at_span(
+                    option[0].span(),
+                    "Expected `name` or `name = \"value\"`",
+                ))
+            }
+        };
+
+        match (name.to_string().as_str(), value) {
+            ("rename", Some((value, _))) => self.rename = Some(value),
+            ("rename_all", Some((value, span))) => {
+                self.rename_all = Some(match value.as_str() {
+                    "camelCase" => Case::Camel,
+                    "PascalCase" => Case::Pascal,
+                    "snake_case" => Case::Snake,
+                    "kebab-case" => Case::Kebab,
+                    "SCREAMING_SNAKE_CASE" => Case::ScreamingSnake,
+                    "lowercase" => Case::Flat,
+                    _ => return Err(Error::new_at_span(span, "Unknown case")),
+                })
+            }
+            ("default", None) => self.default = Some(None),
+            ("default", Some((value, span))) => {
+                let path = value
+                    .parse::<TokenStream>()
+                    .map_err(|_| Error::new_at_span(span, "Expected a path to a function"))?;
+
+                self.default = Some(Some(path));
+            }
+            (name, _) => {
+                return Err(Error::new_at_span(
+                    option[0].span(),
+                    format!("Unknown option '{name}'"),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Name of a field or variant in Nix
+fn nix_name(ident: &Ident, attrs: &NixAttrs, rename_all: Option<Case>) -> String {
+    if let Some(rename) = &attrs.rename {
+        return rename.clone();
+    }
+
+    let name = ident.to_string();
+    let name = name.strip_prefix("r#").unwrap_or(&name);
+
+    match rename_all {
+        Some(case) => name.to_case(case),
+        None => name.to_owned(),
+    }
+}
+
+struct Field<'a> {
+    field: &'a NamedField,
+    name: String,
+    attrs: NixAttrs,
+}
+
+fn named_fields(fields: &Fields, rename_all: Option<Case>) -> Result<Vec<Field<'_>>, Error> {
+    let Fields::Named(fields) = fields else {
+        unreachable!("Only called for named fields")
+    };
+
+    fields
+        .fields
+        .items()
+        .map(|field| {
+            let attrs = NixAttrs::parse(&field.attributes)?;
+            let name = nix_name(&field.name, &attrs, rename_all);
+
+            Ok(Field { field, name, attrs })
+        })
+        .collect()
+}
+
+pub enum Derive {
+    FromNix,
+    IntoNix,
+}
+
+impl Derive {
+    pub fn expand(self, input: TokenStream) -> Result<TokenStream, Error> {
+        match venial::parse_item(input)? {
+            Item::Struct(item) => self.expand_struct(item),
+            Item::Enum(item) => self.expand_enum(item),
+            item => Err(Error::new_at_tokens(
+                item,
+                "Only structs and enums are supported",
+            )),
+        }
+    }
+
+    fn trait_path(&self) -> TokenStream {
+        match self {
+            Derive::FromNix => quote!(::nix_compiler::builtins::FromNixExpr),
+            Derive::IntoNix => quote!(::nix_compiler::builtins::IntoNix),
+        }
+    }
+
+    fn expand_struct(self, item: Struct) -> Result<TokenStream, Error> {
+        let attrs = NixAttrs::parse(&item.attributes)?;
+        let trait_path = self.trait_path();
+        let name = &item.name;
+        let generic_params = &item.generic_params;
+        let generic_args = item.get_inline_generic_args();
+        let where_clause = item.create_derive_where_clause(trait_path.clone());
+
+        let nix_type = match &item.fields {
+            Fields::Tuple(fields) if fields.fields.len() == 1 => {
+                let ty = &fields.fields[0].0.ty;
+                quote!(<#ty as ::nix_compiler::builtins::FromNixExpr>::TYPE)
+            }
+            _ => quote!("a set"),
+        };
+
+        let body = match (&self, &item.fields) {
+            (_, Fields::Named(_)) => {
+                let fields = named_fields(&item.fields, attrs.rename_all)?;
+
+                match self {
+                    Derive::FromNix => {
+                        let fields = from_nix_fields(&fields);
+
+                        quote! {
+                            let set = <::nix_compiler::NixAttrSet as ::nix_compiler::builtins::FromNixExpr>::from_nix_expr(backtrace, var)?;
+
+                            Ok(Self { #(#fields),* })
+                        }
+                    }
+                    Derive::IntoNix => {
+                        let (bindings, set) = into_nix_fields(&fields);
+
+                        quote! {
+                            let Self { #(#bindings),* } = self;
+
+                            #set
+                        }
+                    }
+                }
+            }
+            // Newtypes are converted like the type that they wrap
+            (Derive::FromNix, Fields::Tuple(fields)) if fields.fields.len() == 1 => quote! {
+                ::nix_compiler::builtins::FromNixExpr::from_nix_expr(backtrace, var).map(Self)
+            },
+            (Derive::IntoNix, Fields::Tuple(fields)) if fields.fields.len() == 1 => quote! {
+                ::nix_compiler::builtins::IntoNix::into_nix(self.0)
+            },
+            _ => {
+                return Err(Error::new_at_span(
+                    item.name.span(),
+                    "Only structs with named fields and newtypes are supported",
+                ))
+            }
+        };
+
+        Ok(self.wrap_impl(
+            quote!(#generic_params),
+            quote!(#name #generic_args #where_clause),
+            nix_type,
+            body,
+        ))
+    }
+
+    fn expand_enum(self, item: Enum) -> Result<TokenStream, Error> {
+        let attrs = NixAttrs::parse(&item.attributes)?;
+        let trait_path = self.trait_path();
+        let name = &item.name;
+        let generic_params = &item.generic_params;
+        let generic_args = item.get_inline_generic_args();
+        let where_clause = item.create_derive_where_clause(trait_path.clone());
+
+        let mut variant_names = Vec::new();
+        let mut arms = Vec::new();
+
+        for variant in item.variants.items() {
+            let variant_attrs = NixAttrs::parse(&variant.attributes)?;
+            let ident = &variant.name;
+            let nix_name = nix_name(ident, &variant_attrs, attrs.rename_all);
+
+            let arm = match (&self, &variant.fields) {
+                (Derive::FromNix, Fields::Unit) => quote! {
+                    (#nix_name, None) => Ok(Self::#ident)
+                },
+                (Derive::IntoNix, Fields::Unit) => quote! {
+                    Self::#ident => ::nix_compiler::NixValue::String(#nix_name.to_owned())
+                },
+                (Derive::FromNix, Fields::Tuple(fields)) if fields.fields.len() == 1 => quote! {
+                    (#nix_name, Some((backtrace, var))) => {
+                        ::nix_compiler::builtins::FromNixExpr::from_nix_expr(&backtrace, var).map(Self::#ident)
+                    }
+                },
+                (Derive::IntoNix, Fields::Tuple(fields)) if fields.fields.len() == 1 => {
+                    let set = tagged(
+                        &nix_name,
+                        quote!(::nix_compiler::builtins::IntoNix::into_nix(value)),
+                    );
+
+                    quote! {
+                        Self::#ident(value) => #set
+                    }
+                }
+                (Derive::FromNix, Fields::Named(_)) => {
+                    let fields = named_fields(&variant.fields, variant_attrs.rename_all)?;
+                    let fields = from_nix_fields(&fields);
+
+                    quote! {
+                        (#nix_name, Some((backtrace, var))) => {
+                            let backtrace = &backtrace;
+                            let set = <::nix_compiler::NixAttrSet as ::nix_compiler::builtins::FromNixExpr>::from_nix_expr(backtrace, var)?;
+
+                            Ok(Self::#ident { #(#fields),* })
+                        }
+                    }
+                }
+                (Derive::IntoNix, Fields::Named(_)) => {
+                    let fields = named_fields(&variant.fields, variant_attrs.rename_all)?;
+                    let (bindings, set) = into_nix_fields(&fields);
+                    let set = tagged(&nix_name, quote!({ #set }));
+
+                    quote! {
+                        Self::#ident { #(#bindings),* } => #set
+                    }
+                }
+                _ => {
+                    return Err(Error::new_at_span(
+                        ident.span(),
+                        "Only unit, newtype and struct variants are supported",
+                    ))
+                }
+            };
+
+            variant_names.push(nix_name);
+            arms.push(arm);
+        }
+
+        let body = match self {
+            Derive::FromNix => quote! {
+                let (variant, payload) = ::nix_compiler::builtins::from_nix_variant(backtrace, var)?;
+
+                match (variant.as_str(), payload) {
+                    #(#arms,)*
+                    (variant, _) => Err(::nix_compiler::builtins::unknown_variant(
+                        backtrace,
+                        variant,
+                        &[#(#variant_names),*],
+                    )),
+                }
+            },
+            Derive::IntoNix => quote! {
+                match self {
+                    #(#arms,)*
+                }
+            },
+        };
+
+        Ok(self.wrap_impl(
+            quote!(#generic_params),
+            quote!(#name #generic_args #where_clause),
+            quote!("a string or a set with a single attribute"),
+            body,
+        ))
+    }
+
+    /// `nix_type` is only used by `FromNix`, see `FromNixExpr::TYPE`
+    fn wrap_impl(
+        &self,
+        generic_params: TokenStream,
+        ty: TokenStream,
+        nix_type: TokenStream,
+        body: TokenStream,
+    ) -> TokenStream {
+        let trait_path = self.trait_path();
+
+        match self {
+            Derive::FromNix => quote! {
+                impl #generic_params #trait_path for #ty {
+                    const TYPE: &'static str = #nix_type;
+
+                    fn from_nix_expr(
+                        backtrace: &::nix_compiler::NixBacktrace,
+                        var: ::nix_compiler::NixVar,
+                    ) -> ::nix_compiler::NixResult<Self> {
+                        #body
+                    }
+                }
+            },
+            Derive::IntoNix => quote! {
+                impl #generic_params #trait_path for #ty {
+                    fn into_nix(self) -> ::nix_compiler::NixValue {
+                        #body
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// `field: from_nix_attr(...)` for every field, uses `backtrace` and `set`
+fn from_nix_fields(fields: &[Field]) -> Vec<TokenStream> {
+    fields
+        .iter()
+        .map(|Field { field, name, attrs }| {
+            let ident = &field.name;
+            let ty = &field.ty;
+
+            let default = match &attrs.default {
+                None => quote!(None),
+                Some(None) => quote!(Some(<#ty as ::std::default::Default>::default)),
+                Some(Some(path)) => quote!(Some(#path)),
+            };
+
+            quote_spanned! { ident.span() =>
+                #ident: ::nix_compiler::builtins::from_nix_attr::<#ty>(backtrace, &set, #name, #default)?
+            }
+        })
+        .collect()
+}
+
+/// Bindings of the fields, and an expression that builds the set from them
+fn into_nix_fields(fields: &[Field]) -> (Vec<Ident>, TokenStream) {
+    let bindings = fields
+        .iter()
+        .map(|Field { field, .. }| field.name.clone())
+        .collect::<Vec<_>>();
+
+    let inserts = fields.iter().map(|Field { field, name, .. }| {
+        let ident = &field.name;
+
+        quote_spanned! { ident.span() =>
+            set.insert(
+                #name.to_owned(),
+                ::nix_compiler::builtins::IntoNix::into_nix(#ident).wrap_var(),
+            );
+        }
+    });
+
+    let set = quote! {
+        let mut set = ::nix_compiler::NixAttrSet::new();
+
+        #(#inserts)*
+
+        ::nix_compiler::NixValue::AttrSet(set)
+    };
+
+    (bindings, set)
+}
+
+/// `{ name = value; }`
+fn tagged(name: &str, value: TokenStream) -> TokenStream {
+    quote! {
+        {
+            let mut set = ::nix_compiler::NixAttrSet::new();
+            set.insert(#name.to_owned(), #value.wrap_var());
+            ::nix_compiler::NixValue::AttrSet(set)
+        }
+    }
+}
@@ -1,14 +1,19 @@
 mod builtin;
+mod convert;
+mod nix;
 mod params;
 
-use builtin::{get_builtins, Builtin};
+use builtin::Builtin;
+use convert::Derive;
 use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
+use quote::quote;
 use venial::{parse_item, Error, Item};
 
+/// The name in Nix is the camelCase name of the function, it can be given as
+/// `#[builtin("toJSON")]` when it can't be derived from it
 #[proc_macro_attribute]
 pub fn builtin(
-    _: proc_macro::TokenStream,
+    attribute: proc_macro::TokenStream,
     body: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     let func = match parse_item(body.into()) {
@@ -17,34 +22,76 @@ pub fn builtin(
         Ok(_) => Err(Error::new("")),
     };
 
-    func.and_then(Builtin::new)
+    func.and_then(|func| Builtin::new(func, attribute.into()))
         .and_then(Builtin::generate)
         .unwrap_or_else(|e| e.to_compile_error())
         .into()
 }
 
-#[proc_macro]
-pub fn gen_builtins(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    gen_builtins_impl(input.into())
+/// Implements `FromNixExpr`, attribute sets are converted to structs, and
+/// strings or sets with a single attribute are converted to enums.
+///
+/// Options of `#[nix(...)]`:
+/// - `rename = "name"` on fields and variants
+/// - `rename_all = "camelCase"` on structs, enums and struct variants
+/// - `default` or `default = "path::to::fn"` on fields, used when the
+///   attribute is missing. `Option` fields are `None` when missing.
+#[proc_macro_derive(FromNix, attributes(nix))]
+pub fn derive_from_nix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    Derive::FromNix
+        .expand(input.into())
         .unwrap_or_else(|e| e.to_compile_error())
         .into()
 }
 
-fn gen_builtins_impl(input: TokenStream) -> Result<TokenStream, Error> {
-    let builtins = get_builtins()?
-        .split(";")
-        .map(|builtin| {
-            let builtin = format_ident!("{builtin}");
+/// Implements `IntoNix`, the opposite of `#[derive(FromNix)]`, with the
+/// same options
+#[proc_macro_derive(IntoNix, attributes(nix))]
+pub fn derive_into_nix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    Derive::IntoNix
+        .expand(input.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-            quote! { builtins.insert(<#builtin as crate::builtins::NixBuiltinInfo>::NAME.to_owned(), #builtin::generate().wrap_var()) }
-        })
-        .collect::<Vec<_>>();
+/// Nix expression that is parsed at compile time, and evaluated later with
+/// `NixExpr::eval`. Syntax errors are reported on the token that caused them.
+///
+/// `#{expr}` splices a Rust expression that implements `IntoNix`, it can be
+/// used wherever a Nix expression is expected:
+///
+/// ```ignore
+/// let name = "world";
+/// let greeting = nix! { "hello " + #{name} };
+/// ```
+///
+/// The code has to be valid Rust tokens too, so indented strings `''...''`
+/// can't be used, and `/* */` comments are ignored. `//` is an error, since
+/// it starts a Rust comment and the update operator would be lost.
+/// Spacing is kept, except when `nix!` is called by another macro, where
+/// paths and names like `a-b` should be written in a string or a splice.
+#[proc_macro]
+pub fn nix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    nix::expand(input.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
 
-    Ok(quote! {
+#[proc_macro]
+pub fn gen_builtins(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    gen_builtins_impl(input.into()).into()
+}
+
+fn gen_builtins_impl(input: TokenStream) -> TokenStream {
+    quote! {
         pub fn get_builtins() -> NixValue {
             let mut builtins = crate::NixAttrSet::new();
 
-            #(#builtins;)*
+            for (name, generate) in crate::builtins::BUILTINS {
+                let previous = builtins.insert((*name).to_owned(), generate().wrap_var());
+
+                assert!(previous.is_none(), "Builtin {name} is defined twice");
+            }
 
             {
                 macro_rules! insert {
@@ -62,5 +109,5 @@ fn gen_builtins_impl(input: TokenStream) -> Result<TokenStream, Error> {
 
             NixValue::AttrSet(builtins)
         }
-    })
+    }
 }
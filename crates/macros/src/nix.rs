@@ -0,0 +1,325 @@
+use proc_macro2::{Delimiter, Group, Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned};
+use rnix::parser::ParseError;
+use rnix::SyntaxKind;
+use venial::Error;
+
+/// Nix source rebuilt from the tokens of `nix!`
+struct Source<'a> {
+    /// Text of the invocation between the delimiters, used to keep the
+    /// original spacing. It's `None` when it isn't available, like when
+    /// `nix!` is called by another macro, the tokens are separated by spaces
+    /// instead.
+    text: Option<&'a str>,
+    cursor: usize,
+    out: String,
+    /// Offset in `out` where each token starts, to point parse errors to it
+    spans: Vec<(usize, Span)>,
+    /// Expressions of `#{...}`, replaced by a variable in `out`
+    splices: Vec<Group>,
+    /// Token before the first `//`, the rest of its line is a Rust comment
+    line_comment: Option<Span>,
+}
+
+impl<'a> Source<'a> {
+    fn new(text: Option<&'a str>) -> Self {
+        Self {
+            text,
+            cursor: 0,
+            out: String::new(),
+            spans: Vec::new(),
+            splices: Vec::new(),
+            line_comment: None,
+        }
+    }
+
+    fn splice_name(index: usize) -> String {
+        format!("__nix_splice_{index}")
+    }
+
+    /// Returns `None` if the tokens don't match the text
+    fn push_tokens(&mut self, tokens: TokenStream) -> Option<()> {
+        let mut tokens = tokens.into_iter().peekable();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                TokenTree::Punct(ref punct) if punct.as_char() == '#' => {
+                    // Doc comments are Rust comments too, and they're skipped
+                    // with the others
+                    if punct
+                        .span()
+                        .source_text()
+                        .is_some_and(|text| text.starts_with('/'))
+                    {
+                        if matches!(tokens.peek(), Some(TokenTree::Punct(p)) if p.as_char() == '!')
+                        {
+                            tokens.next();
+                        }
+
+                        tokens.next();
+                        continue;
+                    }
+
+                    let splice = match tokens.peek() {
+                        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                            group.clone()
+                        }
+                        _ => {
+                            self.push_str(&punct.to_string(), punct.span())?;
+                            continue;
+                        }
+                    };
+
+                    // `#{` without spaces is a splice, otherwise it's a comment
+                    if self.push_splice(punct.span(), &splice).is_none() {
+                        self.push_str("#", punct.span())?;
+                        continue;
+                    }
+
+                    tokens.next();
+                }
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+
+                    self.push_str(open, group.span_open())?;
+                    self.push_tokens(group.stream())?;
+                    self.push_str(close, group.span_close())?;
+                }
+                token => self.push_str(&token.to_string(), token.span())?,
+            }
+        }
+
+        Some(())
+    }
+
+    fn push_splice(&mut self, span: Span, splice: &Group) -> Option<()> {
+        let name = Self::splice_name(self.splices.len());
+
+        match self.text {
+            Some(text) => {
+                let splice_text = splice.span().source_text()?;
+
+                let (cursor, len) = (self.cursor, self.out.len());
+                self.push_str("#", span)?;
+
+                if !text[self.cursor..].starts_with(&splice_text) {
+                    self.cursor = cursor;
+                    self.out.truncate(len);
+                    self.spans.pop();
+
+                    return None;
+                }
+
+                self.cursor += splice_text.len();
+                self.out.pop();
+            }
+            None => {
+                self.push_space();
+                self.spans.push((self.out.len(), span));
+            }
+        }
+
+        self.out.push_str(&name);
+        self.splices.push(splice.clone());
+
+        Some(())
+    }
+
+    fn push_space(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with(' ') {
+            self.out.push(' ');
+        }
+    }
+
+    fn push_str(&mut self, string: &str, span: Span) -> Option<()> {
+        let Some(text) = self.text else {
+            self.push_space();
+            self.spans.push((self.out.len(), span));
+            self.out.push_str(string);
+
+            return Some(());
+        };
+
+        loop {
+            let rest = &text[self.cursor..];
+            let trimmed = rest.trim_start();
+
+            self.out.push_str(&rest[..rest.len() - trimmed.len()]);
+            self.cursor += rest.len() - trimmed.len();
+
+            // Rust comments aren't part of the Nix code. `//` is also the
+            // update operator, so it's reported instead of being ignored
+            let comment_len = if trimmed.starts_with("//") {
+                if self.line_comment.is_none() {
+                    self.line_comment = Some(self.spans.last().map_or(span, |(_, span)| *span));
+                }
+
+                trimmed.find('\n').unwrap_or(trimmed.len())
+            } else if trimmed.starts_with("/*") {
+                trimmed.find("*/")? + 2
+            } else if trimmed.starts_with(string) {
+                self.spans.push((self.out.len(), span));
+                self.out.push_str(string);
+                self.cursor += string.len();
+
+                return Some(());
+            } else {
+                return None;
+            };
+
+            let comment = &trimmed[..comment_len];
+            self.out
+                .extend(comment.chars().filter(|c| *c == '\n').map(|_| '\n'));
+            self.cursor += comment_len;
+        }
+    }
+
+    fn span_at(&self, offset: usize) -> Span {
+        let index = self.spans.partition_point(|(start, _)| *start <= offset);
+
+        self.spans
+            .get(index.saturating_sub(1))
+            .map_or_else(Span::call_site, |(_, span)| *span)
+    }
+}
+
+/// Text between the delimiters of the macro invocation, like `nix! { ... }`
+fn invocation_text(invocation: &str) -> Option<&str> {
+    let start = invocation.find('!')?;
+    let open = invocation[start..].find(['{', '(', '['])? + start;
+
+    invocation.get(open + 1..invocation.len() - 1)
+}
+
+fn kind_to_string(kind: SyntaxKind) -> String {
+    use SyntaxKind::*;
+
+    let kind = match kind {
+        TOKEN_SEMICOLON => ";",
+        TOKEN_COLON => ":",
+        TOKEN_COMMA => ",",
+        TOKEN_ASSIGN => "=",
+        TOKEN_L_BRACE => "{",
+        TOKEN_R_BRACE => "}",
+        TOKEN_L_PAREN => "(",
+        TOKEN_R_PAREN => ")",
+        TOKEN_L_BRACK => "[",
+        TOKEN_R_BRACK => "]",
+        TOKEN_IN => "in",
+        TOKEN_THEN => "then",
+        TOKEN_ELSE => "else",
+        kind => {
+            let kind = format!("{kind:?}");
+            let kind = kind
+                .trim_start_matches("TOKEN_")
+                .trim_start_matches("NODE_");
+
+            return kind.to_lowercase().replace('_', " ");
+        }
+    };
+
+    format!("`{kind}`")
+}
+
+fn kinds_to_string(kinds: &[SyntaxKind]) -> String {
+    kinds
+        .iter()
+        .map(|kind| kind_to_string(*kind))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn parse_error(source: &Source, error: &ParseError) -> Error {
+    let (offset, message) = match error {
+        ParseError::Unexpected(range) | ParseError::UnexpectedExtra(range) => {
+            (range.start().into(), "unexpected token".to_owned())
+        }
+        ParseError::UnexpectedWanted(kind, range, wanted) => (
+            range.start().into(),
+            format!(
+                "unexpected {}, expected {}",
+                kind_to_string(*kind),
+                kinds_to_string(wanted)
+            ),
+        ),
+        ParseError::UnexpectedDoubleBind(range) => {
+            (range.start().into(), "pattern is bound twice".to_owned())
+        }
+        ParseError::UnexpectedEOFWanted(wanted) => (
+            source.out.len(),
+            format!(
+                "unexpected end of input, expected {}",
+                kinds_to_string(wanted)
+            ),
+        ),
+        ParseError::DuplicatedArgs(range, name) => (
+            range.start().into(),
+            format!("duplicated argument `{name}`"),
+        ),
+        error => (source.out.len(), error.to_string()),
+    };
+
+    Error::new_at_span(source.span_at(offset), format!("Invalid Nix: {message}"))
+}
+
+pub fn expand(input: TokenStream) -> Result<TokenStream, Error> {
+    let invocation = Span::call_site().source_text();
+
+    let source = invocation
+        .as_deref()
+        .and_then(invocation_text)
+        .and_then(|text| {
+            let mut source = Source::new(Some(text));
+            source.push_tokens(input.clone())?;
+
+            // Only whitespace and comments can be left
+            source.push_str("", Span::call_site())?;
+            source.spans.pop();
+
+            (source.cursor == text.len()).then_some(source)
+        })
+        .unwrap_or_else(|| {
+            let mut source = Source::new(None);
+            source.push_tokens(input).unwrap();
+            source
+        });
+
+    if let Some(span) = source.line_comment {
+        return Err(Error::new_at_span(
+            span,
+            "`//` can't be used in `nix!`, the rest of the line is a Rust comment",
+        ));
+    }
+
+    if source.out.trim().is_empty() {
+        return Err(Error::new("Expected a Nix expression"));
+    }
+
+    if let Some(error) = rnix::Root::parse(&source.out).errors().first() {
+        return Err(parse_error(&source, error));
+    }
+
+    let code = &source.out;
+
+    let splices = source.splices.iter().enumerate().map(|(index, splice)| {
+        let name = Source::splice_name(index);
+        let expr = splice.stream();
+
+        quote_spanned! {splice.span()=>
+            (#name, ::nix_compiler::IntoNix::into_nix(#expr))
+        }
+    });
+
+    Ok(quote! {
+        ::nix_compiler::NixExpr::new(
+            #code,
+            ::core::concat!(::core::file!(), ":", ::core::line!(), ":", ::core::column!()),
+            ::std::vec![#(#splices),*],
+        )
+    })
+}
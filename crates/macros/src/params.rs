@@ -1,38 +1,144 @@
 use std::ops::Not;
 
+use convert_case::{Case, Casing};
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::{format_ident, quote, quote_spanned};
-use venial::{Error, FnParam, Punctuated, TypeExpr};
+use venial::{Attribute, AttributeValue, Error, FnParam, Punctuated, TypeExpr};
 
 pub struct NixBuiltinParams {
     pub decl: Vec<TokenStream>,
     pub def: Vec<TokenStream>,
+    /// `NixBuiltinArgument` of each parameter
+    pub arguments: Vec<TokenStream>,
+    /// Parameters of the function, without `#[lazy]` and `#[optional]`
+    pub run_params: Vec<TokenStream>,
 
     spans: Vec<Span>,
 }
 
+/// How the argument is converted, from the attributes of the parameter
+enum ParamKind {
+    Strict,
+    /// `#[lazy]`, the body receives a `NixLazy` that converts it when it's
+    /// forced
+    Lazy,
+    /// `#[optional]` or `#[optional(default)]`, `null` is replaced by the
+    /// default value
+    Optional(TokenStream),
+}
+
+impl ParamKind {
+    fn parse(attributes: &[Attribute]) -> Result<Self, Error> {
+        let mut kind = ParamKind::Strict;
+
+        for attribute in attributes {
+            let Some(ident) = attribute.get_single_path_segment() else {
+                continue;
+            };
+
+            let new_kind = match (ident.to_string().as_str(), &attribute.value) {
+                ("lazy", AttributeValue::Empty) => ParamKind::Lazy,
+                ("optional", AttributeValue::Empty) => {
+                    ParamKind::Optional(quote!(::std::default::Default::default()))
+                }
+                ("optional", AttributeValue::Group(_, tokens)) => {
+                    ParamKind::Optional(quote!(#(#tokens)*))
+                }
+                ("lazy" | "optional", _) => {
+                    return Err(Error::new_at_tokens(
+                        attribute,
+                        "Expected #[lazy], #[optional] or #[optional(default)]",
+                    ))
+                }
+                _ => continue,
+            };
+
+            if !matches!(kind, ParamKind::Strict) {
+                return Err(Error::new_at_tokens(
+                    attribute,
+                    "A parameter can't be both lazy and optional",
+                ));
+            }
+
+            kind = new_kind;
+        }
+
+        Ok(kind)
+    }
+
+    fn is_param_attribute(attribute: &Attribute) -> bool {
+        attribute
+            .get_single_path_segment()
+            .is_some_and(|ident| ident == "lazy" || ident == "optional")
+    }
+}
+
+struct Param<'a> {
+    name: &'a Ident,
+    ty: &'a TypeExpr,
+    kind: ParamKind,
+}
+
+impl Param<'_> {
+    /// Converts `var` to the type that the body receives, `backtrace` is a
+    /// `&NixBacktrace`
+    fn convert(
+        &self,
+        struct_name: &Ident,
+        position: usize,
+        backtrace: TokenStream,
+        var: TokenStream,
+    ) -> TokenStream {
+        let ty = self.ty;
+        let name = quote!(<#struct_name as crate::builtins::NixBuiltinInfo>::NAME);
+
+        match &self.kind {
+            ParamKind::Strict => quote_spanned! {self.name.span() =>
+                crate::builtins::from_builtin_argument::<#ty>(#backtrace, #var, #name, #position)?
+            },
+            ParamKind::Lazy => quote_spanned! {self.name.span() =>
+                crate::builtins::NixLazy::<#ty>::new(#backtrace, #var, #name, #position)
+            },
+            ParamKind::Optional(default) => quote_spanned! {self.name.span() =>
+                crate::builtins::from_builtin_optional::<#ty>(#backtrace, #var, #name, #position, || #default)?
+            },
+        }
+    }
+}
+
 impl NixBuiltinParams {
     pub fn new(
         struct_name: &Ident,
         params: &Punctuated<FnParam>,
     ) -> Result<NixBuiltinParams, Error> {
-        let params = params
+        let typed_params = params
             .items()
             .filter_map(|param| match param {
                 venial::FnParam::Receiver(receiver) => {
                     Some(Err(Error::new_at_tokens(receiver, "self is not permitted")))
                 }
-                venial::FnParam::Typed(venial::FnTypedParam { name, ty, .. }) => {
-                    ty.tokens.is_empty().not().then_some(Ok((name, ty)))
+                venial::FnParam::Typed(param) => {
+                    param.ty.tokens.is_empty().not().then_some(Ok(param))
                 }
             })
             .collect::<Result<Vec<_>, _>>()?;
 
+        let params = typed_params
+            .iter()
+            .map(|param| {
+                Ok(Param {
+                    name: &param.name,
+                    ty: &param.ty,
+                    kind: ParamKind::parse(&param.attributes)?,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
         // Extract backtrace
         let backtrace = params
             .first()
-            .filter(|p| &p.0.to_string() == "backtrace")
-            .map(|p| p.0.span());
+            .filter(|p| &p.name.to_string() == "backtrace")
+            .map(|p| p.name.span());
 
         let has_backtrace = backtrace.is_some();
 
@@ -40,18 +146,56 @@ impl NixBuiltinParams {
 
         let total_params = params.len() - has_backtrace_offset;
 
+        // Parameters of the body, lazy ones receive a `NixLazy`
+        let run_params = typed_params
+            .iter()
+            .zip(&params)
+            .map(|(typed, param)| {
+                let attributes = typed
+                    .attributes
+                    .iter()
+                    .filter(|attribute| !ParamKind::is_param_attribute(attribute));
+                let tk_mut = &typed.tk_mut;
+                let name = param.name;
+                let ty = param.ty;
+
+                match param.kind {
+                    ParamKind::Lazy => quote_spanned! {ty.span() =>
+                        #(#attributes)* #tk_mut #name: crate::builtins::NixLazy<#ty>
+                    },
+                    _ => quote!(#(#attributes)* #tk_mut #name: #ty),
+                }
+            })
+            .collect();
+
+        let arguments = params
+            .iter()
+            .skip(has_backtrace_offset)
+            .map(|param| {
+                let name = param.name.to_string().to_case(Case::Camel);
+                let ty = param.ty;
+
+                quote_spanned! {param.name.span() =>
+                    crate::builtins::NixBuiltinArgument {
+                        name: #name,
+                        ty: <#ty as crate::builtins::FromNixExpr>::TYPE,
+                    }
+                }
+            })
+            .collect();
+
         let spans = params
             .iter()
             .skip(has_backtrace_offset)
-            .map(|(ident, _)| ident.span())
+            .map(|param| param.name.span())
             .collect();
 
         // Define parameter collection
         let (decl, def) = params
-            .into_iter()
+            .iter()
             .skip(has_backtrace_offset)
             .enumerate()
-            .map(|(idx, (param, ty))| parse_param(idx, total_params, struct_name, param, ty))
+            .map(|(idx, param)| parse_param(idx, total_params, struct_name, param))
             .collect::<(Vec<TokenStream>, Vec<TokenStream>)>();
 
         let def = if let Some(backtrace) = backtrace {
@@ -62,7 +206,13 @@ impl NixBuiltinParams {
             def
         };
 
-        Ok(NixBuiltinParams { decl, def, spans })
+        Ok(NixBuiltinParams {
+            decl,
+            def,
+            arguments,
+            run_params,
+            spans,
+        })
     }
 
     pub fn param_list(&self) -> Vec<Ident> {
@@ -102,31 +252,36 @@ fn parse_param(
     idx: usize,
     total_params: usize,
     struct_name: &Ident,
-    param: &Ident,
-    ty: &TypeExpr,
+    param: &Param,
 ) -> (TokenStream, TokenStream) {
     let is_last = idx == total_params - 1;
+    let position = idx + 1;
+    let ty = param.ty;
+    let param_name = param.name;
 
     if is_last {
         let decl = quote! {};
-        let def = quote_spanned! {param.span() => <#ty as crate::builtins::FromNixExpr>::from_nix_expr(&backtrace, argument)?};
+        let def = param.convert(struct_name, position, quote!(backtrace), quote!(argument));
 
         (decl, def)
     } else {
-        let param_ident = format_ident!("__param_{idx}", span = param.span());
+        let param_ident = format_ident!("__param_{idx}", span = param_name.span());
 
         let prev_params = (0..idx)
-            .map(|i| format_ident!("__param_{i}", span = param.span()))
+            .map(|i| format_ident!("__param_{i}", span = param_name.span()))
             .collect::<Vec<_>>();
         let next_params = ((idx + 1)..(total_params - 1))
-            .map(|_| format_ident!("None", span = param.span()))
+            .map(|_| format_ident!("None", span = param_name.span()))
             .collect::<Vec<_>>();
         let new_param =
             quote_spanned! {ty.span() => Some(::std::rc::Rc::new((backtrace.clone(), argument)))};
 
-        let def = quote_spanned! {param.span() =>
-            <#ty as crate::builtins::FromNixExpr>::from_nix_expr(& #param_ident.0, #param_ident.1.clone())?
-        };
+        let def = param.convert(
+            struct_name,
+            position,
+            quote!(&#param_ident.0),
+            quote!(#param_ident.1.clone()),
+        );
 
         let decl = quote_spanned! {ty.span() =>
             let Some(#param_ident) = #param_ident else {
@@ -0,0 +1,520 @@
+//! Python bindings of the evaluator, built with `maturin`.
+//!
+//! ```python
+//! import pynix
+//!
+//! config = pynix.Evaluator().eval_file("config.nix")
+//! config.services["nginx"].enable  # Only evaluates what's accessed
+//! ```
+//!
+//! Values are converted to Python when they are accessed: attribute sets,
+//! lists and functions become proxies that evaluate their items lazily,
+//! everything else becomes the equivalent Python type.
+//!
+//! The values use reference counting that isn't thread-safe, so an
+//! `Evaluator` and its values can only be used from the thread that created
+//! them.
+//!
+//! The crate isn't part of the workspace because it needs Python, `cargo test`
+//! in its directory runs the Python tests in `tests/test_pynix.py`.
+
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use nix_compiler::{
+    flake, FileScope, LazyNixValue, NixAttrSet, NixBacktrace, NixLambda, NixLimits, NixList,
+    NixSettings, NixValue, NixVar,
+};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyAttributeError, PyException, PyIndexError, PyKeyError, PyTypeError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList, PyString, PyTuple};
+
+create_exception!(
+    pynix,
+    NixError,
+    PyException,
+    "Error while evaluating Nix code, the message is the error as it's printed by the CLI."
+);
+
+fn to_py_err(error: nix_compiler::NixError) -> PyErr {
+    NixError::new_err(error.to_string())
+}
+
+/// Evaluates Nix files and expressions with its own settings
+#[pyclass(unsendable, module = "pynix")]
+pub struct Evaluator {
+    settings: Rc<NixSettings>,
+}
+
+#[pymethods]
+impl Evaluator {
+    #[new]
+    #[pyo3(signature = (*, restrict_eval = false, allowed_paths = Vec::new(), max_depth = NixLimits::default().max_depth, max_thunks = None))]
+    fn new(
+        restrict_eval: bool,
+        allowed_paths: Vec<PathBuf>,
+        max_depth: Option<usize>,
+        max_thunks: Option<usize>,
+    ) -> Self {
+        let mut settings = NixSettings::default();
+
+        settings.restrict_eval = restrict_eval;
+        settings.allowed_paths = allowed_paths;
+        settings.limits.max_depth = max_depth;
+        settings.limits.max_thunks = max_thunks;
+
+        Self {
+            settings: Rc::new(settings),
+        }
+    }
+
+    /// Evaluates the file at `path`, a directory evaluates its `default.nix`
+    fn eval_file(slf: Bound<'_, Self>, path: PathBuf) -> PyResult<PyObject> {
+        let evaluator = slf.borrow();
+
+        evaluator.install();
+
+        if evaluator
+            .settings
+            .allowed_path(FileScope::normalize_path(&path))
+            .is_none()
+        {
+            return Err(NixError::new_err(format!(
+                "Access to path '{}' is forbidden in restricted mode",
+                path.display()
+            )));
+        }
+
+        let (backtrace, value) = FileScope::get_file(None, &path).map_err(to_py_err)?;
+
+        let value = if path.ends_with("flake.nix") {
+            flake::resolve_flake(&backtrace, value).map_err(to_py_err)?
+        } else {
+            value
+        };
+
+        let var = LazyNixValue::Concrete(value).wrap_var();
+
+        Value::new(slf.clone().unbind(), backtrace, var).into_python(slf.py())
+    }
+
+    /// Evaluates the expression `expr`, relative paths are resolved from
+    /// `base_dir`, or from the current directory if it's not given
+    #[pyo3(signature = (expr, base_dir = None))]
+    fn eval_string(
+        slf: Bound<'_, Self>,
+        expr: String,
+        base_dir: Option<PathBuf>,
+    ) -> PyResult<PyObject> {
+        let base_dir = match base_dir {
+            Some(base_dir) => base_dir,
+            None => std::env::current_dir()?,
+        };
+
+        slf.borrow().install();
+
+        let (backtrace, value) =
+            FileScope::repl_file(base_dir.join("«string»"), expr).map_err(to_py_err)?;
+
+        let var = LazyNixValue::Concrete(value).wrap_var();
+
+        Value::new(slf.clone().unbind(), backtrace, var).into_python(slf.py())
+    }
+}
+
+impl Evaluator {
+    /// The settings are per thread, so they are installed before evaluating
+    /// in case that there are multiple evaluators. The limits apply to the
+    /// whole evaluation, including the values accessed later
+    fn install(&self) {
+        NixSettings::install_shared(self.settings.clone());
+    }
+
+    /// Used when accessing the values of an evaluation, so the limits
+    /// aren't restarted
+    fn resume(&self) {
+        NixSettings::resume(&self.settings);
+    }
+}
+
+/// A value that may not be evaluated yet
+struct Value {
+    evaluator: Py<Evaluator>,
+    backtrace: NixBacktrace,
+    var: NixVar,
+}
+
+impl Value {
+    fn new(evaluator: Py<Evaluator>, backtrace: NixBacktrace, var: NixVar) -> Self {
+        Self {
+            evaluator,
+            backtrace,
+            var,
+        }
+    }
+
+    /// Evaluates the value, converting it to a Python value or proxy
+    fn into_python(self, py: Python<'_>) -> PyResult<PyObject> {
+        self.evaluator.borrow(py).resume();
+
+        let value = self.var.resolve(&self.backtrace).map_err(to_py_err)?;
+        let value = value.borrow();
+
+        let Value {
+            evaluator,
+            backtrace,
+            ..
+        } = self;
+
+        Ok(match *value {
+            NixValue::AttrSet(ref set) => AttrSet {
+                evaluator,
+                backtrace,
+                set: set.clone(),
+            }
+            .into_py(py),
+            NixValue::Bool(bool) => bool.into_py(py),
+            NixValue::Float(float) => float.into_py(py),
+            NixValue::Int(int) => int.into_py(py),
+            NixValue::Lambda(ref lambda) => Function {
+                evaluator,
+                backtrace,
+                lambda: lambda.clone(),
+            }
+            .into_py(py),
+            NixValue::List(ref list) => List {
+                evaluator,
+                backtrace,
+                list: list.clone(),
+            }
+            .into_py(py),
+            NixValue::Null => py.None(),
+            NixValue::Path(ref path) => py
+                .import_bound("pathlib")?
+                .getattr("Path")?
+                .call1((path.clone(),))?
+                .unbind(),
+            NixValue::String(ref string) => string.into_py(py),
+        })
+    }
+}
+
+/// Converts a Python value to Nix, proxies are converted back to the value
+/// that they wrap
+fn from_python(value: &Bound<'_, PyAny>) -> PyResult<NixVar> {
+    if let Ok(set) = value.downcast::<AttrSet>() {
+        return Ok(NixValue::AttrSet(set.borrow().set.clone()).wrap_var());
+    }
+
+    if let Ok(list) = value.downcast::<List>() {
+        return Ok(NixValue::List(list.borrow().list.clone()).wrap_var());
+    }
+
+    if let Ok(function) = value.downcast::<Function>() {
+        return Ok(NixValue::Lambda(function.borrow().lambda.clone()).wrap_var());
+    }
+
+    let value = if value.is_none() {
+        NixValue::Null
+    } else if let Ok(bool) = value.downcast::<pyo3::types::PyBool>() {
+        NixValue::Bool(bool.is_true())
+    } else if let Ok(int) = value.downcast::<pyo3::types::PyInt>() {
+        NixValue::Int(int.extract()?)
+    } else if let Ok(float) = value.downcast::<pyo3::types::PyFloat>() {
+        NixValue::Float(float.value())
+    } else if let Ok(string) = value.downcast::<PyString>() {
+        NixValue::String(string.to_str()?.to_owned())
+    } else if let Ok(dict) = value.downcast::<PyDict>() {
+        let mut set = NixAttrSet::new();
+
+        for (key, value) in dict {
+            let key = key
+                .downcast::<PyString>()
+                .map_err(|_| PyTypeError::new_err("Nix attribute names should be strings"))?;
+
+            set.insert(key.to_str()?.to_owned(), from_python(&value)?);
+        }
+
+        NixValue::AttrSet(set)
+    } else if let Ok(list) = value.downcast::<PyList>() {
+        let list = list
+            .iter()
+            .map(|item| from_python(&item))
+            .collect::<PyResult<_>>()?;
+
+        NixValue::List(NixList(std::rc::Rc::new(list)))
+    } else if let Ok(tuple) = value.downcast::<PyTuple>() {
+        let list = tuple
+            .iter()
+            .map(|item| from_python(&item))
+            .collect::<PyResult<_>>()?;
+
+        NixValue::List(NixList(std::rc::Rc::new(list)))
+    } else if value.is_instance(&value.py().import_bound("pathlib")?.getattr("PurePath")?)? {
+        NixValue::Path(value.extract::<PathBuf>()?)
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "Cannot convert {} to a Nix value",
+            value.get_type().name()?
+        )));
+    };
+
+    Ok(value.wrap_var())
+}
+
+/// Converts a value and everything inside of it to Python, so attribute sets
+/// become dicts and lists become lists
+fn to_python_deep(py: Python<'_>, value: PyObject) -> PyResult<PyObject> {
+    let value = value.into_bound(py);
+
+    if let Ok(set) = value.downcast::<AttrSet>() {
+        let dict = PyDict::new_bound(py);
+
+        for name in set.borrow().set.keys() {
+            let item = set.borrow().get_item(py, name)?;
+            dict.set_item(name, to_python_deep(py, item)?)?;
+        }
+
+        return Ok(dict.into_any().unbind());
+    }
+
+    if let Ok(list) = value.downcast::<List>() {
+        let len = list.borrow().list.0.len();
+        let items = (0..len)
+            .map(|index| to_python_deep(py, list.borrow().get_index(py, index)?))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        return Ok(PyList::new_bound(py, items).into_any().unbind());
+    }
+
+    Ok(value.unbind())
+}
+
+/// Attribute set whose attributes are evaluated when accessed, with
+/// `set["name"]` or `set.name`
+#[pyclass(unsendable, module = "pynix")]
+pub struct AttrSet {
+    evaluator: Py<Evaluator>,
+    backtrace: NixBacktrace,
+    set: NixAttrSet,
+}
+
+impl AttrSet {
+    fn get_item(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        let var = self
+            .set
+            .get(name)
+            .ok_or_else(|| PyKeyError::new_err(name.to_owned()))?;
+
+        Value::new(
+            self.evaluator.clone_ref(py),
+            self.backtrace.clone(),
+            var.clone(),
+        )
+        .into_python(py)
+    }
+}
+
+#[pymethods]
+impl AttrSet {
+    fn __len__(&self) -> usize {
+        self.set.len()
+    }
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.set.contains_key(name)
+    }
+
+    fn __getitem__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        self.get_item(py, name)
+    }
+
+    fn __getattr__(&self, py: Python<'_>, name: &str) -> PyResult<PyObject> {
+        if !self.set.contains_key(name) {
+            return Err(PyAttributeError::new_err(format!(
+                "Attribute '{name}' missing"
+            )));
+        }
+
+        self.get_item(py, name)
+    }
+
+    fn __iter__(&self, py: Python<'_>) -> PyResult<PyObject> {
+        Ok(PyList::new_bound(py, self.set.keys())
+            .into_any()
+            .iter()?
+            .into_any()
+            .unbind())
+    }
+
+    fn __dir__(&self) -> Vec<String> {
+        self.set.keys().cloned().collect()
+    }
+
+    fn __repr__(&self) -> String {
+        let names = self
+            .set
+            .keys()
+            .map(|name| format!("{name:?}"))
+            .collect::<Vec<_>>();
+
+        format!("AttrSet([{}])", names.join(", "))
+    }
+
+    /// Names of the attributes, sorted
+    fn keys(&self) -> Vec<String> {
+        self.set.keys().cloned().collect()
+    }
+
+    /// Evaluates the attribute `name`, or returns `default` if it's missing
+    #[pyo3(signature = (name, default = None))]
+    fn get(&self, py: Python<'_>, name: &str, default: Option<PyObject>) -> PyResult<PyObject> {
+        if self.set.contains_key(name) {
+            self.get_item(py, name)
+        } else {
+            Ok(default.unwrap_or_else(|| py.None()))
+        }
+    }
+
+    /// Evaluates every attribute, returning `(name, value)` pairs
+    fn items(&self, py: Python<'_>) -> PyResult<Vec<(String, PyObject)>> {
+        self.set
+            .keys()
+            .map(|name| Ok((name.clone(), self.get_item(py, name)?)))
+            .collect()
+    }
+
+    /// Evaluates everything inside of the set, converting it to a dict
+    fn to_python(slf: Bound<'_, Self>) -> PyResult<PyObject> {
+        to_python_deep(slf.py(), slf.into_any().unbind())
+    }
+}
+
+/// List whose items are evaluated when accessed
+#[pyclass(unsendable, module = "pynix")]
+pub struct List {
+    evaluator: Py<Evaluator>,
+    backtrace: NixBacktrace,
+    list: NixList,
+}
+
+impl List {
+    fn get_index(&self, py: Python<'_>, index: usize) -> PyResult<PyObject> {
+        let var = self
+            .list
+            .0
+            .get(index)
+            .ok_or_else(|| PyIndexError::new_err("list index out of range"))?;
+
+        Value::new(
+            self.evaluator.clone_ref(py),
+            self.backtrace.clone(),
+            var.clone(),
+        )
+        .into_python(py)
+    }
+}
+
+#[pymethods]
+impl List {
+    fn __len__(&self) -> usize {
+        self.list.0.len()
+    }
+
+    fn __getitem__(&self, py: Python<'_>, index: isize) -> PyResult<PyObject> {
+        let len = self.list.0.len() as isize;
+        let index = if index < 0 { index + len } else { index };
+
+        if index < 0 {
+            return Err(PyIndexError::new_err("list index out of range"));
+        }
+
+        self.get_index(py, index as usize)
+    }
+
+    fn __iter__(slf: Bound<'_, Self>) -> ListIterator {
+        ListIterator {
+            list: slf.unbind(),
+            index: 0,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("List(<{} items>)", self.list.0.len())
+    }
+
+    /// Evaluates everything inside of the list, converting it to a list
+    fn to_python(slf: Bound<'_, Self>) -> PyResult<PyObject> {
+        to_python_deep(slf.py(), slf.into_any().unbind())
+    }
+}
+
+#[pyclass(unsendable, module = "pynix")]
+pub struct ListIterator {
+    list: Py<List>,
+    index: usize,
+}
+
+#[pymethods]
+impl ListIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let list = self.list.borrow(py);
+
+        if self.index >= list.list.0.len() {
+            return Ok(None);
+        }
+
+        let item = list.get_index(py, self.index)?;
+        self.index += 1;
+
+        Ok(Some(item))
+    }
+}
+
+/// Nix function or builtin, called with a single argument that is converted
+/// to Nix
+#[pyclass(unsendable, module = "pynix")]
+pub struct Function {
+    evaluator: Py<Evaluator>,
+    backtrace: NixBacktrace,
+    lambda: NixLambda,
+}
+
+#[pymethods]
+impl Function {
+    fn __call__(&self, py: Python<'_>, argument: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let argument = from_python(argument)?;
+
+        self.evaluator.borrow(py).resume();
+
+        let var = self
+            .lambda
+            .call(&self.backtrace, argument)
+            .map_err(to_py_err)?;
+
+        Value::new(self.evaluator.clone_ref(py), self.backtrace.clone(), var).into_python(py)
+    }
+
+    fn __repr__(&self) -> String {
+        match self.lambda {
+            NixLambda::Apply(..) => "Function(<lambda>)".to_owned(),
+            NixLambda::Builtin(ref builtin) => format!("Function(<{}>)", builtin.get_name()),
+        }
+    }
+}
+
+#[pymodule]
+fn pynix(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Evaluator>()?;
+    m.add_class::<AttrSet>()?;
+    m.add_class::<List>()?;
+    m.add_class::<Function>()?;
+    m.add("NixError", m.py().get_type_bound::<NixError>())?;
+
+    Ok(())
+}
@@ -0,0 +1,148 @@
+"""
+Tests of the Python bindings, `cargo test` runs them with the module that it
+builds (see `unittest.rs`), or run them after installing the module:
+
+    maturin develop && python -m unittest discover tests
+"""
+
+import pathlib
+import tempfile
+import unittest
+
+import pynix
+
+
+class TestPrimitives(unittest.TestCase):
+    def setUp(self):
+        self.evaluator = pynix.Evaluator()
+
+    def eval(self, expr):
+        return self.evaluator.eval_string(expr, base_dir="/base")
+
+    def test_primitives(self):
+        self.assertIsNone(self.eval("null"))
+        self.assertIs(self.eval("1 < 2"), True)
+        self.assertEqual(self.eval("40 + 2"), 42)
+        self.assertEqual(self.eval("1.5"), 1.5)
+        self.assertEqual(self.eval('"hello ${toString 42}"'), "hello 42")
+        self.assertEqual(self.eval("./file.nix"), pathlib.Path("/base/file.nix"))
+
+
+class TestProxies(unittest.TestCase):
+    def setUp(self):
+        self.evaluator = pynix.Evaluator()
+        self.set = self.evaluator.eval_string(
+            '{ b = [ 1 "x" { c = null; } ]; a = 1; broken = throw "lazy"; add = a: b: a + b; }'
+        )
+
+    def test_attr_set(self):
+        self.assertIsInstance(self.set, pynix.AttrSet)
+        self.assertEqual(len(self.set), 4)
+        self.assertEqual(self.set.keys(), ["a", "add", "b", "broken"])
+        self.assertEqual(list(self.set), ["a", "add", "b", "broken"])
+        self.assertIn("a", self.set)
+        self.assertNotIn("c", self.set)
+        self.assertEqual(self.set["a"], 1)
+        self.assertEqual(self.set.a, 1)
+        self.assertEqual(self.set.get("c", 2), 2)
+
+        with self.assertRaises(KeyError):
+            self.set["c"]
+
+        with self.assertRaises(AttributeError):
+            self.set.c
+
+    def test_list(self):
+        items = self.set.b
+
+        self.assertIsInstance(items, pynix.List)
+        self.assertEqual(len(items), 3)
+        self.assertEqual(items[0], 1)
+        self.assertEqual(items[-2], "x")
+        self.assertIsNone(items[2].c)
+        self.assertEqual(list(items)[:2], [1, "x"])
+
+        with self.assertRaises(IndexError):
+            items[3]
+
+    def test_to_python(self):
+        self.assertEqual(self.set.b.to_python(), [1, "x", {"c": None}])
+
+    def test_lazy(self):
+        with self.assertRaises(pynix.NixError) as context:
+            self.set.broken
+
+        self.assertIn("Throwing: lazy", str(context.exception))
+
+    def test_function(self):
+        add = self.set.add
+
+        self.assertIsInstance(add, pynix.Function)
+        self.assertEqual(add(1)(2), 3)
+
+        attr_names = self.evaluator.eval_string("builtins.attrNames")
+        self.assertEqual(attr_names({"b": 1, "a": [None]}).to_python(), ["a", "b"])
+        self.assertEqual(attr_names(self.set).to_python(), self.set.keys())
+
+        with self.assertRaises(TypeError):
+            add(object())
+
+
+class TestErrors(unittest.TestCase):
+    def test_error(self):
+        evaluator = pynix.Evaluator()
+
+        with self.assertRaises(pynix.NixError) as context:
+            evaluator.eval_string("let\n  x = { };\nin\n  x.missing")
+
+        self.assertIn("Attribute", str(context.exception))
+        self.assertIn("x.missing", str(context.exception))
+
+    def test_syntax_error(self):
+        with self.assertRaises(pynix.NixError):
+            pynix.Evaluator().eval_string("{ a = 1 }")
+
+    def test_stack_overflow(self):
+        with self.assertRaises(pynix.NixError) as context:
+            pynix.Evaluator().eval_string("let f = x: f x; in f 1")
+
+        self.assertIn("stack overflow", str(context.exception))
+
+
+class TestLimits(unittest.TestCase):
+    def test_limits(self):
+        # The values accessed later count for the limits of their evaluation
+        evaluator = pynix.Evaluator(max_thunks=10)
+        items = evaluator.eval_string("builtins.genList (x: x + 1) 20")
+
+        with self.assertRaises(pynix.NixError) as context:
+            for index in range(20):
+                items[index]
+
+        self.assertIn("Too many values forced", str(context.exception))
+
+        # A new evaluation restarts them
+        self.assertEqual(evaluator.eval_string("1 + 1"), 2)
+
+
+class TestFiles(unittest.TestCase):
+    def test_files(self):
+        with tempfile.TemporaryDirectory() as directory:
+            directory = pathlib.Path(directory)
+            (directory / "main.nix").write_text("{ value = import ./other.nix; }\n")
+            (directory / "other.nix").write_text('[ "from" "file" ]\n')
+
+            value = pynix.Evaluator().eval_file(directory / "main.nix")
+            self.assertEqual(value.value[1], "file")
+
+            restricted = pynix.Evaluator(restrict_eval=True)
+
+            with self.assertRaises(pynix.NixError):
+                restricted.eval_file(directory / "main.nix")
+
+            allowed = pynix.Evaluator(restrict_eval=True, allowed_paths=[directory])
+            self.assertEqual(allowed.eval_file(directory / "main.nix").value[0], "from")
+
+
+if __name__ == "__main__":
+    unittest.main()
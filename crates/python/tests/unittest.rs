@@ -0,0 +1,41 @@
+//! Runs the Python tests of `tests/test_pynix.py` with the module built by
+//! cargo, `python3` (or `$PYTHON`) should be the Python used to build it.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn unittest() {
+    // Built next to the test binary, in `target/<profile>/deps`, because the
+    // crate is also an `rlib`
+    let deps_dir = env::current_exe().unwrap().parent().unwrap().to_path_buf();
+
+    let module_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("pynix");
+    fs::create_dir_all(&module_dir).unwrap();
+    fs::copy(
+        deps_dir.join(format!(
+            "{}pynix{}",
+            env::consts::DLL_PREFIX,
+            env::consts::DLL_SUFFIX
+        )),
+        module_dir.join("pynix.so"),
+    )
+    .unwrap();
+
+    let python = env::var("PYTHON").unwrap_or_else(|_| "python3".to_owned());
+
+    let output = Command::new(python)
+        .args(["-m", "unittest", "discover", "tests"])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("PYTHONPATH", &module_dir)
+        .output()
+        .unwrap();
+
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
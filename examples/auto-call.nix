@@ -0,0 +1,11 @@
+# Automatic call
+# 
+# Test:
+#   - A file that is a function with a pattern is called
+#   - Default values of the arguments
+# 
+# The output must be:
+#@@@
+# "Hello World!"
+{ greeting ? "Hello", name ? "World", ... }:
+"${greeting} ${name}!"
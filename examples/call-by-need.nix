@@ -0,0 +1,16 @@
+# Call by need
+# 
+# Test:
+#   - Arguments that the function doesn't use aren't evaluated
+#   - Arguments are evaluated when the function uses them
+# 
+# The output must be:
+#@@@
+# { curried = 2; unused = "Hello World!"; used = 2; }
+let
+  const = value: _: value;
+in {
+  unused = const "Hello World!" (throw "unused");
+  used = (x: x + 1) 1;
+  curried = (_: y: y) (throw "unused") 2;
+}
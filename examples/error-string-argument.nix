@@ -0,0 +1 @@
+builtins.match { } "x"
@@ -0,0 +1 @@
+toString { a = 1; }
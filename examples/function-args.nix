@@ -0,0 +1,20 @@
+# builtins.functionArgs
+# 
+# Test:
+#   - The arguments of a function that takes a set
+#   - Functions without a set and builtins, even partially applied, have none
+# 
+# The output must be:
+#@@@
+# {
+#   builtin = { };
+#   ident = { };
+#   partial = { };
+#   pattern = { a = false; b = true; };
+# }
+{
+  builtin = builtins.functionArgs builtins.substring;
+  ident = builtins.functionArgs (x: x);
+  partial = builtins.functionArgs (builtins.substring 1);
+  pattern = builtins.functionArgs ({ a, b ? 1, ... }: a);
+}
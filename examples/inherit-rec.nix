@@ -0,0 +1,19 @@
+# Inherit in recursive scopes
+# 
+# Test:
+#   - `inherit x;` in a `let` is the `x` of the outer scope
+#   - `inherit x;` in a `rec` set is the `x` of the outer scope
+# 
+# The output must be:
+#@@@
+# { x = 1; y = 2; }
+let
+  x = 1;
+in
+let
+  inherit x;
+in
+rec {
+  inherit x;
+  y = x + 1;
+}
@@ -0,0 +1,35 @@
+# JSON
+# 
+# Test:
+#   - toJSON of every type, derivations and __toString
+#   - fromJSON with escapes, floats and duplicated keys
+#   - Round trip
+# 
+# The output must be:
+#@@@
+# {
+#   toJSON = "{\"derivation\":\"/nix/store/hello\",\"float\":[1.0,0.1,1e+20,1e-05],\"list\":[1,true,null],\"string\":\"a\\\"b\\n\",\"toString\":\"Hello World!\"}";
+#   fromJSON = { duplicated = 2; float = 0.5; list = [ 1 true null ]; unicode = "é😀"; };
+#   roundTrip = true;
+# }
+let
+  value = {
+    derivation = { type = "derivation"; outPath = "/nix/store/hello"; };
+    float = [ 1.0 0.1 1.0e20 0.00001 ];
+    list = [ 1 true null ];
+    string = "a\"b\n";
+    toString = { __toString = self: "Hello ${self.name}!"; name = "World"; };
+  };
+in {
+  toJSON = builtins.toJSON value;
+  fromJSON = builtins.fromJSON ''
+    {
+      "duplicated": 1,
+      "float": 5e-1,
+      "list": [1, true, null],
+      "unicode": "é😀",
+      "duplicated": 2
+    }
+  '';
+  roundTrip = builtins.fromJSON (builtins.toJSON { a = [ 1 "b" ]; }) == { a = [ 1 "b" ]; };
+}
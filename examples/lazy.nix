@@ -0,0 +1,12 @@
+# Lazy arguments of builtins
+# 
+# Test:
+#   - Builtins with lazy parameters
+# 
+# The output must be:
+#@@@
+# { seq = "Hello World!"; tryEval = { success = false; value = false; }; }
+{
+  tryEval = builtins.tryEval (throw "error");
+  seq = builtins.seq 1 "Hello World!";
+}
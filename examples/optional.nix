@@ -0,0 +1,13 @@
+# Optional arguments of builtins
+# 
+# Test:
+#   - `null` is replaced by the default value of the argument, like the
+#     empty string of `builtins.toString null`
+# 
+# The output must be:
+#@@@
+# { null = ""; string = "a"; }
+{
+  null = builtins.toString null;
+  string = builtins.toString "a";
+}
@@ -0,0 +1,12 @@
+# Cycles through the printed value
+# 
+# Test:
+#   - «repeated» for a value that contains itself
+# 
+# The output must be:
+#@@@
+# { a = «repeated»; b = [ «repeated» ]; }
+let
+  x = { a = x; b = [ x ]; };
+in
+x
@@ -0,0 +1,39 @@
+# Printing
+# 
+# Test:
+#   - Escaping of strings and quoting of attribute names
+#   - Negative numbers in lists and floats
+#   - «repeated» for shared values and rec cycles
+#   - Lambdas, primops and derivations
+# 
+# The output must be:
+#@@@
+# {
+#   drv = «derivation /nix/store/x.drv»;
+#   empty = [ { } [ ] ];
+#   lambda = «lambda @ <dir>/examples/print.nix:33:12»;
+#   names = { "" = 6; "1x" = 2; "a b" = 1; a-b' = 5; "if" = 4; or = 3; };
+#   numbers = [ 1 (-1) 1.0 0.1 1.0e20 (-2.5) 1.5e-7 ];
+#   partial = «partially applied primop map»;
+#   paths = [ /a/b /a/b+c ];
+#   primop = «primop map»;
+#   repeated = [ { x = 1; } «repeated» ];
+#   self = { a = { b = «repeated»; }; };
+#   strings = [ "q\"\\" "a\nb\tc" "\${x}" "$y" "\${z}" ];
+# }
+let
+  shared = { x = 1; };
+  self = rec { a = { b = a; }; };
+in {
+  strings = [ "q\"\\" "a\nb\tc" "\${x}" "$y" "${"$"}{z}" ];
+  names = { "a b" = 1; "1x" = 2; or = 3; "if" = 4; a-b' = 5; "" = 6; };
+  numbers = [ 1 (-1) 1.0 0.1 1.0e20 (-2.5) 1.5e-7 ];
+  repeated = [ shared shared ];
+  inherit self;
+  lambda = x: x;
+  primop = builtins.map;
+  partial = builtins.map (x: x);
+  drv = { type = "derivation"; drvPath = "/nix/store/x.drv"; };
+  paths = [ /a/b /a/b+c ];
+  empty = [ { } [ ] ];
+}
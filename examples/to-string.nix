@@ -0,0 +1,29 @@
+# builtins.toString
+# 
+# Test:
+#   - Strings, paths, numbers, booleans and null
+#   - Lists, with the values separated by spaces
+#   - Sets with `__toString` or `outPath`, like derivations
+# 
+# The output must be:
+#@@@
+# {
+#   bool = [ "1" "" ];
+#   drv = "/nix/store/hello";
+#   int = "42";
+#   list = "1 a  2 3";
+#   null = "";
+#   path = "/tmp/a";
+#   string = "a";
+#   toString = "Hello World!";
+# }
+{
+  bool = [ (toString true) (toString false) ];
+  drv = toString { type = "derivation"; outPath = "/nix/store/hello"; };
+  int = toString 42;
+  list = toString [ 1 "a" null [ 2 3 ] ];
+  null = toString null;
+  path = toString /tmp/a;
+  string = toString "a";
+  toString = toString { __toString = self: "Hello ${self.name}!"; name = "World"; };
+}
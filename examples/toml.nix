@@ -0,0 +1,34 @@
+# TOML
+# 
+# Test:
+#   - Tables, inline tables, dotted keys and arrays of tables
+#   - Every type of value
+# 
+# The output must be:
+#@@@
+# {
+#   package = { authors = [ "a" "b" ]; metadata = { docs = true; }; name = "hello"; };
+#   bin = [ { name = "a"; } { name = "b"; } ];
+#   values = { float = 0.5; hex = 255; literal = "C:\\path"; multiline = "first\nsecond\n"; };
+# }
+builtins.fromTOML ''
+  [package]
+  name = "hello"
+  authors = ["a", "b"]
+  metadata.docs = true
+
+  [[bin]]
+  name = "a"
+
+  [[bin]]
+  name = "b"
+
+  [values]
+  float = 5e-1
+  hex = 0xff
+  literal = 'C:\path'
+  multiline = """
+  first
+  second
+  """
+''
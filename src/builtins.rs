@@ -1,128 +1,148 @@
+mod convert;
 mod hash;
+mod host;
 mod r#impl;
+mod json;
+mod toml;
+mod xml;
 
 use std::fmt::{self, Write};
-use std::path::PathBuf;
-
-use crate::value::{NixLambda, NixList};
-use crate::{NixBacktrace, NixResult, NixValue, NixValueWrapped, NixVar};
 
+use crate::{
+    NixBacktrace, NixError, NixLabelKind, NixLabelMessage, NixResult, NixSpan, NixValue, NixVar,
+};
+
+#[doc(hidden)]
+pub use convert::{
+    from_builtin_argument, from_builtin_optional, from_nix_attr, from_nix_variant, unknown_variant,
+};
+pub use convert::{type_error, FromNixExpr, IntoNix, NixLazy};
+pub use host::{NixHostArguments, NixHostBuiltin, NixHostFn};
+pub use json::{from_json, to_json};
 pub use r#impl::{get_builtins, Abort, BaseNameOf, Import, Map, RemoveAttrs, Throw, ToString};
-
-pub trait FromNixExpr: Sized {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self>;
+pub use toml::from_toml;
+pub use xml::to_xml;
+
+/// Every `#[builtin]`, they're collected at link time so none of them can be
+/// missing from [`get_builtins`]
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static BUILTINS: [(&'static str, fn() -> NixValue)];
+
+/// Error of the parsers of builtins like `fromJSON`, `offset` is the byte
+/// of the input where it was found
+#[derive(Clone, Debug)]
+pub struct ParseError {
+    pub offset: usize,
+    pub message: String,
 }
 
-impl FromNixExpr for NixValueWrapped {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        var.resolve(backtrace)
-    }
-}
+impl ParseError {
+    /// Line and column of the error in `input`, starting at 1
+    pub fn line_column(&self, input: &str) -> (usize, usize) {
+        let before = &input[..self.offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |idx| idx + 1) + 1;
 
-impl FromNixExpr for NixVar {
-    fn from_nix_expr(_: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        Ok(var)
+        (line, column)
     }
-}
 
-impl FromNixExpr for (NixBacktrace, NixVar) {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        Ok((backtrace.clone(), var))
+    /// Error of parsing `input` as `format`, like "JSON". It points into the
+    /// string if it's a literal without escapes.
+    pub fn to_error(&self, backtrace: &NixBacktrace, input: &str, format: &str) -> NixError {
+        let (line, column) = self.line_column(input);
+
+        let backtrace = match NixSpan::in_literal(&backtrace.0, input, self.offset) {
+            Some(span) => backtrace.change_span(span),
+            None => backtrace.clone(),
+        };
+
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Custom(self.message.clone()),
+            format!(
+                "Invalid {format} at line {line}, column {column}: {}",
+                self.message
+            ),
+        )
     }
 }
 
-macro_rules! int_from_nix_expr {
-    ($($ty:ident),+) => { $(
-        #[allow(unused_imports)]
-        use std::primitive::$ty;
-
-        impl FromNixExpr for $ty {
-            fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-                match *var.resolve(backtrace)?.borrow() {
-                    NixValue::Int(i) => Ok(i as $ty),
-                    _ => todo!(concat!("Error handling: ", stringify!($ty)," cast"))
-                }
-            }
-        }
-    )+ };
+/// Argument of a builtin, generated by `#[builtin]` from its parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NixBuiltinArgument {
+    pub name: &'static str,
+    /// Expected type, like "a list", see [`FromNixExpr::TYPE`]
+    pub ty: &'static str,
 }
 
-int_from_nix_expr! {isize, i64, i32, i16, i8}
-int_from_nix_expr! {usize, u64, u32, u16, u8}
+/// Metadata of a `#[builtin]`, generated from its function
+pub trait NixBuiltinInfo {
+    const NAME: &str;
+
+    /// Arguments in order, without the backtrace
+    const ARGUMENTS: &[NixBuiltinArgument];
 
-impl FromNixExpr for NixLambda {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        var.resolve(backtrace)?
-            .borrow()
-            .as_lambda()
-            .cloned()
-            .ok_or_else(|| todo!("Error handling: Lambda cast"))
-    }
+    const ARITY: usize = Self::ARGUMENTS.len();
+
+    /// Doc comments of the function
+    const DOC: &str;
 }
 
-impl FromNixExpr for NixList {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        var.resolve(backtrace)?
-            .borrow()
-            .as_list()
-            .ok_or_else(|| todo!("Error handling: List cast"))
+pub trait NixBuiltin {
+    fn get_name(&self) -> &str;
+
+    /// Arguments in order, empty if they are unknown
+    fn arguments(&self) -> &[NixBuiltinArgument] {
+        &[]
     }
-}
 
-impl FromNixExpr for PathBuf {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        var.resolve(backtrace)?
-            .borrow()
-            .as_path()
-            .ok_or_else(|| todo!("Error handling: Path cast"))
+    fn arity(&self) -> usize {
+        self.arguments().len()
     }
-}
 
-impl FromNixExpr for String {
-    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
-        var.resolve(backtrace)?
-            .borrow()
-            .cast_to_string()
-            .ok_or_else(|| todo!("Error handling: String cast"))
+    /// Amount of arguments that were already applied
+    fn applied(&self) -> usize {
+        0
     }
-}
 
-// TODO:
-// impl FromNixExpr for NixAttrSet {
-//     fn from_nix_expr(
-//             backtrace: Rc<NixBacktrace>,
-//             scope: Rc<Scope>,
-//             var: NixVar,
-//         ) -> NixResult<Self> {
-//         let value = scope.visit_expr(backtrace, expr.clone())?;
-//         let Some(value) = value.borrow().as_attr_set() else {
-//             todo!("Error handling");
-//         };
-//     }
-// }
+    fn doc(&self) -> &str {
+        ""
+    }
 
-pub trait NixBuiltinInfo {
-    const NAME: &str;
+    fn run(&self, backtrace: &NixBacktrace, argument: NixVar) -> NixResult;
 }
 
-pub trait NixBuiltin {
-    fn get_name(&self) -> &'static str;
+impl dyn NixBuiltin {
+    /// Name followed by the names of the arguments, like `map callback list`
+    pub fn signature(&self) -> String {
+        let mut signature = self.get_name().to_owned();
 
-    fn run(&self, backtrace: &NixBacktrace, argument: NixVar) -> NixResult;
+        for argument in self.arguments() {
+            signature.push(' ');
+            signature.push_str(argument.name);
+        }
+
+        signature
+    }
 }
 
 impl fmt::Debug for dyn NixBuiltin {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_char('<')?;
-        f.write_str(self.get_name())?;
-        f.write_char('>')
+        fmt::Display::fmt(self, f)
     }
 }
 
+/// `<map>`, or `<map 1/2>` if it's partially applied
 impl fmt::Display for dyn NixBuiltin {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_char('<')?;
         f.write_str(self.get_name())?;
+
+        if self.applied() > 0 {
+            write!(f, " {}/{}", self.applied(), self.arity())?;
+        }
+
         f.write_char('>')
     }
 }
@@ -0,0 +1,515 @@
+//! Conversions between Rust types and Nix values, used by the parameters of
+//! builtins and by `#[derive(FromNix, IntoNix)]`.
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::value::{NixLambda, NixList};
+use crate::{
+    NixAttrSet, NixBacktrace, NixError, NixLabelKind, NixLabelMessage, NixResult, NixValue,
+    NixValueWrapped, NixVar,
+};
+
+pub trait FromNixExpr: Sized {
+    /// Description of the expected type, like "a list"
+    const TYPE: &'static str = "a value";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self>;
+
+    /// Value of a missing attribute in `#[derive(FromNix)]`, it's required if
+    /// there isn't one
+    fn from_missing() -> Option<Self> {
+        None
+    }
+}
+
+/// Error for a value that isn't of the `expected` type, like "a string"
+pub fn type_error(backtrace: &NixBacktrace, expected: &'static str, value: &NixValue) -> NixError {
+    backtrace.to_error(
+        NixLabelKind::Error,
+        NixLabelMessage::ExpectedType(expected),
+        format!("expected {expected} but got {}", value.as_type()),
+    )
+}
+
+/// Backtrace pointing to the definition of `var`, or to `backtrace` if it was
+/// already resolved
+fn var_backtrace(backtrace: &NixBacktrace, var: &NixVar) -> NixBacktrace {
+    var.backtrace().unwrap_or_else(|| backtrace.clone())
+}
+
+impl FromNixExpr for NixValueWrapped {
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        var.resolve(backtrace)
+    }
+}
+
+impl FromNixExpr for NixVar {
+    fn from_nix_expr(_: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        Ok(var)
+    }
+}
+
+impl FromNixExpr for (NixBacktrace, NixVar) {
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        Ok((backtrace.clone(), var))
+    }
+}
+
+macro_rules! int_from_nix_expr {
+    ($($ty:ident),+) => { $(
+        #[allow(unused_imports)]
+        use std::primitive::$ty;
+
+        impl FromNixExpr for $ty {
+            const TYPE: &'static str = "an integer";
+
+            fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+                const EXPECTED: &str = concat!("an integer that fits in ", stringify!($ty));
+
+                match *var.resolve(backtrace)?.borrow() {
+                    NixValue::Int(int) => $ty::try_from(int).map_err(|_| {
+                        backtrace.to_error(
+                            NixLabelKind::Error,
+                            NixLabelMessage::ExpectedType(EXPECTED),
+                            format!("expected {EXPECTED} but got {int}"),
+                        )
+                    }),
+                    ref value => Err(type_error(backtrace, Self::TYPE, value)),
+                }
+            }
+        }
+    )+ };
+}
+
+int_from_nix_expr! {isize, i64, i32, i16, i8}
+int_from_nix_expr! {usize, u64, u32, u16, u8}
+
+impl FromNixExpr for bool {
+    const TYPE: &'static str = "a bool";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        let value = var.resolve(backtrace)?;
+        let value = value.borrow();
+
+        value
+            .as_bool()
+            .ok_or_else(|| type_error(backtrace, Self::TYPE, &value))
+    }
+}
+
+impl FromNixExpr for f64 {
+    const TYPE: &'static str = "a float";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        match *var.resolve(backtrace)?.borrow() {
+            NixValue::Float(float) => Ok(float),
+            NixValue::Int(int) => Ok(int as f64),
+            ref value => Err(type_error(backtrace, Self::TYPE, value)),
+        }
+    }
+}
+
+impl FromNixExpr for NixLambda {
+    const TYPE: &'static str = "a function";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        let value = var.resolve(backtrace)?;
+        let value = value.borrow();
+
+        value
+            .as_lambda()
+            .cloned()
+            .ok_or_else(|| type_error(backtrace, Self::TYPE, &value))
+    }
+}
+
+impl FromNixExpr for NixList {
+    const TYPE: &'static str = "a list";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        let value = var.resolve(backtrace)?;
+        let value = value.borrow();
+
+        value
+            .as_list()
+            .ok_or_else(|| type_error(backtrace, Self::TYPE, &value))
+    }
+}
+
+impl FromNixExpr for PathBuf {
+    const TYPE: &'static str = "a path";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        let value = var.resolve(backtrace)?;
+        let value = value.borrow();
+
+        value
+            .as_path()
+            .ok_or_else(|| type_error(backtrace, Self::TYPE, &value))
+    }
+}
+
+impl FromNixExpr for String {
+    const TYPE: &'static str = "a string";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        match *var.resolve(backtrace)?.borrow() {
+            NixValue::String(ref string) => Ok(string.clone()),
+            ref value => Err(type_error(backtrace, Self::TYPE, value)),
+        }
+    }
+}
+
+/// `null` is `None`, and so is a missing attribute
+impl<T: FromNixExpr> FromNixExpr for Option<T> {
+    const TYPE: &'static str = T::TYPE;
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        if var.resolve(backtrace)?.borrow().is_null() {
+            return Ok(None);
+        }
+
+        T::from_nix_expr(backtrace, var).map(Some)
+    }
+
+    fn from_missing() -> Option<Self> {
+        Some(None)
+    }
+}
+
+impl<T: FromNixExpr> FromNixExpr for Vec<T> {
+    const TYPE: &'static str = NixList::TYPE;
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        NixList::from_nix_expr(backtrace, var)?
+            .0
+            .iter()
+            .map(|item| T::from_nix_expr(&var_backtrace(backtrace, item), item.clone()))
+            .collect()
+    }
+}
+
+/// Also implements [`NixAttrSet`], which is a `BTreeMap<String, NixVar>`
+impl<T: FromNixExpr> FromNixExpr for BTreeMap<String, T> {
+    const TYPE: &'static str = "a set";
+
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        let set = {
+            let value = var.resolve(backtrace)?;
+            let value = value.borrow();
+
+            value
+                .as_attr_set()
+                .cloned()
+                .ok_or_else(|| type_error(backtrace, Self::TYPE, &value))?
+        };
+
+        set.into_iter()
+            .map(|(name, var)| {
+                let value = T::from_nix_expr(&var_backtrace(backtrace, &var), var)?;
+
+                Ok((name, value))
+            })
+            .collect()
+    }
+}
+
+/// Argument at `position` of `builtins.<builtin>`, starting at 1, used by
+/// `#[builtin]`. Type errors say which argument and builtin it was.
+#[doc(hidden)]
+pub fn from_builtin_argument<T: FromNixExpr>(
+    backtrace: &NixBacktrace,
+    var: NixVar,
+    builtin: &'static str,
+    position: usize,
+) -> NixResult<T> {
+    T::from_nix_expr(backtrace, var).map_err(|mut error| {
+        // Errors of other builtins, that are called while evaluating the
+        // argument, were already renamed
+        let label = error
+            .labels
+            .iter_mut()
+            .find(|label| matches!(label.label, NixLabelMessage::ExpectedType(_)));
+
+        if let Some(label) = label {
+            let NixLabelMessage::ExpectedType(expected) = label.label else {
+                unreachable!()
+            };
+
+            let position = ordinal(position);
+
+            label.label = NixLabelMessage::ExpectedArgument(expected, position, builtin);
+            error.message = format!(
+                "{position} argument of builtins.{builtin}: {}",
+                error.message
+            );
+        }
+
+        error
+    })
+}
+
+/// Like [`from_builtin_argument`], `null` is replaced by `default`. Used by
+/// `#[optional]` parameters.
+#[doc(hidden)]
+pub fn from_builtin_optional<T: FromNixExpr>(
+    backtrace: &NixBacktrace,
+    var: NixVar,
+    builtin: &'static str,
+    position: usize,
+    default: impl FnOnce() -> T,
+) -> NixResult<T> {
+    if var.resolve(backtrace)?.borrow().is_null() {
+        return Ok(default());
+    }
+
+    from_builtin_argument(backtrace, var, builtin, position)
+}
+
+/// Argument of a builtin that isn't evaluated until it's forced, received by
+/// `#[lazy]` parameters
+pub struct NixLazy<T> {
+    backtrace: NixBacktrace,
+    var: NixVar,
+    builtin: &'static str,
+    position: usize,
+    ty: PhantomData<T>,
+}
+
+impl<T: FromNixExpr> NixLazy<T> {
+    #[doc(hidden)]
+    pub fn new(
+        backtrace: &NixBacktrace,
+        var: NixVar,
+        builtin: &'static str,
+        position: usize,
+    ) -> Self {
+        Self {
+            backtrace: backtrace.clone(),
+            var,
+            builtin,
+            position,
+            ty: PhantomData,
+        }
+    }
+
+    /// Evaluates and converts the argument, errors are reported like the ones
+    /// of the other arguments
+    pub fn force(&self) -> NixResult<T> {
+        from_builtin_argument(
+            &self.backtrace,
+            self.var.clone(),
+            self.builtin,
+            self.position,
+        )
+    }
+
+    /// Backtrace of the application of the argument
+    pub fn backtrace(&self) -> &NixBacktrace {
+        &self.backtrace
+    }
+
+    /// The argument without evaluating it
+    pub fn var(&self) -> &NixVar {
+        &self.var
+    }
+}
+
+fn ordinal(position: usize) -> &'static str {
+    match position {
+        1 => "first",
+        2 => "second",
+        3 => "third",
+        4 => "fourth",
+        5 => "fifth",
+        _ => "next",
+    }
+}
+
+/// Attribute `name` of `set`, used by `#[derive(FromNix)]`.
+///
+/// Errors point to the definition of the attribute, if it's missing `default`
+/// or [`FromNixExpr::from_missing`] are used.
+#[doc(hidden)]
+pub fn from_nix_attr<T: FromNixExpr>(
+    backtrace: &NixBacktrace,
+    set: &NixAttrSet,
+    name: &str,
+    default: Option<fn() -> T>,
+) -> NixResult<T> {
+    if let Some(var) = set.get(name) {
+        return T::from_nix_expr(&var_backtrace(backtrace, var), var.clone());
+    }
+
+    default
+        .map(|default| default())
+        .or_else(T::from_missing)
+        .ok_or_else(|| {
+            backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::AttributeMissing,
+                format!("Attribute '\x1b[1;95m{name}\x1b[0m' missing"),
+            )
+        })
+}
+
+/// Variant of an enum, used by `#[derive(FromNix)]`.
+///
+/// Variants without fields are strings, and variants with fields are sets
+/// with a single attribute, like `{ variant = <fields>; }`.
+#[doc(hidden)]
+pub fn from_nix_variant(
+    backtrace: &NixBacktrace,
+    var: NixVar,
+) -> NixResult<(String, Option<(NixBacktrace, NixVar)>)> {
+    let value = var.resolve(backtrace)?;
+    let value = value.borrow();
+
+    match *value {
+        NixValue::String(ref variant) => Ok((variant.clone(), None)),
+        NixValue::AttrSet(ref set) if set.len() == 1 => {
+            let (variant, var) = set.iter().next().unwrap();
+
+            Ok((
+                variant.clone(),
+                Some((var_backtrace(backtrace, var), var.clone())),
+            ))
+        }
+        ref value => Err(type_error(
+            backtrace,
+            "a string or a set with a single attribute",
+            value,
+        )),
+    }
+}
+
+#[doc(hidden)]
+pub fn unknown_variant(backtrace: &NixBacktrace, variant: &str, variants: &[&str]) -> NixError {
+    let variants = variants
+        .iter()
+        .map(|variant| format!("'{variant}'"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    backtrace.to_error(
+        NixLabelKind::Error,
+        NixLabelMessage::Custom(format!("Expected one of {variants}")),
+        format!("Unknown variant '\x1b[1;95m{variant}\x1b[0m'"),
+    )
+}
+
+/// Conversion of Rust values to Nix, the opposite of [`FromNixExpr`]
+pub trait IntoNix {
+    fn into_nix(self) -> NixValue;
+}
+
+impl IntoNix for NixValue {
+    fn into_nix(self) -> NixValue {
+        self
+    }
+}
+
+impl IntoNix for bool {
+    fn into_nix(self) -> NixValue {
+        NixValue::Bool(self)
+    }
+}
+
+macro_rules! int_into_nix {
+    ($($ty:ident),+) => { $(
+        impl IntoNix for $ty {
+            fn into_nix(self) -> NixValue {
+                NixValue::Int(self.into())
+            }
+        }
+    )+ };
+}
+
+// Only the types that always fit in an i64
+int_into_nix! {i64, i32, i16, i8, u32, u16, u8}
+
+impl IntoNix for f64 {
+    fn into_nix(self) -> NixValue {
+        NixValue::Float(self)
+    }
+}
+
+impl IntoNix for f32 {
+    fn into_nix(self) -> NixValue {
+        NixValue::Float(self.into())
+    }
+}
+
+impl IntoNix for String {
+    fn into_nix(self) -> NixValue {
+        NixValue::String(self)
+    }
+}
+
+impl IntoNix for &str {
+    fn into_nix(self) -> NixValue {
+        NixValue::String(self.to_owned())
+    }
+}
+
+impl IntoNix for PathBuf {
+    fn into_nix(self) -> NixValue {
+        NixValue::Path(self)
+    }
+}
+
+impl IntoNix for &Path {
+    fn into_nix(self) -> NixValue {
+        NixValue::Path(self.to_path_buf())
+    }
+}
+
+impl IntoNix for NixLambda {
+    fn into_nix(self) -> NixValue {
+        NixValue::Lambda(self)
+    }
+}
+
+impl IntoNix for NixList {
+    fn into_nix(self) -> NixValue {
+        NixValue::List(self)
+    }
+}
+
+impl IntoNix for NixAttrSet {
+    fn into_nix(self) -> NixValue {
+        NixValue::AttrSet(self)
+    }
+}
+
+/// `None` is `null`
+impl<T: IntoNix> IntoNix for Option<T> {
+    fn into_nix(self) -> NixValue {
+        self.map_or(NixValue::Null, T::into_nix)
+    }
+}
+
+impl<T: IntoNix> IntoNix for Vec<T> {
+    fn into_nix(self) -> NixValue {
+        let list = self
+            .into_iter()
+            .map(|item| item.into_nix().wrap_var())
+            .collect();
+
+        NixValue::List(NixList(Rc::new(list)))
+    }
+}
+
+impl<T: IntoNix> IntoNix for BTreeMap<String, T> {
+    fn into_nix(self) -> NixValue {
+        let set = self
+            .into_iter()
+            .map(|(name, value)| (name, value.into_nix().wrap_var()))
+            .collect();
+
+        NixValue::AttrSet(set)
+    }
+}
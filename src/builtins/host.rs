@@ -0,0 +1,200 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::value::NixLambda;
+use crate::{NixAttrSet, NixBacktrace, NixResult, NixValue, NixVar};
+
+use super::{FromNixExpr, NixBuiltin};
+
+/// Arguments of a host builtin, each one with the backtrace where it was applied
+pub type NixHostArguments = Vec<(NixBacktrace, NixVar)>;
+
+type NixHostFunction = Rc<dyn Fn(&NixBacktrace, NixHostArguments) -> NixResult>;
+
+/// Builtin defined at runtime by the program embedding the evaluator.
+///
+/// It's curried like any other builtin: the function runs once `arity`
+/// arguments were applied.
+#[derive(Clone)]
+pub struct NixHostBuiltin {
+    path: Rc<[String]>,
+    arity: usize,
+    function: NixHostFunction,
+    arguments: NixHostArguments,
+}
+
+impl fmt::Debug for NixHostBuiltin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NixHostBuiltin")
+            .field("name", &self.path.join("."))
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
+impl NixHostBuiltin {
+    /// Creates a builtin that receives the raw arguments.
+    ///
+    /// `name` is the attribute path inside of `builtins`, so
+    /// `ourCompany.lookupSecretPath` defines `builtins.ourCompany.lookupSecretPath`.
+    ///
+    /// # Panics
+    ///
+    /// If `arity` is zero or `name` has an empty component.
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&NixBacktrace, NixHostArguments) -> NixResult + 'static,
+    ) -> Self {
+        assert!(
+            arity > 0,
+            "Builtin '{name}' should take at least one argument"
+        );
+
+        let path = name.split('.').map(str::to_owned).collect::<Rc<[_]>>();
+
+        assert!(
+            path.iter().all(|attr| !attr.is_empty()),
+            "Invalid builtin name '{name}'"
+        );
+
+        Self {
+            path,
+            arity,
+            function: Rc::new(function),
+            arguments: Vec::new(),
+        }
+    }
+
+    /// Creates a builtin from a function with typed parameters, which are
+    /// converted with [`FromNixExpr`]
+    ///
+    /// ```ignore
+    /// NixHostBuiltin::from_fn(
+    ///     "ourCompany.lookupSecretPath",
+    ///     |_: &NixBacktrace, name: String| Ok(NixValue::Path(secrets.join(name)).wrap()),
+    /// );
+    /// ```
+    pub fn from_fn<Args>(name: &str, function: impl NixHostFn<Args> + 'static) -> Self {
+        let arity = function.arity();
+
+        Self::new(name, arity, move |backtrace, arguments| {
+            function.call(backtrace, arguments)
+        })
+    }
+
+    pub fn path(&self) -> &[String] {
+        &self.path
+    }
+
+    /// Moves the builtin inside of the `namespace` attribute set
+    pub fn in_namespace(self, namespace: &str) -> Self {
+        let path = std::iter::once(namespace.to_owned())
+            .chain(self.path.iter().cloned())
+            .collect();
+
+        Self { path, ..self }
+    }
+
+    pub fn generate(&self) -> NixValue {
+        NixValue::Lambda(NixLambda::Builtin(Rc::new(Box::new(self.clone()))))
+    }
+
+    /// Inserts the builtin in `set` following its attribute path, merging it
+    /// with the attribute sets that already exist
+    pub fn insert_into(&self, set: &mut NixAttrSet) {
+        fn insert(set: &mut NixAttrSet, path: &[String], value: NixVar) {
+            let [attr, rest @ ..] = path else {
+                unreachable!("Builtin names have at least one attribute");
+            };
+
+            if rest.is_empty() {
+                set.insert(attr.clone(), value);
+                return;
+            }
+
+            let mut child = set
+                .get(attr)
+                .and_then(NixVar::as_concrete)
+                .and_then(|child| child.borrow().as_attr_set().cloned())
+                .unwrap_or_default();
+
+            insert(&mut child, rest, value);
+
+            set.insert(attr.clone(), NixValue::AttrSet(child).wrap_var());
+        }
+
+        insert(set, &self.path, self.generate().wrap_var());
+    }
+}
+
+impl NixBuiltin for NixHostBuiltin {
+    fn get_name(&self) -> &str {
+        self.path.last().unwrap()
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn applied(&self) -> usize {
+        self.arguments.len()
+    }
+
+    fn run(&self, backtrace: &NixBacktrace, argument: NixVar) -> NixResult {
+        let mut arguments = self.arguments.clone();
+        arguments.push((backtrace.clone(), argument));
+
+        if arguments.len() < self.arity {
+            let builtin = Self {
+                arguments,
+                ..self.clone()
+            };
+
+            return Ok(builtin.generate().wrap());
+        }
+
+        (self.function)(backtrace, arguments)
+    }
+}
+
+/// Functions that can be used as a [`NixHostBuiltin`], implemented for
+/// `Fn(&NixBacktrace, A, B, ...) -> NixResult` where every argument
+/// implements [`FromNixExpr`]
+pub trait NixHostFn<Args> {
+    fn arity(&self) -> usize;
+
+    fn call(&self, backtrace: &NixBacktrace, arguments: NixHostArguments) -> NixResult;
+}
+
+macro_rules! host_fn {
+    ($($arg:ident: $ty:ident),+) => {
+        impl<Fun, $($ty),+> NixHostFn<($($ty,)+)> for Fun
+        where
+            Fun: Fn(&NixBacktrace, $($ty),+) -> NixResult,
+            $($ty: FromNixExpr),+
+        {
+            fn arity(&self) -> usize {
+                [$(stringify!($arg)),+].len()
+            }
+
+            fn call(&self, backtrace: &NixBacktrace, arguments: NixHostArguments) -> NixResult {
+                let mut arguments = arguments.into_iter();
+
+                $(
+                    let (arg_backtrace, var) = arguments.next().expect("Arity already checked");
+                    let $arg = $ty::from_nix_expr(&arg_backtrace, var)?;
+                )+
+
+                self(backtrace, $($arg),+)
+            }
+        }
+    };
+}
+
+host_fn!(a: A);
+host_fn!(a: A, b: B);
+host_fn!(a: A, b: B, c: C);
+host_fn!(a: A, b: B, c: C, d: D);
+host_fn!(a: A, b: B, c: C, d: D, e: E);
+host_fn!(a: A, b: B, c: C, d: D, e: E, f: F);
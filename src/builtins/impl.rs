@@ -4,28 +4,26 @@ use std::rc::Rc;
 
 use nix_macros::{builtin, gen_builtins};
 
-use crate::value::{NixAttrSet, NixLambda, NixList};
+use crate::value::{NixAttrSet, NixLambda, NixLambdaParam, NixList};
 use crate::{
-    LazyNixValue, NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue,
-    NixValueWrapped, NixVar, Scope,
+    LazyNixValue, NixBacktrace, NixDebugger, NixLabelKind, NixLabelMessage, NixResult, NixSettings,
+    NixValue, NixValueWrapped, NixVar, Scope,
 };
 
-use super::hash;
+use super::{hash, json, toml, type_error, xml, FromNixExpr};
 
+/// Aborts the evaluation with `message`
 #[builtin]
 pub fn abort(message: String) {
     panic!("Aborting: {message}")
 }
 
+/// Whether `callback` returns `true` for every item of `list`
 #[builtin]
 pub fn all(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     for item in list.0.iter() {
         let callback = callback.call(backtrace, item.clone())?;
-        let callback = callback
-            .resolve(backtrace)?
-            .borrow()
-            .as_bool()
-            .ok_or_else(|| todo!("Error handling"))?;
+        let callback = bool::from_nix_expr(backtrace, callback)?;
 
         if !callback {
             return Ok(NixValue::Bool(false).wrap());
@@ -35,15 +33,12 @@ pub fn all(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     Ok(NixValue::Bool(true).wrap())
 }
 
+/// Whether `callback` returns `true` for at least one item of `list`
 #[builtin]
 pub fn any(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     for item in list.0.iter() {
         let callback = callback.call(backtrace, item.clone())?;
-        let callback = callback
-            .resolve(backtrace)?
-            .borrow()
-            .as_bool()
-            .ok_or_else(|| todo!("Error handling"))?;
+        let callback = bool::from_nix_expr(backtrace, callback)?;
 
         if callback {
             return Ok(NixValue::Bool(true).wrap());
@@ -53,13 +48,9 @@ pub fn any(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     Ok(NixValue::Bool(false).wrap())
 }
 
+/// Names of the attributes of `set`, sorted alphabetically
 #[builtin]
-pub fn attr_names(set: NixValueWrapped) {
-    let set = set.borrow();
-    let Some(set) = set.as_attr_set() else {
-        todo!("Error handling");
-    };
-
+pub fn attr_names(set: NixAttrSet) {
     let names = set
         .keys()
         .cloned()
@@ -70,6 +61,7 @@ pub fn attr_names(set: NixValueWrapped) {
     Ok(NixValue::List(NixList(Rc::new(names))).wrap())
 }
 
+/// Last component of the path or string `s`
 #[builtin]
 pub fn base_name_of(s: NixValueWrapped) {
     let s = s.borrow();
@@ -98,18 +90,16 @@ pub fn base_name_of(s: NixValueWrapped) {
     Ok(NixValue::String(s.to_owned()).wrap())
 }
 
+/// Values of the attributes of `set`, sorted by their names
 #[builtin]
-pub fn attr_values(set: NixValueWrapped) {
-    let set = set.borrow();
-    let Some(set) = set.as_attr_set() else {
-        todo!("Error handling");
-    };
-
-    let values = set.values().cloned().collect::<Vec<NixVar>>();
+pub fn attr_values(set: NixAttrSet) {
+    let values = set.into_values().collect::<Vec<NixVar>>();
 
     Ok(NixValue::List(NixList(Rc::new(values))).wrap())
 }
 
+/// Compares two versions like `1.2.3`, it's -1, 0 or 1 if the first one is
+/// older, equal or newer than the second one
 #[builtin]
 pub fn compare_versions(first_arg: String, second_arg: String) {
     let first_arg = first_arg.split(".");
@@ -129,6 +119,8 @@ pub fn compare_versions(first_arg: String, second_arg: String) {
     Ok(NixValue::Int(0).wrap())
 }
 
+/// Applies `callback` to every item of `list`, and concatenates the
+/// resulting lists
 #[builtin]
 pub fn concat_map(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     let mut out = vec![];
@@ -146,6 +138,7 @@ pub fn concat_map(backtrace: &NixBacktrace, callback: NixLambda, list: NixList)
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
+/// Concatenates the strings of `list`, with `sep` between them
 #[builtin]
 pub fn concat_string_sep(backtrace: &NixBacktrace, sep: String, list: NixList) {
     let list = list
@@ -163,6 +156,7 @@ pub fn concat_string_sep(backtrace: &NixBacktrace, sep: String, list: NixList) {
     Ok(NixValue::String(list.join(&sep)).wrap())
 }
 
+/// Directory of the path or string `s`
 #[builtin]
 pub fn dir_of(s: NixValueWrapped) {
     let s = s.borrow();
@@ -179,6 +173,7 @@ pub fn dir_of(s: NixValueWrapped) {
     Ok(NixValue::String(s.to_owned()).wrap())
 }
 
+/// Whether `x` is an item of `xs`
 #[builtin]
 pub fn elem(backtrace: &NixBacktrace, x: NixValueWrapped, xs: NixList) {
     for item in xs.0.iter() {
@@ -192,6 +187,7 @@ pub fn elem(backtrace: &NixBacktrace, x: NixValueWrapped, xs: NixList) {
     Ok(NixValue::Bool(false).wrap())
 }
 
+/// Item of `xs` at the index `x`, starting at 0
 #[builtin]
 pub fn elemAt(backtrace: &NixBacktrace, xs: NixList, x: usize) {
     xs.0.get(x)
@@ -199,6 +195,7 @@ pub fn elemAt(backtrace: &NixBacktrace, xs: NixList, x: usize) {
         .resolve(backtrace)
 }
 
+/// Items of `list` for which `callback` returns `true`
 #[builtin]
 pub fn filter(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     let mut out = Vec::with_capacity(list.0.len());
@@ -220,6 +217,26 @@ pub fn filter(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
+/// Parses the JSON string `argument`, duplicated keys keep the last value
+#[builtin("fromJSON")]
+pub fn from_json(backtrace: &NixBacktrace, argument: String) {
+    json::from_json(&argument)
+        .map(NixValue::wrap)
+        .map_err(|error| error.to_error(backtrace, &argument, "JSON"))
+}
+
+/// Parses the TOML string `argument`, dates and times are only supported
+/// with [`NixSettings::parse_toml_timestamps`]
+#[builtin("fromTOML")]
+pub fn from_toml(backtrace: &NixBacktrace, argument: String) {
+    let timestamps = NixSettings::current().parse_toml_timestamps;
+
+    toml::from_toml(&argument, timestamps)
+        .map(NixValue::wrap)
+        .map_err(|error| error.to_error(backtrace, &argument, "TOML"))
+}
+
+/// List of `size` items, where each one is `callback` applied to its index
 #[builtin]
 pub fn gen_list(backtrace: &NixBacktrace, callback: NixLambda, size: i64) {
     let out = (0..size)
@@ -236,6 +253,7 @@ pub fn gen_list(backtrace: &NixBacktrace, callback: NixLambda, size: i64) {
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
+/// Value of the environment variable `env`, or an empty string
 #[builtin()]
 pub fn get_env(env: String) {
     let value = std::env::var(env).unwrap_or_default();
@@ -255,11 +273,13 @@ fn intern_hash(ty: &str, bytes: &[u8]) -> String {
     hash::hex_digest(algorithm, bytes)
 }
 
+/// Hash of the file `p` with the algorithm `t`, like `"sha256"`
 #[builtin()]
-pub fn hash_file(t: String, p: NixValueWrapped) {
+pub fn hash_file(backtrace: &NixBacktrace, t: String, p: NixValueWrapped) {
     let Some(path) = p.borrow().as_path() else {
         todo!("Error Handling: hashFile cannot convert into path");
     };
+    let path = NixSettings::current().check_path(backtrace, path)?;
     let Ok(content) = std::fs::read(path) else {
         todo!("Error Handling: hashFile cannot read file");
     };
@@ -268,6 +288,8 @@ pub fn hash_file(t: String, p: NixValueWrapped) {
     Ok(NixValue::String(value).wrap())
 }
 
+/// Evaluates the Nix file at `argument`, or its `default.nix` if it's a
+/// directory
 #[builtin]
 pub fn import(backtrace: &NixBacktrace, argument: NixValueWrapped) {
     let argument = argument.borrow();
@@ -305,64 +327,75 @@ pub fn import(backtrace: &NixBacktrace, argument: NixValueWrapped) {
     Scope::import_path(backtrace, path)
 }
 
-#[builtin]
 /// Log a variable and return it
+#[builtin]
 pub fn inspect(backtrace: &NixBacktrace, argument: NixVar) {
     let argument = argument.resolve_set(true, backtrace)?;
-    println!("{argument:#?}");
+    eprintln!("{argument:#?}");
     Ok(argument)
 }
 
+/// Whether `argument` is a set
 #[builtin]
 pub fn is_attrs(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_attr_set()).wrap())
 }
 
+/// Whether `argument` is a bool
 #[builtin]
 pub fn is_bool(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().as_bool().is_some()).wrap())
 }
 
+/// Whether `argument` is a function
 #[builtin]
 pub fn is_function(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_function()).wrap())
 }
 
+/// Whether `argument` is a float
 #[builtin]
 pub fn is_float(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_float()).wrap())
 }
 
+/// Whether `argument` is an integer
 #[builtin]
 pub fn is_int(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_int()).wrap())
 }
 
+/// Whether `argument` is a list
 #[builtin()]
 pub fn is_list(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_list()).wrap())
 }
 
+/// Whether `argument` is `null`
 #[builtin()]
 pub fn is_null(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_null()).wrap())
 }
 
+/// Whether `argument` is a path
 #[builtin()]
 pub fn is_path(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_path()).wrap())
 }
 
+/// Whether `argument` is a string
 #[builtin]
 pub fn is_string(argument: NixValueWrapped) {
     Ok(NixValue::Bool(argument.borrow().is_string()).wrap())
 }
 
+/// Amount of items of `list`
 #[builtin()]
 pub fn length(list: NixList) {
     Ok(NixValue::Int(list.0.len() as i64).wrap())
 }
 
+/// Set from a list of `{ name = ...; value = ...; }` sets
 #[builtin]
 pub fn list_to_attrs(backtrace: &NixBacktrace, list: NixList) {
     let out = list
@@ -402,6 +435,7 @@ pub fn list_to_attrs(backtrace: &NixBacktrace, list: NixList) {
     Ok(NixValue::AttrSet(out).wrap())
 }
 
+/// Applies `callback` to every item of `list`
 #[builtin]
 pub fn map(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     let mut out = Vec::with_capacity(list.0.len());
@@ -415,6 +449,7 @@ pub fn map(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
+/// Applies `callback` to the name and value of every attribute of `set`
 #[builtin]
 pub fn map_attrs(backtrace: &NixBacktrace, callback: NixLambda, set: NixValueWrapped) {
     let set = set.borrow();
@@ -441,6 +476,8 @@ pub fn map_attrs(backtrace: &NixBacktrace, callback: NixLambda, set: NixValueWra
     Ok(NixValue::AttrSet(out).wrap())
 }
 
+/// List of the groups of `regex` if it matches the whole `content`, or
+/// `null`
 #[builtin]
 pub fn r#match(regex: String, content: String) {
     // TODO: Should do a regex caching, specially for loop optimisation
@@ -466,19 +503,23 @@ pub fn r#match(regex: String, content: String) {
         .wrap())
 }
 
+/// Whether the file or directory at `path` exists
 #[builtin()]
-pub fn path_exists(path: PathBuf) {
+pub fn path_exists(backtrace: &NixBacktrace, path: PathBuf) {
+    let path = NixSettings::current().check_path(backtrace, path)?;
     let exists = path.try_exists().is_ok_and(|x| x);
 
     Ok(NixValue::Bool(exists).wrap())
 }
 
+/// Contents of the file at `path`
 #[builtin]
-pub fn read_file(path: NixValueWrapped) {
+pub fn read_file(backtrace: &NixBacktrace, path: NixValueWrapped) {
     let path = path.borrow();
     let Some(path) = path.as_path() else {
         todo!("Error Handling");
     };
+    let path = NixSettings::current().check_path(backtrace, path)?;
     let Ok(content) = std::fs::read_to_string(path) else {
         todo!("Error Handling");
     };
@@ -486,12 +527,15 @@ pub fn read_file(path: NixValueWrapped) {
     Ok(NixValue::String(content).wrap())
 }
 
+/// Type of the file at `path`, `"regular"`, `"directory"`,
+/// `"symlink"` or `"unknown"`
 #[builtin]
-pub fn read_file_type(path: NixValueWrapped) {
+pub fn read_file_type(backtrace: &NixBacktrace, path: NixValueWrapped) {
     let path = path.borrow();
     let Some(path) = path.as_path() else {
         todo!("Error Handling");
     };
+    let path = NixSettings::current().check_path(backtrace, path)?;
     let Ok(metadata) = std::fs::metadata(path) else {
         todo!("Error Handling");
     };
@@ -507,6 +551,8 @@ pub fn read_file_type(path: NixValueWrapped) {
     Ok(NixValue::String(res.to_owned()).wrap())
 }
 
+/// Replaces every occurrence of the strings of `from` in `s` with the
+/// string at the same index of `to`
 #[builtin]
 pub fn replace_strings(
     backtrace: &NixBacktrace,
@@ -576,6 +622,7 @@ pub fn replace_strings(
     Ok(NixValue::String(res).wrap())
 }
 
+/// `attrset` without the attributes named in `attrs`
 #[builtin()]
 pub fn remove_attrs(backtrace: &NixBacktrace, attrset: NixValueWrapped, attrs: NixList) {
     if !attrset.borrow().is_attr_set() {
@@ -600,6 +647,8 @@ pub fn remove_attrs(backtrace: &NixBacktrace, attrset: NixValueWrapped, attrs: N
     Ok(NixValue::AttrSet(attrset).wrap())
 }
 
+/// Part of `s` that starts at `start` with a length of `len`, or up to
+/// the end if `len` is negative
 #[builtin]
 pub fn substring(start: usize, len: isize, s: String) {
     if len < 0 || start + len as usize > s.len() {
@@ -611,6 +660,8 @@ pub fn substring(start: usize, len: isize, s: String) {
     }
 }
 
+/// Splits `content` by the matches of `regex`, the list has the strings
+/// between the matches and the lists of groups of each match
 #[builtin]
 pub fn split(regex: String, content: String) {
     // TODO: Should do a regex caching, specially for loop optimisation
@@ -648,50 +699,132 @@ pub fn split(regex: String, content: String) {
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
+/// Length of the string `argument` in bytes
 #[builtin]
 pub fn string_length(argument: NixValueWrapped) {
     Ok(NixValue::Int(argument.borrow().cast_to_string().unwrap().len() as i64).wrap())
 }
 
+/// Converts `argument` to a JSON string, forcing it deeply
+#[builtin("toJSON")]
+pub fn to_json(backtrace: &NixBacktrace, argument: NixValueWrapped) {
+    let mut out = String::new();
+    json::to_json(backtrace, &argument, &mut out)?;
+
+    Ok(NixValue::String(out).wrap())
+}
+
+/// Converts `argument` to the XML format of Nix, forcing it deeply
+#[builtin("toXML")]
+pub fn to_xml(backtrace: &NixBacktrace, argument: NixValueWrapped) {
+    Ok(NixValue::String(xml::to_xml(backtrace, &argument, true, false)?).wrap())
+}
+
+/// Converts `argument` to a string, `null` is the empty string, the values of
+/// lists are separated by spaces and sets need `__toString` or `outPath`, like
+/// derivations
 #[builtin()]
-pub fn to_string(argument: String) {
-    Ok(NixValue::String(argument).wrap())
+pub fn to_string(
+    backtrace: &NixBacktrace,
+    #[optional(NixValue::String(String::new()).wrap())] argument: NixValueWrapped,
+) {
+    Ok(NixValue::String(coerce_to_string(backtrace, &argument)?).wrap())
 }
 
+/// See [`to_string`], sets are converted like in [`json::to_json`]
+fn coerce_to_string(backtrace: &NixBacktrace, value: &NixValueWrapped) -> NixResult<String> {
+    let value_ref = value.borrow();
+
+    match &*value_ref {
+        NixValue::AttrSet(set) => {
+            if let Some(string) = json::call_to_string(backtrace, value, set)? {
+                return Ok(string);
+            }
+
+            match set.get("outPath") {
+                Some(out_path) => coerce_to_string(backtrace, &out_path.resolve(backtrace)?),
+                None => Err(type_error(
+                    backtrace,
+                    "a value convertible to a string",
+                    &value_ref,
+                )),
+            }
+        }
+        NixValue::List(list) => list
+            .0
+            .iter()
+            .map(|var| coerce_to_string(backtrace, &var.resolve(backtrace)?))
+            .collect::<NixResult<Vec<_>>>()
+            .map(|strings| strings.join(" ")),
+        value => value
+            .cast_to_string()
+            .ok_or_else(|| type_error(backtrace, "a value convertible to a string", value)),
+    }
+}
+
+/// Stops the evaluation with `message`, unless it's caught by
+/// `builtins.tryEval`
 #[builtin]
 pub fn throw(backtrace: &NixBacktrace, message: String) {
     // TODO: in `nix-env -qa` and other commands that try
     // to evaluate a derivation that throws an error is
     // silently skipped (which is not the case for abort).
 
-    let error = backtrace.to_error(
+    Err(backtrace.to_error(
         NixLabelKind::Error,
         NixLabelMessage::Empty,
         format!("Throwing: {message}"),
-    );
+    ))
+}
+
+/// Prints `message` and returns `argument`
+#[builtin]
+pub fn trace(message: NixValueWrapped, #[lazy] argument: NixValueWrapped) {
+    {
+        let message = message.borrow();
+
+        if message.is_string() || message.is_path() {
+            let message = message.cast_to_string().unwrap();
+            eprintln!("trace: {message}");
+        } else {
+            eprintln!("trace: {message:?}");
+        }
+    }
 
-    print!("{error}");
+    argument.force()
+}
+
+/// Returns `argument`, stopping the evaluation first if the debugger is
+/// enabled
+#[builtin("break")]
+pub fn break_(#[lazy] argument: NixValueWrapped) {
+    NixDebugger::break_here();
 
-    std::process::exit(1)
+    argument.force()
 }
 
+/// Evaluates `first` and returns `second`
 #[builtin]
-pub fn trace(message: NixValueWrapped, argument: NixValueWrapped) {
-    let message = message.borrow();
+pub fn seq(first: NixValueWrapped, #[lazy] second: NixValueWrapped) {
+    drop(first);
 
-    if message.is_string() || message.is_path() {
-        let message = message.cast_to_string().unwrap();
-        println!("trace: {message}");
-    } else {
-        println!("trace: {message:?}");
-    }
+    second.force()
+}
 
-    Ok(argument)
+/// Adds `context` to the errors of the evaluation of `argument`
+#[builtin]
+pub fn add_error_context(context: String, #[lazy] argument: NixValueWrapped) {
+    argument.force().map_err(|mut error| {
+        error.message = format!("{}\n… {context}", error.message);
+        error
+    })
 }
 
+/// `{ success = true; value = argument; }` if `argument` can be
+/// evaluated, `{ success = false; value = false; }` if it fails
 #[builtin()]
-pub fn try_eval(backtrace: &NixBacktrace, argument: NixVar) {
-    if let Err(_) = argument.resolve(backtrace) {
+pub fn try_eval(#[lazy] argument: NixValueWrapped) {
+    if argument.force().is_err() {
         let mut result = NixAttrSet::new();
         result.insert("success".to_string(), NixValue::Bool(false).wrap_var());
         // `value = false;` is unfortunate but removing it is a breaking change.
@@ -703,16 +836,39 @@ pub fn try_eval(backtrace: &NixBacktrace, argument: NixVar) {
 
     let mut result = NixAttrSet::new();
     result.insert("success".to_string(), NixValue::Bool(true).wrap_var());
-    result.insert("value".to_string(), argument);
+    result.insert("value".to_string(), argument.var().clone());
 
     return Ok(NixValue::AttrSet(result).wrap());
 }
 
+/// Type of `argument`, like `"int"` or `"set"`
 #[builtin]
 pub fn type_of(argument: NixValueWrapped) {
     Ok(NixValue::String(argument.borrow().as_type().to_owned()).wrap())
 }
 
+/// Arguments of a function that takes a set, with `true` for the ones that
+/// have a default value. Builtins don't take a set, so it's empty like in Nix.
+#[builtin]
+pub fn function_args(function: NixLambda) {
+    let arguments = match function {
+        NixLambda::Apply(_, NixLambdaParam::Ident(_), _) | NixLambda::Builtin(_) => {
+            NixAttrSet::new()
+        }
+        NixLambda::Apply(_, NixLambdaParam::Pattern(pattern), _) => pattern
+            .pat_entries()
+            .map(|entry| {
+                let name = entry.ident().unwrap().ident_token().unwrap();
+                let has_default = NixValue::Bool(entry.default().is_some());
+
+                (name.text().to_owned(), has_default.wrap_var())
+            })
+            .collect(),
+    };
+
+    Ok(NixValue::AttrSet(arguments).wrap())
+}
+
 gen_builtins! {
     currentSystem = NixValue::String("x86_64-linux".to_owned());
     false = NixValue::Bool(false);
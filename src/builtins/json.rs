@@ -0,0 +1,488 @@
+//! JSON conversions of `builtins.toJSON` and `builtins.fromJSON`, following
+//! https://github.com/NixOS/nix/blob/master/src/libexpr/value-to-json.cc and
+//! https://github.com/NixOS/nix/blob/master/src/libexpr/json-to-value.cc
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::value::{NixAttrSet, NixList};
+
+use super::ParseError;
+use crate::{
+    LazyNixValue, NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue, NixValueWrapped,
+};
+
+/// Result of `__toString` of the set `value`, if it has it
+pub(super) fn call_to_string(
+    backtrace: &NixBacktrace,
+    value: &NixValueWrapped,
+    set: &NixAttrSet,
+) -> NixResult<Option<String>> {
+    let Some(to_string) = set.get("__toString") else {
+        return Ok(None);
+    };
+
+    let to_string = to_string.resolve(backtrace)?;
+    let Some(to_string) = to_string.borrow().as_lambda().cloned() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::ExpectedType("a function"),
+            "__toString of a set must be a function",
+        ));
+    };
+
+    let string = to_string
+        .call(backtrace, LazyNixValue::Concrete(value.clone()).wrap_var())?
+        .resolve(backtrace)?;
+    let Some(string) = string.borrow().cast_to_string() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::ExpectedType("a string"),
+            "__toString of a set must return a string",
+        ));
+    };
+
+    Ok(Some(string))
+}
+
+/// Serializes `value` forcing it deeply, sets with `__toString` or `outPath`
+/// are converted to strings like derivations
+pub fn to_json(
+    backtrace: &NixBacktrace,
+    value: &NixValueWrapped,
+    out: &mut String,
+) -> NixResult<()> {
+    match &*value.borrow() {
+        NixValue::AttrSet(set) => {
+            if let Some(string) = call_to_string(backtrace, value, set)? {
+                write_string(&string, out);
+                return Ok(());
+            }
+
+            if let Some(out_path) = set.get("outPath") {
+                return to_json(backtrace, &out_path.resolve(backtrace)?, out);
+            }
+
+            out.push('{');
+
+            for (idx, (key, value)) in set.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+
+                write_string(key, out);
+                out.push(':');
+                to_json(backtrace, &value.resolve(backtrace)?, out)?;
+            }
+
+            out.push('}');
+        }
+        NixValue::Bool(true) => out.push_str("true"),
+        NixValue::Bool(false) => out.push_str("false"),
+        NixValue::Float(float) => write_float(*float, out),
+        NixValue::Int(int) => write!(out, "{int}").unwrap(),
+        NixValue::Lambda(_) => {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                "cannot convert a function to JSON",
+            ));
+        }
+        NixValue::List(list) => {
+            out.push('[');
+
+            for (idx, value) in list.0.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+
+                to_json(backtrace, &value.resolve(backtrace)?, out)?;
+            }
+
+            out.push(']');
+        }
+        NixValue::Null => out.push_str("null"),
+        // TODO: Copy the path to the store
+        NixValue::Path(path) => write_string(&path.display().to_string(), out),
+        NixValue::String(string) => write_string(string, out),
+    }
+
+    Ok(())
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+/// Shortest representation that round-trips, like `1.0`, `0.001` or `1e+20`
+fn write_float(float: f64, out: &mut String) {
+    if !float.is_finite() {
+        out.push_str("null");
+        return;
+    }
+
+    // `{:e}` gives the shortest digits, like `-1.25e-7`
+    let scientific = format!("{:e}", float.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent.parse().unwrap();
+
+    if float.is_sign_negative() {
+        out.push('-');
+    }
+
+    // Position of the decimal point relative to the digits
+    let point = exponent + 1;
+    let len = digits.len() as i32;
+
+    if len <= point && point <= 15 {
+        out.push_str(&digits);
+        out.extend((len..point).map(|_| '0'));
+        out.push_str(".0");
+    } else if 0 < point && point <= 15 {
+        out.push_str(&digits[..point as usize]);
+        out.push('.');
+        out.push_str(&digits[point as usize..]);
+    } else if -4 < point && point <= 0 {
+        out.push_str("0.");
+        out.extend((point..0).map(|_| '0'));
+        out.push_str(&digits);
+    } else {
+        out.push_str(&digits[..1]);
+
+        if len > 1 {
+            out.push('.');
+            out.push_str(&digits[1..]);
+        }
+
+        let sign = if exponent < 0 { '-' } else { '+' };
+        write!(out, "e{sign}{:02}", exponent.abs()).unwrap();
+    }
+}
+
+/// Parses `input`, duplicated keys keep the last value
+pub fn from_json(input: &str) -> Result<NixValue, ParseError> {
+    let mut parser = Parser { input, offset: 0 };
+
+    let value = parser.value()?;
+
+    parser.whitespace();
+
+    if parser.offset < input.len() {
+        return Err(parser.unexpected("end of input"));
+    }
+
+    Ok(value)
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl ToString) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            message: message.to_string(),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> ParseError {
+        match self.peek() {
+            Some(c) => self.error(format!("unexpected `{c}`, expected {expected}")),
+            None => self.error(format!("unexpected end of input, expected {expected}")),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.offset..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.offset += c.len_utf8();
+
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let eaten = self.peek() == Some(c);
+
+        if eaten {
+            self.offset += 1;
+        }
+
+        eaten
+    }
+
+    fn expect(&mut self, c: char, expected: &str) -> Result<(), ParseError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.offset += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<NixValue, ParseError> {
+        self.whitespace();
+
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(NixValue::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some('t' | 'f' | 'n') => {
+                for (literal, value) in [
+                    ("true", NixValue::Bool(true)),
+                    ("false", NixValue::Bool(false)),
+                    ("null", NixValue::Null),
+                ] {
+                    if self.input[self.offset..].starts_with(literal) {
+                        self.offset += literal.len();
+                        return Ok(value);
+                    }
+                }
+
+                Err(self.error("invalid literal"))
+            }
+            _ => Err(self.unexpected("a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<NixValue, ParseError> {
+        self.expect('{', "`{`")?;
+        self.whitespace();
+
+        let mut set = NixAttrSet::new();
+
+        if self.eat('}') {
+            return Ok(NixValue::AttrSet(set));
+        }
+
+        loop {
+            self.whitespace();
+
+            if self.peek() != Some('"') {
+                return Err(self.unexpected("a string as key"));
+            }
+
+            let key = self.string()?;
+
+            self.whitespace();
+            self.expect(':', "`:`")?;
+
+            let value = self.value()?;
+            set.insert(key, value.wrap_var());
+
+            self.whitespace();
+
+            if !self.eat(',') {
+                self.expect('}', "`,` or `}`")?;
+                return Ok(NixValue::AttrSet(set));
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<NixValue, ParseError> {
+        self.expect('[', "`[`")?;
+        self.whitespace();
+
+        let mut list = Vec::new();
+
+        if self.eat(']') {
+            return Ok(NixValue::List(NixList(Rc::new(list))));
+        }
+
+        loop {
+            list.push(self.value()?.wrap_var());
+
+            self.whitespace();
+
+            if !self.eat(',') {
+                self.expect(']', "`,` or `]`")?;
+                return Ok(NixValue::List(NixList(Rc::new(list))));
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect('"', "`\"`")?;
+
+        let mut string = String::new();
+
+        loop {
+            let start = self.offset;
+
+            let Some(c) = self.next() else {
+                return Err(self.error("unexpected end of input, expected `\"`"));
+            };
+
+            match c {
+                '"' => return Ok(string),
+                '\\' => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape(start)?,
+                        _ => {
+                            self.offset = start;
+                            return Err(self.error("invalid escape sequence"));
+                        }
+                    };
+
+                    string.push(escaped);
+                }
+                c if c < ' ' => {
+                    self.offset = start;
+                    return Err(self.error(format!(
+                        "control character U+{:04X} must be escaped",
+                        c as u32
+                    )));
+                }
+                c => string.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let hex = self.input.get(self.offset..self.offset + 4)?;
+
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        self.offset += 4;
+
+        u32::from_str_radix(hex, 16).ok()
+    }
+
+    /// `\uXXXX` after the `u`, surrogate pairs are joined
+    fn unicode_escape(&mut self, start: usize) -> Result<char, ParseError> {
+        let invalid = |this: &mut Self, message: &str| {
+            this.offset = start;
+            Err(this.error(message))
+        };
+
+        let Some(high) = self.hex4() else {
+            return invalid(self, "`\\u` must be followed by 4 hex digits");
+        };
+
+        let code = match high {
+            0xD800..=0xDBFF => {
+                if !self.input[self.offset..].starts_with("\\u") {
+                    return invalid(
+                        self,
+                        "surrogate U+D800..U+DBFF must be followed by U+DC00..U+DFFF",
+                    );
+                }
+
+                self.offset += 2;
+
+                match self.hex4() {
+                    Some(low @ 0xDC00..=0xDFFF) => {
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    }
+                    _ => {
+                        return invalid(
+                            self,
+                            "surrogate U+D800..U+DBFF must be followed by U+DC00..U+DFFF",
+                        )
+                    }
+                }
+            }
+            0xDC00..=0xDFFF => {
+                return invalid(self, "surrogate U+DC00..U+DFFF must follow U+D800..U+DBFF")
+            }
+            code => code,
+        };
+
+        Ok(char::from_u32(code).unwrap())
+    }
+
+    fn number(&mut self) -> Result<NixValue, ParseError> {
+        let start = self.offset;
+        let mut is_float = false;
+
+        self.eat('-');
+
+        let digits = |this: &mut Self| {
+            let start = this.offset;
+
+            while matches!(this.peek(), Some('0'..='9')) {
+                this.offset += 1;
+            }
+
+            this.offset > start
+        };
+
+        if !self.eat('0') && !digits(self) {
+            return Err(self.unexpected("a digit"));
+        }
+
+        if self.eat('.') {
+            is_float = true;
+
+            if !digits(self) {
+                return Err(self.unexpected("a digit"));
+            }
+        }
+
+        if self.eat('e') || self.eat('E') {
+            is_float = true;
+
+            if !self.eat('+') {
+                self.eat('-');
+            }
+
+            if !digits(self) {
+                return Err(self.unexpected("a digit"));
+            }
+        }
+
+        let number = &self.input[start..self.offset];
+
+        if !is_float {
+            if let Ok(int) = number.parse::<i64>() {
+                return Ok(NixValue::Int(int));
+            }
+
+            if number.parse::<u64>().is_ok() {
+                self.offset = start;
+                return Err(self.error(format!(
+                    "unsigned json number {number} outside of Nix integer range"
+                )));
+            }
+        }
+
+        Ok(NixValue::Float(number.parse().unwrap()))
+    }
+}
@@ -0,0 +1,156 @@
+//! TOML conversion of `builtins.fromTOML`, following
+//! https://github.com/NixOS/nix/blob/master/src/libexpr/primops/fromTOML.cc
+
+use std::fmt::Write;
+use std::rc::Rc;
+
+use toml_edit::{Datetime, ImDocument, Item, Offset, Table, Value};
+
+use crate::value::{NixAttrSet, NixList};
+use crate::NixValue;
+
+use super::ParseError;
+
+/// Parses `input`, dates and times are `{ _type = "timestamp"; value = ...; }`
+/// with `timestamps` and an error otherwise
+pub fn from_toml(input: &str, timestamps: bool) -> Result<NixValue, ParseError> {
+    let document = ImDocument::parse(input).map_err(|error| {
+        let offset = error.span().map_or(0, |span| span.start);
+
+        let message = match error.message().trim_end() {
+            "" if offset == input.len() => "unexpected end of input".to_owned(),
+            "" => "unexpected token".to_owned(),
+            message => message.replace('\n', ", "),
+        };
+
+        ParseError { offset, message }
+    })?;
+
+    Converter { timestamps }.table(document.as_table())
+}
+
+struct Converter {
+    timestamps: bool,
+}
+
+impl Converter {
+    fn table(&self, table: &Table) -> Result<NixValue, ParseError> {
+        let mut set = NixAttrSet::new();
+
+        for (key, item) in table.iter() {
+            set.insert(key.to_owned(), self.item(item)?.wrap_var());
+        }
+
+        Ok(NixValue::AttrSet(set))
+    }
+
+    fn item(&self, item: &Item) -> Result<NixValue, ParseError> {
+        match item {
+            Item::None => Ok(NixValue::Null),
+            Item::Value(value) => self.value(value),
+            Item::Table(table) => self.table(table),
+            Item::ArrayOfTables(array) => {
+                let list = array
+                    .iter()
+                    .map(|table| self.table(table).map(NixValue::wrap_var))
+                    .collect::<Result<_, _>>()?;
+
+                Ok(NixValue::List(NixList(Rc::new(list))))
+            }
+        }
+    }
+
+    fn value(&self, value: &Value) -> Result<NixValue, ParseError> {
+        Ok(match value {
+            Value::String(string) => NixValue::String(string.value().clone()),
+            Value::Integer(int) => NixValue::Int(*int.value()),
+            Value::Float(float) => NixValue::Float(*float.value()),
+            Value::Boolean(bool) => NixValue::Bool(*bool.value()),
+            Value::Datetime(datetime) => {
+                if !self.timestamps {
+                    return Err(ParseError {
+                        offset: value.span().map_or(0, |span| span.start),
+                        message: "Dates and times are not supported".to_owned(),
+                    });
+                }
+
+                let mut set = NixAttrSet::new();
+                set.insert(
+                    "_type".to_owned(),
+                    NixValue::String("timestamp".to_owned()).wrap_var(),
+                );
+                set.insert(
+                    "value".to_owned(),
+                    NixValue::String(format_datetime(datetime.value())).wrap_var(),
+                );
+
+                NixValue::AttrSet(set)
+            }
+            Value::Array(array) => {
+                let list = array
+                    .iter()
+                    .map(|value| self.value(value).map(NixValue::wrap_var))
+                    .collect::<Result<_, _>>()?;
+
+                NixValue::List(NixList(Rc::new(list)))
+            }
+            Value::InlineTable(table) => {
+                let mut set = NixAttrSet::new();
+
+                for (key, value) in table.iter() {
+                    set.insert(key.to_owned(), self.value(value)?.wrap_var());
+                }
+
+                NixValue::AttrSet(set)
+            }
+        })
+    }
+}
+
+/// Formats `datetime` like toml11, the parser of Nix. The fraction of the
+/// seconds has groups of 3 digits, and an offset of 0 is `Z`.
+fn format_datetime(datetime: &Datetime) -> String {
+    let mut out = String::new();
+
+    if let Some(date) = datetime.date {
+        write!(out, "{:04}-{:02}-{:02}", date.year, date.month, date.day).unwrap();
+    }
+
+    if let Some(time) = datetime.time {
+        if datetime.date.is_some() {
+            out.push('T');
+        }
+
+        write!(
+            out,
+            "{:02}:{:02}:{:02}",
+            time.hour, time.minute, time.second
+        )
+        .unwrap();
+
+        if time.nanosecond != 0 {
+            let fraction = format!("{:09}", time.nanosecond);
+            let len = if time.nanosecond % 1_000_000 == 0 {
+                3
+            } else if time.nanosecond % 1_000 == 0 {
+                6
+            } else {
+                9
+            };
+
+            write!(out, ".{}", &fraction[..len]).unwrap();
+        }
+    }
+
+    match datetime.offset {
+        None => {}
+        Some(Offset::Z | Offset::Custom { minutes: 0 }) => out.push('Z'),
+        Some(Offset::Custom { minutes }) => {
+            let sign = if minutes < 0 { '-' } else { '+' };
+            let minutes = minutes.unsigned_abs();
+            write!(out, "{sign}{:02}:{:02}", minutes / 60, minutes % 60).unwrap();
+        }
+    }
+
+    out
+}
@@ -0,0 +1,305 @@
+//! XML format of `builtins.toXML` and `--xml`, following
+//! https://github.com/NixOS/nix/blob/master/src/libexpr/value-to-xml.cc
+
+use std::collections::{BTreeMap, HashSet};
+
+use rnix::ast;
+use rowan::ast::AstNode;
+
+use crate::value::{NixAttrSet, NixLambda, NixLambdaParam};
+use crate::{NixBacktrace, NixResult, NixSpan, NixValue, NixValueWrapped, NixVar};
+
+/// Attributes of an element, sorted by name like in Nix
+type XmlAttrs = BTreeMap<&'static str, String>;
+
+/// Serializes `value`, without `strict` the values that aren't evaluated yet
+/// are `<unevaluated />`. With `location` attributes and functions have the
+/// position where they're defined.
+pub fn to_xml(
+    backtrace: &NixBacktrace,
+    value: &NixValueWrapped,
+    strict: bool,
+    location: bool,
+) -> NixResult<String> {
+    let mut printer = Printer {
+        backtrace,
+        strict,
+        location,
+        out: String::from("<?xml version='1.0' encoding='utf-8'?>\n"),
+        elements: Vec::new(),
+        drvs_seen: HashSet::new(),
+    };
+
+    printer.open("expr", XmlAttrs::new());
+    printer.value(value)?;
+    printer.close();
+
+    Ok(printer.out)
+}
+
+struct Printer<'a> {
+    backtrace: &'a NixBacktrace,
+    strict: bool,
+    location: bool,
+    out: String,
+    /// Open elements
+    elements: Vec<&'static str>,
+    /// `drvPath` of the derivations already printed
+    drvs_seen: HashSet<String>,
+}
+
+impl Printer<'_> {
+    fn indent(&mut self) {
+        self.out.extend((0..self.elements.len() * 2).map(|_| ' '));
+    }
+
+    fn attrs(&mut self, attrs: XmlAttrs) {
+        for (name, value) in attrs {
+            self.out.push(' ');
+            self.out.push_str(name);
+            self.out.push_str("=\"");
+
+            for c in value.chars() {
+                match c {
+                    '"' => self.out.push_str("&quot;"),
+                    '<' => self.out.push_str("&lt;"),
+                    '>' => self.out.push_str("&gt;"),
+                    '&' => self.out.push_str("&amp;"),
+                    // Prevents the normalization of attributes values
+                    '\n' => self.out.push_str("&#xA;"),
+                    c => self.out.push(c),
+                }
+            }
+
+            self.out.push('"');
+        }
+    }
+
+    fn open(&mut self, name: &'static str, attrs: XmlAttrs) {
+        self.indent();
+        self.out.push('<');
+        self.out.push_str(name);
+        self.attrs(attrs);
+        self.out.push_str(">\n");
+        self.elements.push(name);
+    }
+
+    fn close(&mut self) {
+        let name = self.elements.pop().unwrap();
+
+        self.indent();
+        self.out.push_str("</");
+        self.out.push_str(name);
+        self.out.push_str(">\n");
+    }
+
+    fn empty(&mut self, name: &'static str, attrs: XmlAttrs) {
+        self.indent();
+        self.out.push('<');
+        self.out.push_str(name);
+        self.attrs(attrs);
+        self.out.push_str(" />\n");
+    }
+
+    fn empty_value(&mut self, name: &'static str, value: String) {
+        self.empty(name, XmlAttrs::from([("value", value)]));
+    }
+
+    fn position(&self, attrs: &mut XmlAttrs, span: &NixSpan) {
+        if !self.location {
+            return;
+        }
+
+        attrs.insert("path", span.file.path.display().to_string());
+        attrs.insert("line", span.start.0.to_string());
+        attrs.insert("column", (span.start.1 + 1).to_string());
+    }
+
+    /// Resolves `var` if it's strict, `None` if it isn't evaluated yet
+    fn force(&self, var: &NixVar) -> NixResult<Option<NixValueWrapped>> {
+        if self.strict {
+            var.resolve(self.backtrace).map(Some)
+        } else {
+            Ok(var.as_concrete())
+        }
+    }
+
+    fn var(&mut self, var: &NixVar) -> NixResult<()> {
+        match self.force(var)? {
+            Some(value) => self.value(&value),
+            None => {
+                self.empty("unevaluated", XmlAttrs::new());
+                Ok(())
+            }
+        }
+    }
+
+    fn value(&mut self, value: &NixValueWrapped) -> NixResult<()> {
+        match &*value.borrow() {
+            NixValue::AttrSet(set) => {
+                if self.is_derivation(set)? {
+                    return self.derivation(set);
+                }
+
+                self.open("attrs", XmlAttrs::new());
+                self.attr_set(set)?;
+                self.close();
+            }
+            NixValue::Bool(bool) => self.empty_value("bool", bool.to_string()),
+            NixValue::Float(float) => self.empty_value("float", format_float(*float)),
+            NixValue::Int(int) => self.empty_value("int", int.to_string()),
+            NixValue::Lambda(NixLambda::Apply(scope, param, body)) => {
+                let mut attrs = XmlAttrs::new();
+
+                if let Some(lambda) = body.syntax().parent().and_then(ast::Lambda::cast) {
+                    self.position(&mut attrs, &NixSpan::from_ast_node(&scope.file, &lambda));
+                }
+
+                self.open("function", attrs);
+
+                match param {
+                    NixLambdaParam::Ident(name) => {
+                        self.empty("varpat", XmlAttrs::from([("name", name.clone())]));
+                    }
+                    NixLambdaParam::Pattern(pattern) => {
+                        let mut attrs = XmlAttrs::new();
+
+                        if let Some(name) = pattern.pat_bind().and_then(|bind| bind.ident()) {
+                            attrs.insert("name", name.to_string());
+                        }
+
+                        if pattern.ellipsis_token().is_some() {
+                            attrs.insert("ellipsis", "1".to_owned());
+                        }
+
+                        self.open("attrspat", attrs);
+
+                        let mut names = pattern
+                            .pat_entries()
+                            .filter_map(|entry| entry.ident())
+                            .map(|ident| ident.to_string())
+                            .collect::<Vec<_>>();
+                        names.sort();
+
+                        for name in names {
+                            self.empty("attr", XmlAttrs::from([("name", name)]));
+                        }
+
+                        self.close();
+                    }
+                }
+
+                self.close();
+            }
+            // Builtins aren't lambdas in Nix, even if they are partially
+            // applied
+            NixValue::Lambda(NixLambda::Builtin(_)) => self.empty("unimplemented", XmlAttrs::new()),
+            NixValue::List(list) => {
+                self.open("list", XmlAttrs::new());
+
+                for var in list.0.iter() {
+                    self.var(var)?;
+                }
+
+                self.close();
+            }
+            NixValue::Null => self.empty("null", XmlAttrs::new()),
+            NixValue::Path(path) => self.empty_value("path", path.display().to_string()),
+            NixValue::String(string) => self.empty_value("string", string.clone()),
+        }
+
+        Ok(())
+    }
+
+    fn attr_set(&mut self, set: &NixAttrSet) -> NixResult<()> {
+        for (name, var) in set {
+            let mut attrs = XmlAttrs::from([("name", name.clone())]);
+
+            if let Some(position) = var.position() {
+                self.position(&mut attrs, position);
+            }
+
+            self.open("attr", attrs);
+            self.var(var)?;
+            self.close();
+        }
+
+        Ok(())
+    }
+
+    fn is_derivation(&self, set: &NixAttrSet) -> NixResult<bool> {
+        let Some(ty) = set.get("type") else {
+            return Ok(false);
+        };
+
+        let ty = ty.resolve(self.backtrace)?;
+        let is_derivation = ty.borrow().as_string().is_some_and(|ty| ty == "derivation");
+
+        Ok(is_derivation)
+    }
+
+    fn derivation(&mut self, set: &NixAttrSet) -> NixResult<()> {
+        let mut attrs = XmlAttrs::new();
+
+        for name in ["drvPath", "outPath"] {
+            let Some(var) = set.get(name) else {
+                continue;
+            };
+
+            if let Some(value) = self.force(var)? {
+                if let Some(path) = value.borrow().as_string() {
+                    attrs.insert(name, path.clone());
+                }
+            }
+        }
+
+        let drv_path = attrs.get("drvPath").cloned().unwrap_or_default();
+
+        self.open("derivation", attrs);
+
+        if !drv_path.is_empty() && self.drvs_seen.insert(drv_path) {
+            self.attr_set(set)?;
+        } else {
+            self.empty("repeated", XmlAttrs::new());
+        }
+
+        self.close();
+
+        Ok(())
+    }
+}
+
+/// Like C++ streams, 6 significant digits without trailing zeros
+fn format_float(float: f64) -> String {
+    if float.is_nan() {
+        return "nan".to_owned();
+    }
+
+    if float.is_infinite() {
+        return if float > 0.0 { "inf" } else { "-inf" }.to_owned();
+    }
+
+    // Rounded to 6 digits, like `1.50000e0`
+    let scientific = format!("{float:.5e}");
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    let trim = |number: &str| {
+        if number.contains('.') {
+            number
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_owned()
+        } else {
+            number.to_owned()
+        }
+    };
+
+    if float == 0.0 || (-4..6).contains(&exponent) {
+        let decimals = (5 - exponent).max(0) as usize;
+        trim(&format!("{float:.decimals$}"))
+    } else {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{sign}{:02}", trim(mantissa), exponent.abs())
+    }
+}
@@ -0,0 +1,228 @@
+//! Prompt of `--debugger`, shown every time the evaluation stops
+
+use std::cell::RefCell;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use nix_compiler::{
+    FileScope, LazyNixValue, NixDebugAction, NixDebugHandler, NixDebugger, NixFrame, NixPrinter,
+    NixStopReason,
+};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+const HELP: &str = "\
+The following commands are available:
+
+  <expr>              Evaluate and print an expression in the current frame
+  :?, :help           Print this help
+  :b <file>:<line>    Stop at a line, or list the breakpoints without arguments
+  :bt, :backtrace     Show the frames, the current one is marked with '*'
+  :c, :continue       Continue until the next breakpoint, error or break
+  :d <file>:<line>    Delete a breakpoint
+  :down, :up          Go to the inner or the outer frame
+  :env                Show the variables of the current frame
+  :q, :quit           Stop the evaluation and exit
+  :s, :step           Stop again at the next expression
+  :st <n>             Go to the frame <n>";
+
+pub struct Prompt {
+    editor: RefCell<DefaultEditor>,
+}
+
+impl Prompt {
+    pub fn new() -> Self {
+        let editor = DefaultEditor::new().unwrap_or_else(|err| {
+            eprintln!("Cannot start the line editor: {err}");
+            std::process::exit(1);
+        });
+
+        Self {
+            editor: RefCell::new(editor),
+        }
+    }
+}
+
+impl NixDebugHandler for Prompt {
+    fn stop(
+        &self,
+        debugger: &NixDebugger,
+        reason: NixStopReason,
+        frames: &[NixFrame],
+    ) -> NixDebugAction {
+        match reason {
+            NixStopReason::Breakpoint => println!("Stopped at a breakpoint"),
+            NixStopReason::Step => {}
+            NixStopReason::Break => println!("Stopped at builtins.break"),
+            NixStopReason::Error(error) => eprintln!("{error}"),
+        }
+
+        // Index from the innermost frame
+        let mut selected = 0;
+
+        if let Some(frame) = frame(frames, selected) {
+            print_frame(frame, selected, true);
+        }
+
+        let mut editor = self.editor.borrow_mut();
+
+        loop {
+            let line = match editor.readline("nix-debug> ") {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return NixDebugAction::Continue,
+                Err(err) => {
+                    eprintln!("{err}");
+                    return NixDebugAction::Continue;
+                }
+            };
+
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let _ = editor.add_history_entry(line);
+
+            let (command, argument) = match line.split_once(char::is_whitespace) {
+                Some((command, argument)) if command.starts_with(':') => (command, argument.trim()),
+                _ if line.starts_with(':') => (line, ""),
+                _ => ("", line),
+            };
+
+            match command {
+                "" => match frame(frames, selected) {
+                    Some(frame) => eval(frame, argument),
+                    None => eprintln!("There are no frames to evaluate the expression in"),
+                },
+                ":?" | ":help" => println!("{HELP}"),
+                ":b" if argument.is_empty() => {
+                    for (path, line) in debugger.breakpoints() {
+                        println!("{}:{line}", path.display());
+                    }
+                }
+                ":b" => {
+                    if let Some((path, line)) = parse_location(argument) {
+                        debugger.add_breakpoint(path, line);
+                    }
+                }
+                ":bt" | ":backtrace" => {
+                    for idx in 0..frames.len() {
+                        print_frame(frame(frames, idx).unwrap(), idx, idx == selected);
+                    }
+                }
+                ":c" | ":continue" => return NixDebugAction::Continue,
+                ":d" => {
+                    if let Some((path, line)) = parse_location(argument) {
+                        if !debugger.remove_breakpoint(&path, line) {
+                            eprintln!("There is no breakpoint at {}:{line}", path.display());
+                        }
+                    }
+                }
+                ":down" | ":up" | ":st" => {
+                    let idx = match command {
+                        ":down" => selected.checked_sub(1),
+                        ":up" => Some(selected + 1),
+                        _ => argument.parse().ok(),
+                    };
+
+                    match idx.and_then(|idx| Some((idx, frame(frames, idx)?))) {
+                        Some((idx, frame)) => {
+                            selected = idx;
+                            print_frame(frame, idx, true);
+                        }
+                        None => eprintln!("There is no such frame"),
+                    }
+                }
+                ":env" => match frame(frames, selected) {
+                    Some(frame) => print_env(frame),
+                    None => eprintln!("There are no frames"),
+                },
+                ":q" | ":quit" => std::process::exit(1),
+                ":s" | ":step" => return NixDebugAction::Step,
+                _ => eprintln!("Unknown command '{command}', type :? for help"),
+            }
+        }
+    }
+}
+
+/// Frame `idx`, starting from the innermost one
+fn frame(frames: &[NixFrame], idx: usize) -> Option<&NixFrame> {
+    frames.iter().rev().nth(idx)
+}
+
+fn print_frame(frame: &NixFrame, idx: usize, selected: bool) {
+    let span = &frame.backtrace.0;
+    let marker = if selected { '*' } else { ' ' };
+
+    println!(
+        "{marker}{idx}: {} {}:{}:{}",
+        frame.backtrace.2,
+        span.file.path.display(),
+        span.start.0,
+        span.start.1 + 1
+    );
+
+    if let Some(line) = span.file.content.lines().nth(span.start.0 - 1) {
+        println!("      {}", line.trim_end());
+    }
+}
+
+/// Prints the variables of every level of the scope, without the globals
+fn print_env(frame: &NixFrame) {
+    let mut scope = Some(&frame.scope);
+    let mut level = 0;
+
+    while let Some(current) = scope.filter(|scope| scope.parent.is_some()) {
+        let variables = current.variables.borrow();
+        let names = variables
+            .as_attr_set()
+            .map(|set| set.keys().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        if !names.is_empty() {
+            println!("Level {level}: {}", names.join(" "));
+        }
+
+        scope = current.parent.as_ref();
+        level += 1;
+    }
+}
+
+fn eval(frame: &NixFrame, expr: &str) {
+    let path = env::current_dir().unwrap().join("«debugger»");
+
+    let printed = FileScope::eval_in_scope(path, expr.to_owned(), &frame.scope).and_then(
+        |(backtrace, value)| {
+            NixPrinter::pretty().print_limited(
+                &LazyNixValue::Concrete(value).wrap_var(),
+                Some(1),
+                None,
+                &backtrace,
+            )
+        },
+    );
+
+    match printed {
+        Ok(printed) => println!("{printed}"),
+        Err(err) => eprintln!("{err}"),
+    }
+}
+
+/// Parses `file:line`, the file must exist
+pub fn parse_location(location: &str) -> Option<(PathBuf, usize)> {
+    let Some((path, line)) = location
+        .rsplit_once(':')
+        .and_then(|(path, line)| Some((path, line.parse().ok()?)))
+    else {
+        eprintln!("Invalid location '{location}', expected <file>:<line>");
+        return None;
+    };
+
+    if !Path::new(path).exists() {
+        eprintln!("File '{path}' doesn't exist");
+        return None;
+    }
+
+    Some((FileScope::normalize_path(path), line))
+}
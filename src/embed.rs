@@ -0,0 +1,90 @@
+//! Nix expressions embedded in Rust with [`nix!`](crate::nix)
+
+use std::fmt;
+use std::path::Path;
+
+use crate::{
+    FileScope, FromNixExpr, LazyNixValue, NixAttrSet, NixResult, NixValue, NixValueWrapped, NixVar,
+};
+
+/// Expression created by [`nix!`](crate::nix), its syntax is checked at
+/// compile time but it's only evaluated with [`NixExpr::eval`]
+#[derive(Clone, Debug)]
+pub struct NixExpr {
+    source: &'static str,
+    location: &'static str,
+    splices: Vec<(&'static str, NixVar)>,
+}
+
+impl NixExpr {
+    #[doc(hidden)]
+    pub fn new(
+        source: &'static str,
+        location: &'static str,
+        splices: Vec<(&'static str, NixValue)>,
+    ) -> Self {
+        let splices = splices
+            .into_iter()
+            .map(|(name, value)| (name, value.wrap_var()))
+            .collect();
+
+        Self {
+            source,
+            location,
+            splices,
+        }
+    }
+
+    /// Nix source of the expression, with `#{...}` replaced by variables
+    pub fn source(&self) -> &'static str {
+        self.source
+    }
+
+    /// Rust file, line and column of the `nix!` invocation
+    pub fn location(&self) -> &'static str {
+        self.location
+    }
+
+    /// Evaluates the expression, relative paths are resolved from the current
+    /// directory
+    pub fn eval(&self) -> NixResult<NixValueWrapped> {
+        self.eval_in(std::env::current_dir().unwrap())
+    }
+
+    /// Evaluates the expression, relative paths are resolved from `base_dir`
+    pub fn eval_in(&self, base_dir: impl AsRef<Path>) -> NixResult<NixValueWrapped> {
+        self.eval_as_in(base_dir)
+    }
+
+    /// Evaluates the expression and converts it with [`FromNixExpr`]
+    pub fn eval_as<T: FromNixExpr>(&self) -> NixResult<T> {
+        self.eval_as_in(std::env::current_dir().unwrap())
+    }
+
+    /// Like [`NixExpr::eval_as`], relative paths are resolved from `base_dir`
+    pub fn eval_as_in<T: FromNixExpr>(&self, base_dir: impl AsRef<Path>) -> NixResult<T> {
+        // Without the directories of the Rust file, the parent of the path is
+        // used to resolve relative paths
+        let location = Path::new(self.location).file_name().unwrap();
+        let path = base_dir
+            .as_ref()
+            .join(format!("«nix! {}»", location.to_string_lossy()));
+
+        let variables = self
+            .splices
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect::<NixAttrSet>();
+
+        let (backtrace, value) =
+            FileScope::eval_with_variables(path, self.source.to_owned(), variables)?;
+
+        T::from_nix_expr(&backtrace, LazyNixValue::Concrete(value).wrap_var())
+    }
+}
+
+impl fmt::Display for NixExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.source.trim())
+    }
+}
@@ -8,7 +8,7 @@ use crate::result::{NixBacktrace, NixSpan};
 use crate::value::{NixLambda, NixList};
 use crate::{
     LazyNixValue, NixAttrSet, NixBacktraceKind, NixError, NixLabel, NixLabelKind, NixLabelMessage,
-    NixLambdaParam, NixResult, NixValue, NixValueWrapped, NixVar, Scope,
+    NixLambdaParam, NixResult, NixSettings, NixValue, NixValueWrapped, NixVar, Scope,
 };
 
 impl Scope {
@@ -38,7 +38,8 @@ impl Scope {
             self.clone().new_child(),
             attr_value,
         )
-        .wrap_var();
+        .wrap_var()
+        .with_position(NixSpan::from_ast_node(&self.file, &last_attr_path));
 
         let mut target = target.borrow_mut();
         let set = target.as_attr_set_mut().unwrap();
@@ -116,13 +117,21 @@ impl Scope {
                             )
                         };
 
-                        out.borrow_mut()
-                            .as_attr_set_mut()
-                            .unwrap()
-                            .insert(attr, value.wrap_var());
+                        out.borrow_mut().as_attr_set_mut().unwrap().insert(
+                            attr,
+                            value
+                                .wrap_var()
+                                .with_position(NixSpan::from_ast_node(&self.file, &attr_node)),
+                        );
                     } else {
+                        // In a `let` or a `rec` set, `inherit x;` is the `x`
+                        // of the outer scope instead of itself
+                        let scope = match &self.parent {
+                            Some(parent) if Rc::ptr_eq(&out, &self.variables) => parent.clone(),
+                            _ => self.clone(),
+                        };
+
                         let value = {
-                            let scope = self.clone();
                             let attr = attr.clone();
                             let attr_node = attr_node.clone();
                             let file = self.file.clone();
@@ -146,10 +155,12 @@ impl Scope {
                             )
                         };
 
-                        out.borrow_mut()
-                            .as_attr_set_mut()
-                            .unwrap()
-                            .insert(attr, value.wrap_var());
+                        out.borrow_mut().as_attr_set_mut().unwrap().insert(
+                            attr,
+                            value
+                                .wrap_var()
+                                .with_position(NixSpan::from_ast_node(&self.file, &attr_node)),
+                        );
                     }
                 }
 
@@ -182,8 +193,18 @@ impl Scope {
         node: ast::Expr,
     ) -> NixResult<NixVar> {
         let backtrace = &backtrace.visit(&self.file, &node);
-
-        match node {
+        // A single access to the settings, it's done for every expression
+        let (_guard, debugger) = NixSettings::with(|settings| {
+            settings.interrupt.check_token(backtrace)?;
+            let guard = settings.limits.enter(backtrace)?;
+
+            NixResult::Ok((guard, settings.debugger.clone()))
+        })?;
+        let _frame = debugger
+            .as_ref()
+            .and_then(|debugger| debugger.enter(self, backtrace));
+
+        let result = match node {
             ast::Expr::Apply(node) => self.visit_apply(backtrace, node),
             ast::Expr::Assert(node) => self.visit_assert(backtrace, node),
             ast::Expr::AttrSet(node) => self.visit_attrset(backtrace, node),
@@ -204,7 +225,13 @@ impl Scope {
             ast::Expr::Str(node) => self.visit_str(backtrace, node),
             ast::Expr::UnaryOp(node) => self.visit_unaryop(backtrace, node),
             ast::Expr::With(node) => self.visit_with(backtrace, node),
+        };
+
+        if let Some(debugger) = &debugger {
+            debugger.leave(&result);
         }
+
+        result
     }
 
     pub fn visit_apply(
@@ -222,7 +249,13 @@ impl Scope {
             .and_then(|l| {
                 let backtrace = &backtrace.change_span((&self.file, &node.argument().unwrap()));
 
-                let argument = self.visit_expr(backtrace, node.argument().unwrap())?;
+                // The argument is only evaluated when the function uses it
+                let argument = LazyNixValue::Pending(
+                    backtrace.clone(),
+                    self.clone(),
+                    node.argument().unwrap(),
+                )
+                .wrap_var();
                 l.call(backtrace, argument)
             })
     }
@@ -707,10 +740,11 @@ impl Scope {
     ) -> NixResult<NixVar> {
         let mut content = String::new();
 
-        for part in node.parts() {
+        // Escapes and the indentation of `''` strings are removed
+        for part in node.normalized_parts() {
             match part {
                 ast::InterpolPart::Literal(str) => {
-                    content += str.syntax().text();
+                    content += &str;
                 }
                 ast::InterpolPart::Interpolation(interpol) => {
                     content += &self
@@ -0,0 +1,31 @@
+// Lets the derive macros refer to `::nix_compiler` inside of this crate
+extern crate self as nix_compiler;
+
+pub mod builtins;
+mod embed;
+mod expr;
+pub mod flake;
+pub mod plugin;
+mod result;
+mod scope;
+mod settings;
+mod value;
+
+pub use builtins::{
+    FromNixExpr, IntoNix, NixBuiltin, NixBuiltinArgument, NixBuiltinInfo, NixHostBuiltin,
+};
+pub use embed::NixExpr;
+pub use nix_macros::{nix, FromNix, IntoNix};
+pub use result::{
+    NixBacktrace, NixBacktraceKind, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult,
+    NixSpan,
+};
+pub use scope::{FileScope, Scope};
+pub use settings::{
+    NixDebugAction, NixDebugHandler, NixDebugger, NixFrame, NixInterrupt, NixLimits, NixSettings,
+    NixStopReason,
+};
+pub use value::{
+    LazyNixValue, NixAttrSet, NixLambda, NixLambdaParam, NixList, NixPrinter, NixValue,
+    NixValueWrapped, NixVar,
+};
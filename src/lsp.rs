@@ -0,0 +1,658 @@
+//! `nix-compiler lsp`, a language server speaking LSP over stdio.
+//!
+//! Diagnostics and go-to-definition only use the static scope of the
+//! document, hover and completion evaluate the expressions that don't depend
+//! on function arguments or `with`.
+
+mod analysis;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use nix_compiler::{
+    FileScope, LazyNixValue, NixAttrSet, NixBacktrace, NixError, NixLambda, NixLimits, NixPrinter,
+    NixSettings, NixValue, NixValueWrapped, Scope,
+};
+use rnix::parser::ParseError;
+use rnix::{TextRange, TextSize};
+use rowan::ast::AstNode;
+use serde_json::{json, Value};
+
+use analysis::{Analysis, BindingKind, Target};
+
+/// Error codes of JSON-RPC and LSP
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+
+/// `CompletionItemKind` of LSP
+const COMPLETION_FIELD: u8 = 5;
+const COMPLETION_VARIABLE: u8 = 6;
+
+/// `DiagnosticSeverity` of LSP
+const SEVERITY_ERROR: u8 = 1;
+
+pub fn run() {
+    // Only used to get the global variables
+    let scope = Scope::new_with_builtins(Rc::new(FileScope {
+        path: PathBuf::from("«lsp»"),
+        content: String::new(),
+    }));
+    let globals = scope.parent.as_ref().unwrap().variables.borrow();
+    let globals = globals.as_attr_set().unwrap();
+
+    let mut server = Server {
+        documents: HashMap::new(),
+        globals: globals.keys().cloned().collect(),
+        is_initialized: false,
+        is_shutdown: false,
+    };
+
+    let mut stdin = io::stdin().lock();
+
+    loop {
+        let message = match read_message(&mut stdin) {
+            Ok(Some(message)) => message,
+            // The client exited without `exit`
+            Ok(None) => std::process::exit(1),
+            Err(err) => {
+                eprintln!("Cannot read a message: {err}");
+                std::process::exit(1);
+            }
+        };
+
+        match serde_json::from_slice(&message) {
+            Ok(message) => server.handle(message),
+            Err(err) => send(&json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": PARSE_ERROR, "message": err.to_string() },
+            })),
+        }
+    }
+}
+
+/// Body of the next message, `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Missing Content-Length header",
+        ));
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    Ok(Some(body))
+}
+
+fn send(message: &Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+
+    let sent =
+        write!(stdout, "Content-Length: {}\r\n\r\n{body}", body.len()).and_then(|_| stdout.flush());
+
+    if let Err(err) = sent {
+        eprintln!("Cannot send a message: {err}");
+        std::process::exit(1);
+    }
+}
+
+struct Server {
+    /// Open documents, by URI
+    documents: HashMap<String, Document>,
+    /// Names of the global variables, like `builtins` and `map`
+    globals: Vec<String>,
+    is_initialized: bool,
+    /// After `shutdown`, only `exit` is accepted
+    is_shutdown: bool,
+}
+
+struct Document {
+    uri: String,
+    path: PathBuf,
+    text: String,
+    /// Offsets where every line starts
+    lines: Vec<usize>,
+    analysis: Analysis,
+}
+
+/// Values of the bindings of a document evaluated during a request, `None`
+/// while it's being evaluated or if it cannot be evaluated
+type Evaluated = HashMap<usize, Option<(NixBacktrace, NixValueWrapped)>>;
+
+impl Server {
+    fn handle(&mut self, message: Value) {
+        let params = message.get("params").unwrap_or(&Value::Null);
+
+        match (message.get("id"), message["method"].as_str()) {
+            (Some(id), Some(method)) => {
+                let response = match self.request(method, params) {
+                    Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                    Err((code, message)) => json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": code, "message": message },
+                    }),
+                };
+
+                send(&response);
+            }
+            (None, Some(method)) => self.notification(method, params),
+            // Responses, the server doesn't send requests
+            _ => {}
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if method == "initialize" {
+            self.is_initialized = true;
+
+            return Ok(json!({
+                "capabilities": {
+                    // The whole document is sent on every change
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": { "triggerCharacters": ["."] },
+                },
+                "serverInfo": {
+                    "name": "nix-compiler",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }));
+        }
+
+        if !self.is_initialized {
+            return Err((
+                SERVER_NOT_INITIALIZED,
+                "The server is not initialized".to_owned(),
+            ));
+        }
+
+        if self.is_shutdown {
+            return Err((INVALID_REQUEST, "The server is shutting down".to_owned()));
+        }
+
+        let position = || {
+            self.document_position(params).ok_or_else(|| {
+                (
+                    INVALID_PARAMS,
+                    "Expected the position of an open document".to_owned(),
+                )
+            })
+        };
+
+        let result = match method {
+            "shutdown" => {
+                self.is_shutdown = true;
+                None
+            }
+            "textDocument/hover" => {
+                let (document, offset) = position()?;
+                self.hover(document, offset)
+            }
+            "textDocument/definition" => {
+                let (document, offset) = position()?;
+                definition(document, offset)
+            }
+            "textDocument/completion" => {
+                let (document, offset) = position()?;
+                Some(self.completion(document, offset))
+            }
+            _ => {
+                return Err((METHOD_NOT_FOUND, format!("Unknown method '{method}'")));
+            }
+        };
+
+        Ok(result.unwrap_or(Value::Null))
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+        let uri = params["textDocument"]["uri"].as_str().map(str::to_owned);
+
+        match method {
+            "exit" => std::process::exit(if self.is_shutdown { 0 } else { 1 }),
+            "textDocument/didOpen" => {
+                if let (Some(uri), Some(text)) = (uri, params["textDocument"]["text"].as_str()) {
+                    self.open(uri, text.to_owned());
+                }
+            }
+            "textDocument/didChange" => {
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    // Other documents may import it
+                    FileScope::clear_cache();
+                    self.open(uri, text.to_owned());
+                }
+            }
+            "textDocument/didSave" => FileScope::clear_cache(),
+            "textDocument/didClose" => {
+                if let Some(uri) = uri {
+                    self.documents.remove(&uri);
+
+                    send(&json!({
+                        "jsonrpc": "2.0",
+                        "method": "textDocument/publishDiagnostics",
+                        "params": { "uri": uri, "diagnostics": [] },
+                    }));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Analyzes the new text of a document and publishes its diagnostics
+    fn open(&mut self, uri: String, text: String) {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+
+        let document = Document {
+            path: uri_to_path(&uri),
+            analysis: Analysis::new(&text, &self.globals),
+            uri: uri.clone(),
+            text,
+            lines,
+        };
+
+        let diagnostics = document.diagnostics();
+        self.documents.insert(uri.clone(), document);
+
+        send(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }));
+    }
+
+    /// Document and offset of `TextDocumentPositionParams`
+    fn document_position(&self, params: &Value) -> Option<(&Document, TextSize)> {
+        let document = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+
+        let position = &params["position"];
+        let offset = document.offset(
+            position["line"].as_u64()? as usize,
+            position["character"].as_u64()? as usize,
+        );
+
+        Some((document, offset))
+    }
+
+    fn hover(&self, document: &Document, offset: TextSize) -> Option<Value> {
+        let analysis = &document.analysis;
+        let mut evaluated = Evaluated::new();
+
+        let (range, mut contents, value) = if let Some(reference) = analysis.reference_at(offset) {
+            let contents = match reference.target {
+                Target::Binding(idx) => {
+                    let binding = &analysis.bindings[idx];
+                    format!("`{}`: {}", binding.name, binding.kind.describe())
+                }
+                Target::Global => format!("`{}`: global variable", reference.name),
+                Target::With => format!("`{}`: variable of a `with`", reference.name),
+                Target::Missing => return None,
+            };
+
+            let value = analysis
+                .expr_at(reference.range.start())
+                .and_then(|expr| document.eval_expr(&expr, &mut evaluated));
+
+            (reference.range, contents, value)
+        } else if let Some(idx) = analysis.binding_at(offset) {
+            let binding = &analysis.bindings[idx];
+            let contents = format!("`{}`: {}", binding.name, binding.kind.describe());
+            let value = document.eval_binding(idx, &mut evaluated);
+
+            (binding.range, contents, value)
+        } else {
+            let expr = analysis.expr_at(offset)?;
+            let value = document.eval_expr(&expr, &mut evaluated)?;
+
+            (expr.syntax().text_range(), String::new(), Some(value))
+        };
+
+        if let Some((backtrace, value)) = value {
+            if !contents.is_empty() {
+                contents.push_str("\n\n");
+            }
+
+            contents.push_str(&describe_value(&backtrace, &value));
+        }
+
+        Some(json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": document.range(range),
+        }))
+    }
+
+    fn completion(&self, document: &Document, offset: TextSize) -> Value {
+        let before = &document.text[..usize::from(offset)];
+
+        let start = before
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-' | '.')))
+            .map_or(0, |idx| idx + 1);
+        let word = &before[start..];
+
+        let mut items = match word.rsplit_once('.') {
+            Some((path, prefix)) => document
+                .attr_names(offset, path)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| (name, COMPLETION_FIELD))
+                .collect::<Vec<_>>(),
+            None => {
+                let analysis = &document.analysis;
+
+                analysis
+                    .visible_bindings(offset)
+                    .into_iter()
+                    .map(|idx| analysis.bindings[idx].name.clone())
+                    .chain(self.globals.iter().cloned())
+                    .filter(|name| name.starts_with(word))
+                    .map(|name| (name, COMPLETION_VARIABLE))
+                    .collect()
+            }
+        };
+
+        items.sort();
+        items.dedup_by(|a, b| a.0 == b.0);
+
+        let items = items
+            .into_iter()
+            .map(|(label, kind)| json!({ "label": label, "kind": kind }))
+            .collect::<Vec<_>>();
+
+        json!({ "isIncomplete": false, "items": items })
+    }
+}
+
+fn definition(document: &Document, offset: TextSize) -> Option<Value> {
+    let analysis = &document.analysis;
+
+    let Target::Binding(idx) = analysis.reference_at(offset)?.target else {
+        return None;
+    };
+
+    Some(json!({
+        "uri": document.uri,
+        "range": document.range(analysis.bindings[idx].range),
+    }))
+}
+
+impl Document {
+    fn diagnostics(&self) -> Vec<Value> {
+        let file = Rc::new(FileScope {
+            path: self.path.clone(),
+            content: self.text.clone(),
+        });
+
+        let parse_error = self.analysis.error.iter().map(|error| {
+            let range = match error {
+                ParseError::Unexpected(range)
+                | ParseError::UnexpectedExtra(range)
+                | ParseError::UnexpectedWanted(_, range, _)
+                | ParseError::UnexpectedDoubleBind(range)
+                | ParseError::DuplicatedArgs(range, _) => *range,
+                _ => TextRange::empty(TextSize::of(self.text.as_str())),
+            };
+
+            let message = NixError::from_parse_error(&file, error.clone()).message;
+
+            (range, message)
+        });
+
+        let scope_errors = self
+            .analysis
+            .references
+            .iter()
+            .filter(|reference| reference.target == Target::Missing)
+            .map(|reference| {
+                let message = format!("Variable '{}' not found", reference.name);
+                (reference.range, message)
+            });
+
+        parse_error
+            .chain(scope_errors)
+            .map(|(range, message)| {
+                json!({
+                    "range": self.range(range),
+                    "severity": SEVERITY_ERROR,
+                    "source": "nix-compiler",
+                    "message": message,
+                })
+            })
+            .collect()
+    }
+
+    /// LSP position of `offset`, the columns are in UTF-16 code units
+    fn position(&self, offset: TextSize) -> Value {
+        let offset = usize::from(offset).min(self.text.len());
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.lines[line]..offset].encode_utf16().count();
+
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, range: TextRange) -> Value {
+        json!({
+            "start": self.position(range.start()),
+            "end": self.position(range.end()),
+        })
+    }
+
+    fn offset(&self, line: usize, character: usize) -> TextSize {
+        let Some(&start) = self.lines.get(line) else {
+            return TextSize::of(self.text.as_str());
+        };
+
+        let mut offset = start;
+        let mut units = 0;
+
+        for c in self.text[start..].chars() {
+            if units >= character || c == '\n' {
+                break;
+            }
+
+            units += c.len_utf16();
+            offset += c.len_utf8();
+        }
+
+        TextSize::new(offset as u32)
+    }
+
+    /// Evaluates `expr` with the values of the variables it uses, if they
+    /// don't depend on function arguments or `with`
+    fn eval_expr(
+        &self,
+        expr: &rnix::ast::Expr,
+        evaluated: &mut Evaluated,
+    ) -> Option<(NixBacktrace, NixValueWrapped)> {
+        let analysis = &self.analysis;
+        let mut variables = NixAttrSet::new();
+
+        for reference in analysis.free_references(expr.syntax().text_range()) {
+            match reference.target {
+                Target::Global => {}
+                Target::With | Target::Missing => return None,
+                Target::Binding(idx) => {
+                    if variables.contains_key(&reference.name) {
+                        continue;
+                    }
+
+                    let (_, value) = self.eval_binding(idx, evaluated)?;
+                    let value = LazyNixValue::Concrete(value).wrap_var();
+
+                    variables.insert(reference.name.clone(), value);
+                }
+            }
+        }
+
+        self.eval_source(expr.syntax().text().to_string(), variables)
+    }
+
+    fn eval_binding(
+        &self,
+        idx: usize,
+        evaluated: &mut Evaluated,
+    ) -> Option<(NixBacktrace, NixValueWrapped)> {
+        if let Some(value) = evaluated.get(&idx) {
+            return value.clone();
+        }
+
+        let binding = &self.analysis.bindings[idx];
+
+        // Arguments are only known when the function is called
+        if binding.kind == BindingKind::Argument {
+            return None;
+        }
+
+        // Also stops recursive bindings, like `let x = [ x ]; in`
+        evaluated.insert(idx, None);
+
+        let value = binding
+            .value
+            .as_ref()
+            .and_then(|value| self.eval_expr(value, evaluated));
+
+        evaluated.insert(idx, value.clone());
+
+        value
+    }
+
+    /// Evaluates `source` as if it was in the directory of the document, with
+    /// the limits of the settings
+    fn eval_source(
+        &self,
+        source: String,
+        variables: NixAttrSet,
+    ) -> Option<(NixBacktrace, NixValueWrapped)> {
+        NixSettings::current().interrupt.reset();
+        NixLimits::reset();
+
+        let path = self.path.with_file_name("«lsp»");
+
+        // The panic is already printed to stderr
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            FileScope::eval_with_variables(path, source, variables).ok()
+        }))
+        .ok()
+        .flatten()
+    }
+
+    /// Names of the set at `path`, like `builtins` or `pkgs.lib`
+    fn attr_names(&self, offset: TextSize, path: &str) -> Option<Vec<String>> {
+        let analysis = &self.analysis;
+        let mut evaluated = Evaluated::new();
+        let mut components = path.split('.');
+
+        let name = components.next()?;
+
+        let binding = analysis
+            .visible_bindings(offset)
+            .into_iter()
+            .find(|&idx| analysis.bindings[idx].name == name);
+
+        let (backtrace, mut value) = match binding {
+            Some(idx) => self.eval_binding(idx, &mut evaluated)?,
+            None => self.eval_source(name.to_owned(), NixAttrSet::new())?,
+        };
+
+        for name in components {
+            let var = value.borrow().as_attr_set()?.get(name)?.clone();
+            value = var.resolve(&backtrace).ok()?;
+        }
+
+        let names = value.borrow().as_attr_set()?.keys().cloned().collect();
+
+        Some(names)
+    }
+}
+
+/// Markdown of a value, with its first level evaluated
+fn describe_value(backtrace: &NixBacktrace, value: &NixValueWrapped) -> String {
+    let ty = value.borrow().as_type();
+
+    match &*value.borrow() {
+        NixValue::Lambda(NixLambda::Builtin(builtin)) => {
+            return match builtin.doc() {
+                "" => format!("```nix\nbuiltins.{}\n```", builtin.signature()),
+                doc => format!("```nix\nbuiltins.{}\n```\n\n{doc}", builtin.signature()),
+            };
+        }
+        // The position would be in the evaluated snippet, not in the document
+        NixValue::Lambda(_) => return format!("Type: `{ty}`"),
+        _ => {}
+    }
+
+    let var = LazyNixValue::Concrete(value.clone()).wrap_var();
+    let printed = NixPrinter::pretty()
+        .print_limited(&var, Some(1), Some(20), backtrace)
+        .unwrap_or_else(|_| NixPrinter::pretty().print(&value.borrow()));
+
+    format!("```nix\n{printed}\n```\n\nType: `{ty}`")
+}
+
+/// Path of a `file://` URI, other URIs are kept as they are
+fn uri_to_path(uri: &str) -> PathBuf {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return PathBuf::from(uri);
+    };
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| hex::decode(hex).ok());
+
+        match decoded {
+            Some(decoded) => {
+                bytes.extend(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -0,0 +1,474 @@
+//! Static scope of a document, without evaluating it. Like [`Scope`] during
+//! the evaluation, every `let`, function and `rec` set adds a level with its
+//! variables, and the variables of `with` are only known at runtime.
+//!
+//! [`Scope`]: nix_compiler::Scope
+
+use std::collections::{HashMap, HashSet};
+
+use rnix::ast::{self, HasEntry};
+use rnix::parser::ParseError;
+use rnix::{Root, SyntaxKind, SyntaxNode, TextRange, TextSize};
+use rowan::ast::AstNode;
+
+/// How a variable is defined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    /// `let x = ...; in`
+    Let,
+    /// `x: ...` or `{ x }: ...`
+    Argument,
+    /// `rec { x = ...; }`
+    RecAttr,
+    /// `inherit x;` or `inherit (set) x;` in a `let` or a `rec` set
+    Inherit,
+}
+
+impl BindingKind {
+    pub fn describe(self) -> &'static str {
+        match self {
+            BindingKind::Let => "let binding",
+            BindingKind::Argument => "function argument",
+            BindingKind::RecAttr => "attribute of a recursive set",
+            BindingKind::Inherit => "inherited variable",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Binding {
+    pub name: String,
+    pub kind: BindingKind,
+    /// Name where it's defined
+    pub range: TextRange,
+    /// Expression of `x = <value>;`, or the outer variable of `inherit x;`
+    pub value: Option<ast::Expr>,
+}
+
+/// What a variable refers to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// Index in [`Analysis::bindings`]
+    Binding(usize),
+    /// Like `builtins` or `map`
+    Global,
+    /// Not defined, but inside of a `with` that may define it
+    With,
+    /// Not defined anywhere
+    Missing,
+}
+
+#[derive(Debug)]
+pub struct Reference {
+    pub name: String,
+    pub range: TextRange,
+    pub target: Target,
+}
+
+#[derive(Debug)]
+pub struct Analysis {
+    pub root: Root,
+    /// First syntax error, the next ones are usually caused by it
+    pub error: Option<ParseError>,
+    pub bindings: Vec<Binding>,
+    /// Variables used, in the order they appear
+    pub references: Vec<Reference>,
+    /// Levels of the scope, the range where their variables are visible
+    pub scopes: Vec<(TextRange, Vec<usize>)>,
+}
+
+impl Analysis {
+    /// `globals` are the names of the outermost level, like `builtins`
+    pub fn new(text: &str, globals: &[String]) -> Self {
+        let parse = Root::parse(text);
+        let root = parse.tree();
+
+        let mut walker = Walker {
+            globals,
+            analysis: Analysis {
+                root: root.clone(),
+                error: parse.errors().first().cloned(),
+                bindings: Vec::new(),
+                references: Vec::new(),
+                scopes: Vec::new(),
+            },
+            stack: Vec::new(),
+            with_depth: 0,
+        };
+
+        walker.visit(root.syntax());
+        walker.analysis
+    }
+
+    pub fn reference_at(&self, offset: TextSize) -> Option<&Reference> {
+        self.references
+            .iter()
+            .find(|reference| reference.range.contains_inclusive(offset))
+    }
+
+    pub fn binding_at(&self, offset: TextSize) -> Option<usize> {
+        self.bindings
+            .iter()
+            .position(|binding| binding.range.contains_inclusive(offset))
+    }
+
+    /// Variables visible at `offset`, the inner ones first
+    pub fn visible_bindings(&self, offset: TextSize) -> Vec<usize> {
+        let mut scopes = self
+            .scopes
+            .iter()
+            .filter(|(range, _)| range.contains_inclusive(offset))
+            .collect::<Vec<_>>();
+
+        scopes.sort_by_key(|(range, _)| range.len());
+
+        scopes
+            .into_iter()
+            .flat_map(|(_, bindings)| bindings.iter().copied())
+            .collect()
+    }
+
+    /// Variables used in `range` and defined outside of it
+    pub fn free_references(&self, range: TextRange) -> impl Iterator<Item = &Reference> {
+        self.references.iter().filter(move |reference| {
+            range.contains_range(reference.range)
+                && match reference.target {
+                    Target::Binding(idx) => !range.contains_range(self.bindings[idx].range),
+                    _ => true,
+                }
+        })
+    }
+
+    /// Innermost expression at `offset`. Names of attributes are the whole
+    /// `set.name`, or the value in `name = value;`.
+    pub fn expr_at(&self, offset: TextSize) -> Option<ast::Expr> {
+        let token = self
+            .root
+            .syntax()
+            .token_at_offset(offset)
+            .find(|token| !token.kind().is_trivia())?;
+
+        for node in token.parent_ancestors() {
+            if node.kind() == SyntaxKind::NODE_ATTRPATH {
+                let parent = node.parent()?;
+
+                return match ast::AttrpathValue::cast(parent.clone()) {
+                    Some(attrpath_value) => attrpath_value.value(),
+                    None => ast::Expr::cast(parent),
+                };
+            }
+
+            if node.kind() == SyntaxKind::NODE_ROOT {
+                return None;
+            }
+
+            if let Some(expr) = ast::Expr::cast(node) {
+                // Names in an attribute path aren't variables
+                if expr.syntax().parent().map(|parent| parent.kind())
+                    != Some(SyntaxKind::NODE_ATTRPATH)
+                {
+                    return Some(expr);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+struct Walker<'a> {
+    globals: &'a [String],
+    analysis: Analysis,
+    /// Levels of the scope, the innermost last
+    stack: Vec<HashMap<String, usize>>,
+    /// Amount of `with` around the current expression
+    with_depth: usize,
+}
+
+impl Walker<'_> {
+    fn visit(&mut self, node: &SyntaxNode) {
+        match node.kind() {
+            SyntaxKind::NODE_IDENT => {
+                if let Some(ident) = ast::Ident::cast(node.clone()) {
+                    self.reference(&ident);
+                }
+            }
+            SyntaxKind::NODE_LET_IN => {
+                let let_in = ast::LetIn::cast(node.clone()).unwrap();
+                self.visit_recursive(node, &let_in, BindingKind::Let);
+
+                if let Some(body) = let_in.body() {
+                    self.visit(body.syntax());
+                }
+
+                self.stack.pop();
+            }
+            SyntaxKind::NODE_LEGACY_LET => {
+                let legacy_let = ast::LegacyLet::cast(node.clone()).unwrap();
+                self.visit_recursive(node, &legacy_let, BindingKind::RecAttr);
+                self.stack.pop();
+            }
+            SyntaxKind::NODE_ATTR_SET => {
+                let set = ast::AttrSet::cast(node.clone()).unwrap();
+
+                if set.rec_token().is_some() {
+                    self.visit_recursive(node, &set, BindingKind::RecAttr);
+                    self.stack.pop();
+                } else {
+                    self.visit_entries(&set);
+                }
+            }
+            SyntaxKind::NODE_LAMBDA => {
+                let lambda = ast::Lambda::cast(node.clone()).unwrap();
+                let mut variables = Vec::new();
+                let mut defaults = Vec::new();
+
+                match lambda.param() {
+                    Some(ast::Param::IdentParam(param)) => {
+                        variables.extend(param.ident());
+                    }
+                    Some(ast::Param::Pattern(pattern)) => {
+                        for entry in pattern.pat_entries() {
+                            variables.extend(entry.ident());
+                            defaults.extend(entry.default());
+                        }
+
+                        variables.extend(pattern.pat_bind().and_then(|bind| bind.ident()));
+                    }
+                    None => {}
+                }
+
+                let variables = variables
+                    .into_iter()
+                    .map(|ident| self.define(&ident, BindingKind::Argument, None))
+                    .collect();
+
+                self.push_scope(node, variables);
+
+                for default in defaults {
+                    self.visit(default.syntax());
+                }
+
+                if let Some(body) = lambda.body() {
+                    self.visit(body.syntax());
+                }
+
+                self.stack.pop();
+            }
+            SyntaxKind::NODE_WITH => {
+                let with = ast::With::cast(node.clone()).unwrap();
+
+                if let Some(namespace) = with.namespace() {
+                    self.visit(namespace.syntax());
+                }
+
+                if let Some(body) = with.body() {
+                    self.with_depth += 1;
+                    self.visit(body.syntax());
+                    self.with_depth -= 1;
+                }
+            }
+            SyntaxKind::NODE_SELECT => {
+                let select = ast::Select::cast(node.clone()).unwrap();
+
+                if let Some(expr) = select.expr() {
+                    self.visit(expr.syntax());
+                }
+                if let Some(attrpath) = select.attrpath() {
+                    self.visit_attrpath(&attrpath);
+                }
+                if let Some(default) = select.default_expr() {
+                    self.visit(default.syntax());
+                }
+            }
+            SyntaxKind::NODE_HAS_ATTR => {
+                let has_attr = ast::HasAttr::cast(node.clone()).unwrap();
+
+                if let Some(expr) = has_attr.expr() {
+                    self.visit(expr.syntax());
+                }
+                if let Some(attrpath) = has_attr.attrpath() {
+                    self.visit_attrpath(&attrpath);
+                }
+            }
+            _ => {
+                for child in node.children() {
+                    self.visit(&child);
+                }
+            }
+        }
+    }
+
+    /// Defines the variables of a `let` or a `rec` set and visits their
+    /// values, the caller pops the new level
+    fn visit_recursive(&mut self, node: &SyntaxNode, entries: &impl HasEntry, kind: BindingKind) {
+        let mut variables = Vec::new();
+        let mut names = HashSet::new();
+
+        for entry in entries.entries() {
+            match entry {
+                ast::Entry::AttrpathValue(attrpath_value) => {
+                    let Some(attrpath) = attrpath_value.attrpath() else {
+                        continue;
+                    };
+                    let mut attrs = attrpath.attrs();
+
+                    // `a.b = 1; a.c = 2;` define `a` once
+                    let Some(ast::Attr::Ident(ident)) = attrs.next() else {
+                        continue;
+                    };
+                    let Some(name) = ident_name(&ident) else {
+                        continue;
+                    };
+
+                    let value = attrpath_value.value().filter(|_| attrs.next().is_none());
+
+                    if names.insert(name) {
+                        variables.push(self.define(&ident, kind, value));
+                    }
+                }
+                ast::Entry::Inherit(inherit) => {
+                    let from = inherit.from();
+
+                    for attr in inherit.attrs() {
+                        let ast::Attr::Ident(ident) = attr else {
+                            continue;
+                        };
+                        let Some(name) = ident_name(&ident) else {
+                            continue;
+                        };
+
+                        // `inherit x;` refers to the `x` of the outer level
+                        let value = if from.is_none() {
+                            self.reference(&ident);
+                            Some(ast::Expr::Ident(ident.clone()))
+                        } else {
+                            None
+                        };
+
+                        if names.insert(name) {
+                            variables.push(self.define(&ident, BindingKind::Inherit, value));
+                        }
+                    }
+                }
+            }
+        }
+
+        self.push_scope(node, variables);
+
+        for entry in entries.entries() {
+            match entry {
+                ast::Entry::AttrpathValue(attrpath_value) => {
+                    if let Some(attrpath) = attrpath_value.attrpath() {
+                        self.visit_attrpath(&attrpath);
+                    }
+                    if let Some(value) = attrpath_value.value() {
+                        self.visit(value.syntax());
+                    }
+                }
+                ast::Entry::Inherit(inherit) => {
+                    if let Some(expr) = inherit.from().and_then(|from| from.expr()) {
+                        self.visit(expr.syntax());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Entries of a set that isn't recursive, they don't define variables
+    fn visit_entries(&mut self, set: &ast::AttrSet) {
+        for entry in set.entries() {
+            match entry {
+                ast::Entry::AttrpathValue(attrpath_value) => {
+                    if let Some(attrpath) = attrpath_value.attrpath() {
+                        self.visit_attrpath(&attrpath);
+                    }
+                    if let Some(value) = attrpath_value.value() {
+                        self.visit(value.syntax());
+                    }
+                }
+                ast::Entry::Inherit(inherit) => match inherit.from() {
+                    Some(from) => {
+                        if let Some(expr) = from.expr() {
+                            self.visit(expr.syntax());
+                        }
+                    }
+                    None => {
+                        for attr in inherit.attrs() {
+                            if let ast::Attr::Ident(ident) = attr {
+                                self.reference(&ident);
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// Names of an attribute path aren't variables, only the expressions in
+    /// `${...}` and strings
+    fn visit_attrpath(&mut self, attrpath: &ast::Attrpath) {
+        for attr in attrpath.attrs() {
+            match attr {
+                ast::Attr::Ident(_) => {}
+                ast::Attr::Dynamic(dynamic) => {
+                    if let Some(expr) = dynamic.expr() {
+                        self.visit(expr.syntax());
+                    }
+                }
+                ast::Attr::Str(string) => self.visit(string.syntax()),
+            }
+        }
+    }
+
+    fn push_scope(&mut self, node: &SyntaxNode, variables: Vec<usize>) {
+        let level = variables
+            .iter()
+            .map(|&idx| (self.analysis.bindings[idx].name.clone(), idx))
+            .collect();
+
+        self.stack.push(level);
+        self.analysis.scopes.push((node.text_range(), variables));
+    }
+
+    fn define(&mut self, ident: &ast::Ident, kind: BindingKind, value: Option<ast::Expr>) -> usize {
+        self.analysis.bindings.push(Binding {
+            name: ident_name(ident).unwrap_or_default(),
+            kind,
+            range: ident.syntax().text_range(),
+            value,
+        });
+
+        self.analysis.bindings.len() - 1
+    }
+
+    fn reference(&mut self, ident: &ast::Ident) {
+        let Some(name) = ident_name(ident) else {
+            return;
+        };
+
+        let binding = self
+            .stack
+            .iter()
+            .rev()
+            .find_map(|level| level.get(&name).copied());
+
+        let target = match binding {
+            Some(idx) => Target::Binding(idx),
+            // Variables of `with` have less priority than the globals
+            None if self.globals.contains(&name) => Target::Global,
+            None if self.with_depth > 0 => Target::With,
+            None => Target::Missing,
+        };
+
+        self.analysis.references.push(Reference {
+            name,
+            range: ident.syntax().text_range(),
+            target,
+        });
+    }
+}
+
+fn ident_name(ident: &ast::Ident) -> Option<String> {
+    Some(ident.ident_token()?.text().to_owned())
+}
@@ -1,36 +1,258 @@
-pub mod builtins;
-mod expr;
-pub mod flake;
-mod result;
-mod scope;
-mod value;
-
-pub use builtins::{NixBuiltin, NixBuiltinInfo};
-pub use result::{
-    NixBacktrace, NixBacktraceKind, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult,
-    NixSpan,
-};
-pub use scope::{FileScope, Scope};
 use std::env;
-pub use value::{LazyNixValue, NixAttrSet, NixLambdaParam, NixValue, NixValueWrapped, NixVar};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+mod debugger;
+mod lsp;
+mod repl;
+
+use nix_compiler::builtins::{to_json, to_xml};
+use nix_compiler::plugin::NixPlugin;
+use nix_compiler::{
+    flake, FileScope, LazyNixValue, NixAttrSet, NixBacktrace, NixDebugger, NixError, NixLabel,
+    NixLabelKind, NixLabelMessage, NixPrinter, NixResult, NixSettings, NixSpan, NixValue,
+    NixValueWrapped,
+};
+
+/// Stack size of the evaluation thread, big enough for [`MAX_DEPTH`] even in
+/// debug builds
+const EVAL_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Default of `--max-depth`, the same as the default `max-call-depth` of Nix.
+/// It's higher than the default of the library, which is for smaller stacks
+const MAX_DEPTH: usize = 10_000;
+
+/// How the result is printed, like the options of `nix eval`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Output {
+    /// Nix syntax
+    Nix,
+    /// JSON, with the rules of `builtins.toJSON`
+    Json,
+    /// A string without quotes
+    Raw,
+    /// The XML format of `builtins.toXML`, with positions
+    Xml,
+}
+
+/// Argument of the function in the file, from `--arg` or `--argstr`
+enum AutoArg {
+    Expr(String),
+    Str(String),
+}
 
 fn main() {
+    let evaluation = std::thread::Builder::new()
+        .name("evaluation".to_owned())
+        .stack_size(EVAL_STACK_SIZE)
+        .spawn(run)
+        .expect("Cannot spawn the evaluation thread");
+
+    if evaluation.join().is_err() {
+        std::process::exit(101);
+    }
+}
+
+fn run() {
     let mut iter = env::args().skip(1).peekable();
 
-    let is_evaluation = iter
-        .peek()
-        .is_some_and(|arg| arg == "-e" || arg == "--eval");
+    let subcommand = iter.next_if(|arg| matches!(arg.as_str(), "repl" | "lsp"));
+
+    let mut settings = NixSettings::default();
+    settings.limits.max_depth = Some(MAX_DEPTH);
+    let mut is_evaluation = false;
+    let mut output = Output::Nix;
+    // Levels of sets and lists and values of each one forced before printing,
+    // the whole result is evaluated by default
+    let mut depth = None;
+    let mut items = None;
+    let mut auto_args = Vec::new();
+    let mut attr_path = None;
+    let mut apply = None;
+
+    while let Some(arg) = iter.next_if(|arg| arg.starts_with('-')) {
+        match arg.as_str() {
+            // Like `nix-instantiate --eval`, printing the value is the only mode
+            "--eval" => {}
+            "-e" | "-E" | "--expr" => {
+                is_evaluation = true;
+                // The expression can start with `-`
+                break;
+            }
+            "-A" | "--attr" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("Missing attribute path for '{arg}'");
+                    std::process::exit(1);
+                };
+
+                attr_path = Some(path);
+            }
+            "--apply" => {
+                let Some(expr) = iter.next() else {
+                    eprintln!("Missing expression for '--apply'");
+                    std::process::exit(1);
+                };
+
+                apply = Some(expr);
+            }
+            "--json" => output = Output::Json,
+            "--raw" => output = Output::Raw,
+            "--xml" => output = Output::Xml,
+            "--strict" => {
+                depth = None;
+                items = None;
+            }
+            "--depth" => depth = Some(parse_limit(&arg, iter.next())),
+            "--items" => items = Some(parse_limit(&arg, iter.next())),
+            "--arg" | "--argstr" => {
+                let (Some(name), Some(value)) = (iter.next(), iter.next()) else {
+                    eprintln!("Missing name and value for '{arg}'");
+                    std::process::exit(1);
+                };
+
+                let value = if arg == "--arg" {
+                    AutoArg::Expr(value)
+                } else {
+                    AutoArg::Str(value)
+                };
+
+                auto_args.push((name, value));
+            }
+            "--debugger" => {
+                settings
+                    .debugger
+                    .get_or_insert_with(|| NixDebugger::new(debugger::Prompt::new()));
+            }
+            "--break" => {
+                let Some(location) = iter.next() else {
+                    eprintln!("Missing location for '--break'");
+                    std::process::exit(1);
+                };
+
+                let Some((path, line)) = debugger::parse_location(&location) else {
+                    std::process::exit(1);
+                };
+
+                settings
+                    .debugger
+                    .get_or_insert_with(|| NixDebugger::new(debugger::Prompt::new()))
+                    .add_breakpoint(path, line);
+            }
+            "--restrict-eval" => settings.restrict_eval = true,
+            "--extra-experimental-features" => {
+                let Some(features) = iter.next() else {
+                    eprintln!("Missing features for '--extra-experimental-features'");
+                    std::process::exit(1);
+                };
+
+                for feature in features.split_whitespace() {
+                    match feature {
+                        "parse-toml-timestamps" => settings.parse_toml_timestamps = true,
+                        _ => {
+                            eprintln!("Unknown experimental feature '{feature}'");
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+            "--allow-path" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("Missing path for '--allow-path'");
+                    std::process::exit(1);
+                };
+
+                settings.allowed_paths.push(path.into());
+            }
+            "--plugin" => {
+                let Some(path) = iter.next() else {
+                    eprintln!("Missing path for '--plugin'");
+                    std::process::exit(1);
+                };
 
-    if is_evaluation {
-        iter.next();
+                let plugin = NixPlugin::load(path).unwrap_or_else(|err| {
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                });
+
+                plugin.register(&mut settings);
+            }
+            "--max-depth" => settings.limits.max_depth = Some(parse_limit(&arg, iter.next())),
+            "--max-thunks" => settings.limits.max_thunks = Some(parse_limit(&arg, iter.next())),
+            "--max-values" => settings.limits.max_values = Some(parse_limit(&arg, iter.next())),
+            "--timeout" => {
+                let secs = parse_limit(&arg, iter.next());
+                settings.limits.timeout = Some(Duration::from_secs(secs as u64));
+            }
+            _ => {
+                eprintln!("Unknown option '{arg}'");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    match subcommand.as_deref() {
+        Some("repl") => {
+            install(settings);
+            repl::run(iter);
+            return;
+        }
+        Some("lsp") => {
+            // Hover and completion evaluate while typing, they cannot hang
+            settings
+                .limits
+                .timeout
+                .get_or_insert(Duration::from_secs(1));
+            install(settings);
+            lsp::run();
+            return;
+        }
+        _ => {}
     }
 
     let Some(arg) = iter.next() else {
-        eprintln!("Usage: nix-compiler <file>");
-        eprintln!("Usage: nix-compiler (--eval | -e) <expr>");
+        eprintln!("Usage: nix-compiler [OPTIONS] <file>");
+        eprintln!("Usage: nix-compiler [OPTIONS] (-e | -E | --expr) <expr>");
+        eprintln!("Usage: nix-compiler repl [OPTIONS] [files]");
+        eprintln!("Usage: nix-compiler lsp [OPTIONS]");
+        eprintln!();
+        eprintln!("Options:");
+        eprintln!("  -A, --attr <path>     Only evaluate the attribute <path>, like 'a.\"b.c\"'");
+        eprintln!("  --apply <expr>        Apply the function <expr> to the result");
+        eprintln!("  --eval                Ignored, like in 'nix-instantiate --eval'");
+        eprintln!("  --json                Print the result as JSON");
+        eprintln!("  --raw                 Print the result, a string, without quotes");
+        eprintln!("  --xml                 Print the result as XML");
+        eprintln!("  --strict              Evaluate the whole result before printing (default)");
+        eprintln!("  --depth <n>           Only evaluate <n> levels of sets and lists");
+        eprintln!("  --items <n>           Only evaluate the first <n> values of sets and lists");
+        eprintln!("  --arg <name> <expr>   Pass <expr> as <name> if the result is a function");
+        eprintln!("  --argstr <name> <str> Pass the string <str> as <name>");
+        eprintln!("  --debugger            Open a prompt on errors and calls to builtins.break");
+        eprintln!("  --break <file>:<line> Open a prompt before evaluating <line>");
+        eprintln!("  --restrict-eval       Only allow reading files inside of the allowed paths");
+        eprintln!("  --allow-path <path>   Allow reading files under <path> in restricted mode");
+        eprintln!("  --plugin <path>       Load the builtins of a plugin (a shared library)");
+        eprintln!("  --extra-experimental-features <features>");
+        eprintln!("                        Enable features like 'parse-toml-timestamps'");
+        eprintln!("  --max-depth <n>       Maximum depth of nested expressions being evaluated");
+        eprintln!("  --max-thunks <n>      Maximum amount of lazy values forced");
+        eprintln!("  --max-values <n>      Maximum amount of values allocated");
+        eprintln!("  --timeout <secs>      Maximum time the evaluation can take");
         return;
     };
 
+    if !is_evaluation
+        && settings
+            .allowed_path(FileScope::normalize_path(&arg))
+            .is_none()
+    {
+        eprintln!("Access to path '{arg}' is forbidden in restricted mode");
+        std::process::exit(1);
+    }
+
+    install(settings);
+
     let is_flake = !is_evaluation && arg.ends_with("flake.nix");
 
     let file = if is_evaluation {
@@ -44,20 +266,277 @@ fn main() {
         std::process::exit(1);
     });
 
+    let mut args = NixAttrSet::new();
+
+    for (name, value) in auto_args {
+        let value = match value {
+            // Only evaluated if the function uses it
+            AutoArg::Expr(expr) => LazyNixValue::Eval(
+                backtrace.clone(),
+                Rc::new(move |_: &NixBacktrace| {
+                    FileScope::repl_file(env::current_dir().unwrap(), expr.clone())
+                        .map(|(_, value)| value)
+                }),
+            )
+            .wrap_var(),
+            AutoArg::Str(string) => NixValue::String(string).wrap_var(),
+        };
+
+        args.insert(name, value);
+    }
+
     let outputs = if is_flake {
         flake::resolve_flake(&backtrace, result).unwrap()
     } else {
         result
     };
 
-    let outputs = LazyNixValue::Concrete(outputs)
-        .wrap_var()
-        .resolve_set(true, &backtrace)
-        .unwrap_or_else(|err| {
-            eprintln!("{err}");
-            std::process::exit(1);
-        });
+    let outputs = select_attr_path(
+        &backtrace,
+        outputs,
+        attr_path.as_deref().unwrap_or_default(),
+        &args,
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    let outputs = match apply {
+        Some(expr) => apply_function(outputs, expr),
+        None => Ok(outputs),
+    };
+
+    let outputs = outputs.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    let is_strict = depth.is_none() && items.is_none();
+
+    let printed = match output {
+        Output::Nix => {
+            let var = LazyNixValue::Concrete(outputs).wrap_var();
+
+            // Like `nix eval`, the whole result must evaluate without errors,
+            // with `--depth` or `--items` they are printed inline like in
+            // `nix repl`
+            let forced = if is_strict {
+                var.resolve_set(true, &backtrace).map(|_| ())
+            } else {
+                Ok(())
+            };
 
-    println!("Result (Expanded): {:#}", outputs.borrow());
-    println!("Result (Minimized): {}", outputs.borrow());
+            forced
+                .and_then(|()| NixPrinter::default().print_limited(&var, depth, items, &backtrace))
+                .map(|printed| printed + "\n")
+        }
+        Output::Json => {
+            let mut out = String::new();
+            to_json(&backtrace, &outputs, &mut out).map(|_| out + "\n")
+        }
+        Output::Raw => to_raw_string(&backtrace, &outputs),
+        Output::Xml => LazyNixValue::Concrete(outputs)
+            .wrap_var()
+            .resolve_limited(depth, items, &backtrace)
+            .and_then(|outputs| to_xml(&backtrace, &outputs, is_strict, true)),
+    };
+
+    let printed = printed.unwrap_or_else(|err| {
+        eprintln!("{err}");
+        std::process::exit(1);
+    });
+
+    print!("{printed}");
+}
+
+/// Installs `settings` and interrupts the evaluation on Ctrl-C
+fn install(settings: NixSettings) {
+    let interrupt = settings.interrupt.clone();
+
+    ctrlc::set_handler(move || {
+        // A second Ctrl-C stops even if the evaluation doesn't check the token
+        if interrupt.is_interrupted() {
+            std::process::exit(130);
+        }
+
+        interrupt.interrupt();
+    })
+    .expect("Cannot set the Ctrl-C handler");
+
+    settings.install();
+}
+
+/// Selects `path` of `-A`, like `a.b."c.d"` or `list.0`. Only the values in
+/// the path are evaluated, and the functions are called with `args` like the
+/// result of the file.
+fn select_attr_path(
+    backtrace: &NixBacktrace,
+    mut value: NixValueWrapped,
+    path: &str,
+    args: &NixAttrSet,
+) -> NixResult {
+    if path.is_empty() {
+        return NixValue::auto_call(&value, backtrace, args);
+    }
+
+    // The errors point to the path in a virtual file
+    let file = Rc::new(FileScope {
+        path: PathBuf::from("«attr-path»"),
+        content: path.to_owned(),
+    });
+
+    let error = |start: usize, end: usize, label, message: String| {
+        let span = NixSpan::from_offset(&file, start + 1, end);
+        NixError::from_message(
+            NixLabel::new(span.into(), label, NixLabelKind::Error),
+            message,
+        )
+    };
+
+    let mut components = Vec::new();
+    let mut name = String::new();
+    let mut start = 0;
+    // Offset of the opening quote
+    let mut quote = None;
+
+    for (offset, c) in path.char_indices() {
+        match c {
+            '"' => quote = quote.xor(Some(offset)),
+            '.' if quote.is_none() => {
+                components.push((std::mem::take(&mut name), start, offset));
+                start = offset + 1;
+            }
+            c => name.push(c),
+        }
+    }
+
+    if let Some(offset) = quote {
+        return Err(error(
+            offset,
+            offset + 1,
+            NixLabelMessage::Custom("Opened here".to_owned()),
+            format!("Missing closing quote in selection path '{path}'"),
+        ));
+    }
+
+    components.push((name, start, path.len()));
+
+    for (name, start, end) in components {
+        if name.is_empty() {
+            // Points to the dot, the name can be at the end
+            let dot = start.saturating_sub(1);
+
+            return Err(error(
+                dot,
+                dot + 1,
+                NixLabelMessage::Custom("Empty attribute name".to_owned()),
+                format!("Empty attribute name in selection path '{path}'"),
+            ));
+        }
+
+        value = NixValue::auto_call(&value, backtrace, args)?;
+
+        let var = match &*value.borrow() {
+            NixValue::AttrSet(set) => match set.get(&name) {
+                Some(var) => var.clone(),
+                None if set.is_empty() => {
+                    return Err(error(
+                        start,
+                        end,
+                        NixLabelMessage::AttributeMissing,
+                        format!("Attribute '{name}' in selection path '{path}' not found, the set is empty"),
+                    ))
+                }
+                None => {
+                    let names = set.keys().cloned().collect::<Vec<_>>().join(", ");
+
+                    return Err(error(
+                        start,
+                        end,
+                        NixLabelMessage::AttributeMissing,
+                        format!("Attribute '{name}' in selection path '{path}' not found, available attributes: {names}"),
+                    ));
+                }
+            },
+            NixValue::List(list) => match name.parse().ok().and_then(|index: usize| list.0.get(index)) {
+                Some(var) => var.clone(),
+                None => {
+                    return Err(error(
+                        start,
+                        end,
+                        NixLabelMessage::Custom("Invalid index".to_owned()),
+                        format!("Invalid index '{name}' in selection path '{path}', the list has {} elements", list.0.len()),
+                    ))
+                }
+            },
+            value => {
+                return Err(error(
+                    start,
+                    end,
+                    NixLabelMessage::ExpectedType("a set"),
+                    format!("Cannot select '{name}' in selection path '{path}', the value is of type {}",
+                        value.as_type()
+                    ),
+                ))
+            }
+        };
+
+        value = var.resolve(backtrace)?;
+    }
+
+    NixValue::auto_call(&value, backtrace, args)
+}
+
+/// Calls the function of `--apply` with `value`, the errors point to the
+/// expression in a virtual file
+fn apply_function(value: NixValueWrapped, expr: String) -> NixResult {
+    let (backtrace, function) = FileScope::repl_file(PathBuf::from("«apply»"), expr)?;
+
+    let function = function.borrow();
+    let Some(lambda) = function.as_lambda() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::ExpectedType("a function"),
+            format!(
+                "The expression of '--apply' must be a function, but it's of type {}",
+                function.as_type()
+            ),
+        ));
+    };
+
+    lambda
+        .call(&backtrace, LazyNixValue::Concrete(value).wrap_var())?
+        .resolve(&backtrace)
+}
+
+/// Result of `--raw`, derivations are printed as their `outPath`
+fn to_raw_string(backtrace: &NixBacktrace, value: &NixValueWrapped) -> NixResult<String> {
+    match &*value.borrow() {
+        NixValue::String(string) => Ok(string.clone()),
+        NixValue::Path(path) => Ok(path.display().to_string()),
+        NixValue::AttrSet(set) if set.contains_key("outPath") => {
+            to_raw_string(backtrace, &set["outPath"].resolve(backtrace)?)
+        }
+        value => Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::ExpectedType("a string"),
+            format!(
+                "Cannot print {} with '--raw', expected a string",
+                value.as_type()
+            ),
+        )),
+    }
+}
+
+fn parse_limit(option: &str, value: Option<String>) -> usize {
+    let Some(value) = value else {
+        eprintln!("Missing value for '{option}'");
+        std::process::exit(1);
+    };
+
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid value '{value}' for '{option}', it should be a positive integer");
+        std::process::exit(1);
+    })
 }
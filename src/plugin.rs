@@ -0,0 +1,259 @@
+//! Builtins loaded from shared libraries, like `plugin-files` in Nix.
+//!
+//! A plugin is a `cdylib` crate that depends on this crate and declares
+//! itself with [`declare_plugin!`](crate::declare_plugin):
+//!
+//! ```ignore
+//! use nix_compiler::plugin::NixPluginRegistrar;
+//! use nix_compiler::{NixBacktrace, NixHostBuiltin, NixValue};
+//!
+//! fn register(registrar: &mut NixPluginRegistrar) {
+//!     registrar.register(NixHostBuiltin::from_fn(
+//!         "lookupSecretPath",
+//!         |_: &NixBacktrace, name: String| Ok(NixValue::String(format!("/run/secrets/{name}")).wrap()),
+//!     ));
+//! }
+//!
+//! nix_compiler::declare_plugin!("ourCompany", register);
+//! ```
+//!
+//! The builtins of the plugin are available as `builtins.<namespace>.<name>`.
+//!
+//! Only [`NixPluginDeclaration`] has a C layout, so it can be read by any
+//! version of this crate. The builtins themselves cross the library boundary
+//! as Rust types, so the plugin must be built with the same compiler and the
+//! same version of this crate. Both are checked when loading it, after
+//! checking [`NIX_PLUGIN_ABI_VERSION`].
+//!
+//! The plugin links its own copy of this crate, so the [`NixSettings`]
+//! installed by the host don't apply to the code running inside of it.
+
+use std::ffi::{c_char, CStr};
+use std::path::{Path, PathBuf};
+
+use libloading::Library;
+use thiserror::Error;
+
+use crate::{NixHostBuiltin, NixSettings};
+
+/// Version of [`NixPluginDeclaration`], changed on every incompatible change
+/// of its layout
+pub const NIX_PLUGIN_ABI_VERSION: u32 = 2;
+
+/// Version of the compiler that built this crate
+pub const RUSTC_VERSION: &CStr = c_str(concat!(env!("NIX_PLUGIN_RUSTC_VERSION"), "\0"));
+
+/// Version of this crate
+pub const CORE_VERSION: &CStr = c_str(concat!(env!("CARGO_PKG_VERSION"), "\0"));
+
+/// Name of the symbol exported by [`declare_plugin!`](crate::declare_plugin)
+const DECLARATION_SYMBOL: &[u8] = b"NIX_PLUGIN_DECLARATION\0";
+
+/// Used by [`declare_plugin!`](crate::declare_plugin) to check its strings
+/// at compile time
+#[doc(hidden)]
+pub const fn c_str(string: &str) -> &CStr {
+    match CStr::from_bytes_with_nul(string.as_bytes()) {
+        Ok(string) => string,
+        Err(_) => panic!("The string should end with its only NUL"),
+    }
+}
+
+/// Exported by every plugin, use [`declare_plugin!`](crate::declare_plugin)
+/// instead of building it manually.
+///
+/// The strings are NUL-terminated and live as long as the library.
+#[repr(C)]
+pub struct NixPluginDeclaration {
+    /// Always the first field, so it can be read even if the rest of the
+    /// layout changed
+    pub abi_version: u32,
+    pub rustc_version: *const c_char,
+    pub core_version: *const c_char,
+    pub namespace: *const c_char,
+    /// Receives a registrar that is only valid during the call
+    pub register: unsafe extern "C" fn(*mut NixPluginRegistrar),
+}
+
+// SAFETY: The strings are never written
+unsafe impl Sync for NixPluginDeclaration {}
+
+/// Declares the current crate as a plugin, that registers its builtins
+/// under `builtins.<namespace>` with `register`, a
+/// `fn(&mut NixPluginRegistrar)`
+#[macro_export]
+macro_rules! declare_plugin {
+    ($namespace:literal, $register:path) => {
+        #[no_mangle]
+        pub static NIX_PLUGIN_DECLARATION: $crate::plugin::NixPluginDeclaration =
+            $crate::plugin::NixPluginDeclaration {
+                abi_version: $crate::plugin::NIX_PLUGIN_ABI_VERSION,
+                rustc_version: $crate::plugin::RUSTC_VERSION.as_ptr(),
+                core_version: $crate::plugin::CORE_VERSION.as_ptr(),
+                namespace: $crate::plugin::c_str(concat!($namespace, "\0")).as_ptr(),
+                register: {
+                    // Not named `register`, it would shadow `$register`
+                    unsafe extern "C" fn declared_register(
+                        registrar: *mut $crate::plugin::NixPluginRegistrar,
+                    ) {
+                        // SAFETY: `NixPlugin::register` passes a valid registrar
+                        $register(unsafe { &mut *registrar })
+                    }
+
+                    declared_register
+                },
+            };
+    };
+}
+
+#[derive(Debug, Error)]
+pub enum NixPluginError {
+    #[error("Cannot load plugin {}: {source}", path.display())]
+    Load {
+        path: PathBuf,
+        source: libloading::Error,
+    },
+
+    #[error("{} is not a plugin, it doesn't export `NIX_PLUGIN_DECLARATION`", path.display())]
+    MissingDeclaration { path: PathBuf },
+
+    #[error("Plugin {} uses the ABI version {found}, but {expected} is required", path.display())]
+    AbiVersion {
+        path: PathBuf,
+        expected: u32,
+        found: u32,
+    },
+
+    #[error("Plugin {} was built with {found}, but it should be built with {expected}", path.display())]
+    RustcVersion {
+        path: PathBuf,
+        expected: &'static str,
+        found: String,
+    },
+
+    #[error("Plugin {} was built against nix-compiler {found}, but {expected} is running", path.display())]
+    CoreVersion {
+        path: PathBuf,
+        expected: &'static str,
+        found: String,
+    },
+
+    #[error("Plugin {} has an invalid namespace '{namespace}'", path.display())]
+    InvalidNamespace { path: PathBuf, namespace: String },
+}
+
+/// Collects the builtins of a plugin while it's registering them
+pub struct NixPluginRegistrar {
+    namespace: &'static str,
+    builtins: Vec<NixHostBuiltin>,
+}
+
+impl NixPluginRegistrar {
+    /// Add a builtin, its name is relative to the namespace of the plugin
+    pub fn register(&mut self, builtin: NixHostBuiltin) {
+        self.builtins.push(builtin.in_namespace(self.namespace));
+    }
+}
+
+pub struct NixPlugin {
+    namespace: &'static str,
+    register: unsafe extern "C" fn(*mut NixPluginRegistrar),
+}
+
+impl NixPlugin {
+    /// Loads the shared library at `path`, checking that it's a compatible
+    /// plugin.
+    ///
+    /// The library is never unloaded, because the builtins that it defines can
+    /// live until the end of the process.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, NixPluginError> {
+        let path = path.as_ref().to_path_buf();
+
+        // SAFETY: Loading a library runs its initialization routines, plugins
+        // are trusted in the same way as the binary itself
+        let library = unsafe { Library::new(&path) }.map_err(|source| NixPluginError::Load {
+            path: path.clone(),
+            source,
+        })?;
+
+        let library: &'static Library = Box::leak(Box::new(library));
+
+        // SAFETY: The symbol is declared by `declare_plugin!` as a static
+        // `NixPluginDeclaration`. Only `abi_version` is read before checking
+        // that the layout is the expected one.
+        let declaration = unsafe {
+            library
+                .get::<*const NixPluginDeclaration>(DECLARATION_SYMBOL)
+                .map(|symbol| &**symbol)
+        }
+        .map_err(|_| NixPluginError::MissingDeclaration { path: path.clone() })?;
+
+        if declaration.abi_version != NIX_PLUGIN_ABI_VERSION {
+            return Err(NixPluginError::AbiVersion {
+                path,
+                expected: NIX_PLUGIN_ABI_VERSION,
+                found: declaration.abi_version,
+            });
+        }
+
+        // SAFETY: The ABI version is the same, so the strings are valid and
+        // live as long as the library, which is never unloaded
+        let (rustc_version, core_version, namespace) = unsafe {
+            (
+                CStr::from_ptr(declaration.rustc_version),
+                CStr::from_ptr(declaration.core_version),
+                CStr::from_ptr(declaration.namespace),
+            )
+        };
+
+        if rustc_version != RUSTC_VERSION {
+            return Err(NixPluginError::RustcVersion {
+                path,
+                expected: RUSTC_VERSION.to_str().unwrap(),
+                found: rustc_version.to_string_lossy().into_owned(),
+            });
+        }
+
+        if core_version != CORE_VERSION {
+            return Err(NixPluginError::CoreVersion {
+                path,
+                expected: CORE_VERSION.to_str().unwrap(),
+                found: core_version.to_string_lossy().into_owned(),
+            });
+        }
+
+        let namespace = match namespace.to_str() {
+            Ok(namespace) if !namespace.is_empty() && !namespace.contains('.') => namespace,
+            _ => {
+                return Err(NixPluginError::InvalidNamespace {
+                    path,
+                    namespace: namespace.to_string_lossy().into_owned(),
+                });
+            }
+        };
+
+        Ok(Self {
+            namespace,
+            register: declaration.register,
+        })
+    }
+
+    pub fn namespace(&self) -> &'static str {
+        self.namespace
+    }
+
+    /// Add the builtins of the plugin to `settings`
+    pub fn register(&self, settings: &mut NixSettings) {
+        let mut registrar = NixPluginRegistrar {
+            namespace: self.namespace,
+            builtins: Vec::new(),
+        };
+
+        // SAFETY: The registrar lives during the whole call
+        unsafe { (self.register)(&mut registrar) };
+
+        for builtin in registrar.builtins {
+            settings.register_builtin(builtin, false);
+        }
+    }
+}
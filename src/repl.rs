@@ -0,0 +1,383 @@
+//! `nix-compiler repl`, evaluates expressions interactively like `nix repl`
+
+use std::cell::RefCell;
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::LazyLock;
+
+use nix_compiler::{
+    FileScope, LazyNixValue, NixAttrSet, NixBacktrace, NixBacktraceKind, NixLambda, NixLimits,
+    NixPrinter, NixResult, NixSettings, NixSpan, NixValue, NixValueWrapped, Scope,
+};
+use regex::Regex;
+use rnix::parser::ParseError;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Editor, Helper};
+
+const HELP: &str = "\
+The following commands are available:
+
+  <expr>        Evaluate and print an expression
+  <x> = <expr>  Bind an expression to a variable
+  :?, :help     Print this help
+  :doc <expr>   Show the documentation of a builtin
+  :l <path>     Load a Nix file and add its attributes to the scope
+  :p <expr>     Evaluate and print an expression recursively
+  :q, :quit     Exit
+  :r, :reload   Reload all the files loaded with :l
+  :t <expr>     Describe the type of an expression";
+
+/// Lines like `x = <expr>`
+static BINDING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)^([a-zA-Z_][a-zA-Z0-9_'-]*)\s*=([^=].*)$").unwrap());
+
+const COMMANDS: [&str; 11] = [
+    ":?", ":doc", ":help", ":l", ":load", ":p", ":print", ":q", ":quit", ":r", ":reload",
+];
+
+/// Variables of the REPL, shared with the completion
+struct State {
+    /// Bound with `x = <expr>` or loaded with `:l`
+    variables: NixAttrSet,
+    /// Global variables, like `builtins` and `map`
+    globals: NixAttrSet,
+    /// Files loaded with `:l`, in order
+    files: Vec<PathBuf>,
+}
+
+pub fn run(files: impl Iterator<Item = String>) {
+    let cwd = env::current_dir().unwrap();
+
+    // Only used to get the global variables
+    let scope = Scope::new_with_builtins(Rc::new(FileScope {
+        path: cwd.join("«repl»"),
+        content: String::new(),
+    }));
+    let globals = scope.parent.as_ref().unwrap().variables.borrow();
+
+    let state = Rc::new(RefCell::new(State {
+        variables: NixAttrSet::new(),
+        globals: globals.as_attr_set().unwrap().clone(),
+        files: Vec::new(),
+    }));
+
+    let mut editor = Editor::<ReplHelper, DefaultHistory>::new().unwrap_or_else(|err| {
+        eprintln!("Cannot start the line editor: {err}");
+        std::process::exit(1);
+    });
+    editor.set_helper(Some(ReplHelper(state.clone())));
+
+    for file in files {
+        load(&state, PathBuf::from(file));
+    }
+
+    println!("Welcome to nix-compiler. Type :? for help.");
+
+    loop {
+        let line = match editor.readline("nix-repl> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("{err}");
+                break;
+            }
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(line);
+
+        // Every line is a new evaluation
+        NixSettings::current().interrupt.reset();
+        NixLimits::reset();
+
+        // The panic is already printed, the bindings are still usable
+        match panic::catch_unwind(AssertUnwindSafe(|| run_line(&state, line))) {
+            Ok(true) | Err(_) => {}
+            Ok(false) => break,
+        }
+    }
+}
+
+/// Runs a line, `false` if the REPL should exit
+fn run_line(state: &Rc<RefCell<State>>, line: &str) -> bool {
+    let (command, argument) = match line.split_once(char::is_whitespace) {
+        Some((command, argument)) if command.starts_with(':') => (command, argument.trim()),
+        _ if line.starts_with(':') => (line, ""),
+        _ => ("", line),
+    };
+
+    match command {
+        "" => {
+            if let Some(captures) = BINDING.captures(argument) {
+                let name = captures[1].to_owned();
+
+                // Like in Nix, the value is only evaluated when it's used
+                let variables = state.borrow().variables.clone();
+                let parsed = FileScope::parse_with_variables(
+                    repl_path(),
+                    captures[2].trim().to_owned(),
+                    variables,
+                );
+
+                match parsed {
+                    Ok((_, var)) => {
+                        state.borrow_mut().variables.insert(name, var);
+                    }
+                    Err(err) => eprintln!("{err}"),
+                }
+            } else {
+                print(state, argument, Some(1));
+            }
+        }
+        ":?" | ":help" => println!("{HELP}"),
+        ":doc" => match eval(state, argument) {
+            Ok((_, value)) => match &*value.borrow() {
+                NixValue::Lambda(NixLambda::Builtin(builtin)) => {
+                    println!("Synopsis: builtins.{}\n", builtin.signature());
+
+                    match builtin.doc() {
+                        "" => println!("No documentation"),
+                        doc => println!("{doc}"),
+                    }
+                }
+                _ => eprintln!("Documentation is only available for builtins"),
+            },
+            Err(err) => eprintln!("{err}"),
+        },
+        ":l" | ":load" => load(state, PathBuf::from(argument)),
+        ":p" | ":print" => print(state, argument, None),
+        ":q" | ":quit" => return false,
+        ":r" | ":reload" => {
+            FileScope::clear_cache();
+
+            let files = std::mem::take(&mut state.borrow_mut().files);
+            for file in files {
+                load(state, file);
+            }
+        }
+        ":t" | ":type" => match eval(state, argument) {
+            Ok((_, value)) => println!("{}", value.borrow().as_type()),
+            Err(err) => eprintln!("{err}"),
+        },
+        _ => eprintln!("Unknown command '{command}', type :? for help"),
+    }
+
+    true
+}
+
+fn eval(state: &Rc<RefCell<State>>, expr: &str) -> NixResult<(NixBacktrace, NixValueWrapped)> {
+    let variables = state.borrow().variables.clone();
+
+    FileScope::eval_with_variables(repl_path(), expr.to_owned(), variables)
+}
+
+/// Path of the expressions typed in the REPL, relative paths are resolved
+/// from the current directory
+fn repl_path() -> PathBuf {
+    env::current_dir().unwrap().join("«repl»")
+}
+
+/// Prints `expr` evaluating `depth` levels, or everything if it's `None`
+fn print(state: &Rc<RefCell<State>>, expr: &str, depth: Option<usize>) {
+    let printed = eval(state, expr).and_then(|(backtrace, value)| {
+        NixPrinter::pretty().print_limited(
+            &LazyNixValue::Concrete(value).wrap_var(),
+            depth,
+            None,
+            &backtrace,
+        )
+    });
+
+    match printed {
+        Ok(printed) => println!("{printed}"),
+        Err(err) => eprintln!("{err}"),
+    }
+}
+
+/// Adds the attributes of the file to the variables
+fn load(state: &Rc<RefCell<State>>, path: PathBuf) {
+    if !path.exists() {
+        eprintln!("File '{}' doesn't exist", path.display());
+        return;
+    }
+
+    if NixSettings::current()
+        .allowed_path(FileScope::normalize_path(&path))
+        .is_none()
+    {
+        eprintln!(
+            "Access to path '{}' is forbidden in restricted mode",
+            path.display()
+        );
+        return;
+    }
+
+    let value = FileScope::get_file(None, &path)
+        .and_then(|(backtrace, value)| NixValue::auto_call(&value, &backtrace, &NixAttrSet::new()));
+
+    let value = match value {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("{err}");
+            return;
+        }
+    };
+
+    let value = value.borrow();
+    let Some(set) = value.as_attr_set() else {
+        eprintln!(
+            "Cannot load '{}', it's a {} instead of a set",
+            path.display(),
+            value.as_type()
+        );
+        return;
+    };
+
+    let mut state = state.borrow_mut();
+
+    state
+        .variables
+        .extend(set.iter().map(|(name, var)| (name.clone(), var.clone())));
+    state.files.push(path);
+
+    println!("Added {} variables.", set.len());
+}
+
+struct ReplHelper(Rc<RefCell<State>>);
+
+impl ReplHelper {
+    /// Names of the set at `path`, like `pkgs.lib`, the values are evaluated
+    fn attr_names(&self, path: &str) -> Option<Vec<String>> {
+        let state = self.0.borrow();
+        let mut components = path.split('.');
+
+        // Errors are ignored, but they point to the path
+        let file = Rc::new(FileScope {
+            path: PathBuf::from("«completion»"),
+            content: path.to_owned(),
+        });
+        let span = NixSpan::from_offset(&file, 1, path.len());
+        let backtrace = NixBacktrace(span.into(), None.into(), NixBacktraceKind::File);
+
+        let name = components.next()?;
+        let mut value = state
+            .variables
+            .get(name)
+            .or_else(|| state.globals.get(name))?
+            .resolve(&backtrace)
+            .ok()?;
+
+        for name in components {
+            let var = value.borrow().as_attr_set()?.get(name)?.clone();
+            value = var.resolve(&backtrace).ok()?;
+        }
+
+        let names = value.borrow().as_attr_set()?.keys().cloned().collect();
+
+        Some(names)
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+
+        let start = line
+            .rfind(|c: char| {
+                !(c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-' | '.' | ':'))
+            })
+            .map_or(0, |idx| idx + 1);
+        let word = &line[start..];
+
+        if word.starts_with(':') {
+            let candidates = COMMANDS
+                .iter()
+                .filter(|command| start == 0 && command.starts_with(word))
+                .map(|command| command.to_string())
+                .collect();
+
+            return Ok((start, candidates));
+        }
+
+        let mut candidates = match word.rsplit_once('.') {
+            Some((path, prefix)) => self
+                .attr_names(path)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|name| name.starts_with(prefix))
+                .map(|name| format!("{path}.{name}"))
+                .collect::<Vec<_>>(),
+            None => {
+                let state = self.0.borrow();
+
+                state
+                    .variables
+                    .keys()
+                    .chain(state.globals.keys())
+                    .filter(|name| name.starts_with(word))
+                    .cloned()
+                    .collect()
+            }
+        };
+
+        candidates.sort();
+        candidates.dedup();
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+impl Validator for ReplHelper {
+    /// Expressions that end too early continue in the next line
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let input = ctx.input().trim();
+
+        if input.starts_with(':') {
+            return Ok(ValidationResult::Valid(None));
+        }
+
+        let expr = BINDING
+            .captures(input)
+            .map_or(input, |captures| captures.get(2).unwrap().as_str());
+
+        let is_incomplete = rnix::Root::parse(expr).errors().iter().any(|error| {
+            matches!(
+                error,
+                ParseError::UnexpectedEOF | ParseError::UnexpectedEOFWanted(_)
+            )
+        });
+
+        if is_incomplete {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
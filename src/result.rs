@@ -4,7 +4,7 @@ use std::fmt::{self, Write};
 use std::rc::Rc;
 
 use backtrace::BACKTRACE_ENV;
-use rnix::{parser, SyntaxKind};
+use rnix::{parser, SyntaxKind, TextRange};
 use rowan::ast::AstNode;
 use thiserror::Error;
 
@@ -60,6 +60,21 @@ pub enum NixLabelMessage {
     #[error("")]
     Empty,
 
+    #[error("Expected {0} as the {1} argument of builtins.{2}")]
+    ExpectedArgument(&'static str, &'static str, &'static str),
+
+    #[error("Expected {0}")]
+    ExpectedType(&'static str),
+
+    #[error("Interrupted here")]
+    Interrupted,
+
+    #[error("Evaluation limit exceeded here")]
+    LimitExceeded,
+
+    #[error("Path not allowed in restricted mode")]
+    RestrictedPath,
+
     #[error("Unexpected token")]
     UnexpectedToken,
 
@@ -122,9 +137,45 @@ impl NixError {
 
     pub fn from_parse_error(file: &Rc<FileScope>, error: parser::ParseError) -> Self {
         use parser::ParseError::*;
+        let error_label = |range: TextRange, label| {
+            let start = usize::from(range.start());
+            let end = usize::from(range.end()).max(start + 1);
+
+            NixLabel::new(
+                NixSpan::from_offset(file, start + 1, end).into(),
+                label,
+                NixLabelKind::Error,
+            )
+        };
+
+        // Points to the last character, the content cannot be empty
+        let eof_label = |label| {
+            let end = file.content.len().max(1);
+
+            NixLabel::new(
+                NixSpan::from_offset(file, end, end).into(),
+                label,
+                NixLabelKind::Error,
+            )
+        };
+
+        let expected_list = |expected: &[SyntaxKind]| {
+            expected
+                .iter()
+                .map(|kind| format!("'{}'", syntax_kind_to_string(*kind)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
         let (message, labels) = match error {
-            Unexpected(_) => todo!(),
-            UnexpectedExtra(_) => todo!(),
+            Unexpected(range) | UnexpectedExtra(range) => {
+                let token = &file.content[range];
+
+                (
+                    format!("Unexpected token '{token}'"),
+                    vec![error_label(range, NixLabelMessage::UnexpectedToken)],
+                )
+            }
             UnexpectedWanted(unexpected, range, expected) => {
                 if expected.len() == 1 {
                     let range_start: usize = range.start().into();
@@ -156,14 +207,50 @@ impl NixError {
                         vec![unexpected_label, expected_label],
                     )
                 } else {
-                    todo!()
+                    let unexpected = syntax_kind_to_string(unexpected);
+
+                    (
+                        format!(
+                            "Unexpected token '{unexpected}', expected one of {}",
+                            expected_list(&expected)
+                        ),
+                        vec![error_label(range, NixLabelMessage::UnexpectedToken)],
+                    )
                 }
             }
-            UnexpectedDoubleBind(_) => todo!(),
-            UnexpectedEOF => todo!(),
-            UnexpectedEOFWanted(_) => todo!(),
-            DuplicatedArgs(_, _) => todo!(),
-            RecursionLimitExceeded => todo!(),
+            UnexpectedDoubleBind(range) => (
+                "Pattern bound twice".to_owned(),
+                vec![error_label(
+                    range,
+                    NixLabelMessage::Custom("Second binding".to_owned()),
+                )],
+            ),
+            UnexpectedEOF => (
+                "Unexpected end of file".to_owned(),
+                vec![eof_label(NixLabelMessage::Custom(
+                    "Expected more after this".to_owned(),
+                ))],
+            ),
+            UnexpectedEOFWanted(expected) => (
+                format!(
+                    "Unexpected end of file, expected {}",
+                    expected_list(&expected)
+                ),
+                vec![eof_label(NixLabelMessage::Custom(
+                    "Expected more after this".to_owned(),
+                ))],
+            ),
+            DuplicatedArgs(range, name) => (
+                format!("Duplicated argument '{name}'"),
+                vec![error_label(
+                    range,
+                    NixLabelMessage::Custom("Duplicated argument".to_owned()),
+                )],
+            ),
+            RecursionLimitExceeded => (
+                "Expression nested too deeply".to_owned(),
+                vec![eof_label(NixLabelMessage::Empty)],
+            ),
             _ => unreachable!(),
         };
 
@@ -210,6 +297,11 @@ impl NixSpan {
                 + 1;
 
             let Some(column) = (offset - last_newline).checked_sub(1) else {
+                // Only newlines before it, it's the start of the file
+                if last_newline == 0 {
+                    break (1, 0, 0);
+                }
+
                 offset = last_newline.saturating_sub(1);
                 continue;
             };
@@ -229,6 +321,25 @@ impl NixSpan {
         }
     }
 
+    /// Span of the character at `offset` of `content`, if this span is a
+    /// string literal of `content` without escapes
+    pub fn in_literal(&self, content: &str, offset: usize) -> Option<Self> {
+        let start = self.start.2 + self.start.1;
+        let end = self.end.2 + self.end.1 + 1;
+        let literal = self.file.content.get(start..end)?;
+
+        let inner = literal
+            .strip_prefix('"')
+            .and_then(|literal| literal.strip_suffix('"'))?;
+
+        if inner != content {
+            return None;
+        }
+
+        let offset = start + 1 + offset;
+        Some(Self::from_offset(&self.file, offset + 1, offset + 1))
+    }
+
     pub fn from_ast_node(file: &Rc<FileScope>, node: &impl AstNode) -> Self {
         Self::from_offset(
             file,
@@ -264,27 +375,27 @@ fn syntax_kind_to_string(kind: SyntaxKind) -> &'static str {
 
         // Keywords
         SyntaxKind::TOKEN_ASSERT => "assert",
-        SyntaxKind::TOKEN_ELSE => todo!(),
-        SyntaxKind::TOKEN_IF => todo!(),
-        SyntaxKind::TOKEN_IN => todo!(),
-        SyntaxKind::TOKEN_INHERIT => todo!(),
-        SyntaxKind::TOKEN_LET => todo!(),
-        SyntaxKind::TOKEN_OR => todo!(),
-        SyntaxKind::TOKEN_REC => todo!(),
-        SyntaxKind::TOKEN_THEN => todo!(),
-        SyntaxKind::TOKEN_WITH => todo!(),
+        SyntaxKind::TOKEN_ELSE => "else",
+        SyntaxKind::TOKEN_IF => "if",
+        SyntaxKind::TOKEN_IN => "in",
+        SyntaxKind::TOKEN_INHERIT => "inherit",
+        SyntaxKind::TOKEN_LET => "let",
+        SyntaxKind::TOKEN_OR => "or",
+        SyntaxKind::TOKEN_REC => "rec",
+        SyntaxKind::TOKEN_THEN => "then",
+        SyntaxKind::TOKEN_WITH => "with",
 
         // Literals
-        SyntaxKind::TOKEN_FLOAT => todo!(),
-        SyntaxKind::TOKEN_IDENT => todo!(),
-        SyntaxKind::TOKEN_INTEGER => todo!(),
-        SyntaxKind::TOKEN_INTERPOL_END => todo!(),
-        SyntaxKind::TOKEN_INTERPOL_START => todo!(),
-        SyntaxKind::TOKEN_PATH => todo!(),
-        SyntaxKind::TOKEN_URI => todo!(),
-        SyntaxKind::TOKEN_STRING_CONTENT => todo!(),
-        SyntaxKind::TOKEN_STRING_END => todo!(),
-        SyntaxKind::TOKEN_STRING_START => todo!(),
+        SyntaxKind::TOKEN_FLOAT => "<float>",
+        SyntaxKind::TOKEN_IDENT => "<identifier>",
+        SyntaxKind::TOKEN_INTEGER => "<integer>",
+        SyntaxKind::TOKEN_INTERPOL_END => "}",
+        SyntaxKind::TOKEN_INTERPOL_START => "${",
+        SyntaxKind::TOKEN_PATH => "<path>",
+        SyntaxKind::TOKEN_URI => "<uri>",
+        SyntaxKind::TOKEN_STRING_CONTENT => "<string content>",
+        SyntaxKind::TOKEN_STRING_END => "\"",
+        SyntaxKind::TOKEN_STRING_START => "\"",
 
         // Punctuation
         SyntaxKind::TOKEN_ELLIPSIS => "...",
@@ -297,60 +408,60 @@ fn syntax_kind_to_string(kind: SyntaxKind) -> &'static str {
         SyntaxKind::TOKEN_SEMICOLON => ";",
 
         // Operators
-        SyntaxKind::TOKEN_ASSIGN => todo!(),
-        SyntaxKind::TOKEN_AT => todo!(),
-        SyntaxKind::TOKEN_COLON => todo!(),
-        SyntaxKind::TOKEN_COMMA => todo!(),
-        SyntaxKind::TOKEN_DOT => todo!(),
-        SyntaxKind::TOKEN_QUESTION => todo!(),
-        SyntaxKind::TOKEN_CONCAT => todo!(),
-        SyntaxKind::TOKEN_INVERT => todo!(),
-        SyntaxKind::TOKEN_UPDATE => todo!(),
-        SyntaxKind::TOKEN_ADD => todo!(),
-        SyntaxKind::TOKEN_SUB => todo!(),
-        SyntaxKind::TOKEN_MUL => todo!(),
-        SyntaxKind::TOKEN_DIV => todo!(),
-        SyntaxKind::TOKEN_AND_AND => todo!(),
-        SyntaxKind::TOKEN_EQUAL => todo!(),
-        SyntaxKind::TOKEN_IMPLICATION => todo!(),
-        SyntaxKind::TOKEN_LESS => todo!(),
-        SyntaxKind::TOKEN_LESS_OR_EQ => todo!(),
-        SyntaxKind::TOKEN_MORE => todo!(),
-        SyntaxKind::TOKEN_MORE_OR_EQ => todo!(),
-        SyntaxKind::TOKEN_NOT_EQUAL => todo!(),
-        SyntaxKind::TOKEN_OR_OR => todo!(),
-
-        SyntaxKind::NODE_APPLY => todo!(),
-        SyntaxKind::NODE_ASSERT => todo!(),
-        SyntaxKind::NODE_ATTRPATH => todo!(),
-        SyntaxKind::NODE_DYNAMIC => todo!(),
-        SyntaxKind::NODE_ERROR => todo!(),
-        SyntaxKind::NODE_IDENT => todo!(),
-        SyntaxKind::NODE_IF_ELSE => todo!(),
-        SyntaxKind::NODE_SELECT => todo!(),
-        SyntaxKind::NODE_INHERIT => todo!(),
-        SyntaxKind::NODE_INHERIT_FROM => todo!(),
-        SyntaxKind::NODE_STRING => todo!(),
-        SyntaxKind::NODE_INTERPOL => todo!(),
-        SyntaxKind::NODE_LAMBDA => todo!(),
-        SyntaxKind::NODE_IDENT_PARAM => todo!(),
-        SyntaxKind::NODE_LEGACY_LET => todo!(),
-        SyntaxKind::NODE_LET_IN => todo!(),
-        SyntaxKind::NODE_LIST => todo!(),
-        SyntaxKind::NODE_BIN_OP => todo!(),
-        SyntaxKind::NODE_PAREN => todo!(),
-        SyntaxKind::NODE_PATTERN => todo!(),
-        SyntaxKind::NODE_PAT_BIND => todo!(),
-        SyntaxKind::NODE_PAT_ENTRY => todo!(),
-        SyntaxKind::NODE_ROOT => todo!(),
-        SyntaxKind::NODE_ATTR_SET => todo!(),
-        SyntaxKind::NODE_ATTRPATH_VALUE => todo!(),
-        SyntaxKind::NODE_UNARY_OP => todo!(),
-        SyntaxKind::NODE_LITERAL => todo!(),
-        SyntaxKind::NODE_WITH => todo!(),
-        SyntaxKind::NODE_PATH => todo!(),
-        SyntaxKind::NODE_HAS_ATTR => todo!(),
-        _ => todo!(),
+        SyntaxKind::TOKEN_ASSIGN => "=",
+        SyntaxKind::TOKEN_AT => "@",
+        SyntaxKind::TOKEN_COLON => ":",
+        SyntaxKind::TOKEN_COMMA => ",",
+        SyntaxKind::TOKEN_DOT => ".",
+        SyntaxKind::TOKEN_QUESTION => "?",
+        SyntaxKind::TOKEN_CONCAT => "++",
+        SyntaxKind::TOKEN_INVERT => "!",
+        SyntaxKind::TOKEN_UPDATE => "//",
+        SyntaxKind::TOKEN_ADD => "+",
+        SyntaxKind::TOKEN_SUB => "-",
+        SyntaxKind::TOKEN_MUL => "*",
+        SyntaxKind::TOKEN_DIV => "/",
+        SyntaxKind::TOKEN_AND_AND => "&&",
+        SyntaxKind::TOKEN_EQUAL => "==",
+        SyntaxKind::TOKEN_IMPLICATION => "->",
+        SyntaxKind::TOKEN_LESS => "<",
+        SyntaxKind::TOKEN_LESS_OR_EQ => "<=",
+        SyntaxKind::TOKEN_MORE => ">",
+        SyntaxKind::TOKEN_MORE_OR_EQ => ">=",
+        SyntaxKind::TOKEN_NOT_EQUAL => "!=",
+        SyntaxKind::TOKEN_OR_OR => "||",
+
+        SyntaxKind::NODE_APPLY => "<apply>",
+        SyntaxKind::NODE_ASSERT => "<assert>",
+        SyntaxKind::NODE_ATTRPATH => "<attribute path>",
+        SyntaxKind::NODE_DYNAMIC => "<dynamic attribute>",
+        SyntaxKind::NODE_ERROR => "<error>",
+        SyntaxKind::NODE_IDENT => "<identifier>",
+        SyntaxKind::NODE_IF_ELSE => "<if>",
+        SyntaxKind::NODE_SELECT => "<select>",
+        SyntaxKind::NODE_INHERIT => "<inherit>",
+        SyntaxKind::NODE_INHERIT_FROM => "<inherit from>",
+        SyntaxKind::NODE_STRING => "<string>",
+        SyntaxKind::NODE_INTERPOL => "<interpolation>",
+        SyntaxKind::NODE_LAMBDA => "<lambda>",
+        SyntaxKind::NODE_IDENT_PARAM => "<parameter>",
+        SyntaxKind::NODE_LEGACY_LET => "<let>",
+        SyntaxKind::NODE_LET_IN => "<let>",
+        SyntaxKind::NODE_LIST => "<list>",
+        SyntaxKind::NODE_BIN_OP => "<binary operation>",
+        SyntaxKind::NODE_PAREN => "<parenthesis>",
+        SyntaxKind::NODE_PATTERN => "<pattern>",
+        SyntaxKind::NODE_PAT_BIND => "<pattern binding>",
+        SyntaxKind::NODE_PAT_ENTRY => "<pattern entry>",
+        SyntaxKind::NODE_ROOT => "<root>",
+        SyntaxKind::NODE_ATTR_SET => "<attribute set>",
+        SyntaxKind::NODE_ATTRPATH_VALUE => "<attribute>",
+        SyntaxKind::NODE_UNARY_OP => "<unary operation>",
+        SyntaxKind::NODE_LITERAL => "<literal>",
+        SyntaxKind::NODE_WITH => "<with>",
+        SyntaxKind::NODE_PATH => "<path>",
+        SyntaxKind::NODE_HAS_ATTR => "<has attribute>",
+        _ => "<unknown>",
     }
 }
 
@@ -415,12 +526,12 @@ fn print_labels(
 
             if is_singleline {
                 let next_newline = label.span.file.content[offset_line..]
-                    .chars()
+                    .char_indices()
                     .skip(1)
-                    .position(|c| c == '\n')
-                    .unwrap_or_else(|| label.span.file.content.len() - offset_line)
-                    + offset_line
-                    + 1;
+                    .find(|(_, c)| *c == '\n')
+                    .map_or(label.span.file.content.len(), |(index, _)| {
+                        offset_line + index
+                    });
 
                 f.write_fmt(format_args!(
                     "\n{backtrace_padding}\x1b[1;34m{line: >max_line_width$} | \x1b[0m{context}",
@@ -431,9 +542,9 @@ fn print_labels(
                 let next_newline = {
                     let mut line = start_line;
                     label.span.file.content[offset_line..]
-                        .chars()
+                        .char_indices()
                         .skip(1)
-                        .position(|c| match c {
+                        .find(|(_, c)| match c {
                             '\n' if line >= label.span.end.0 => true,
                             '\n' => {
                                 line += 1;
@@ -441,9 +552,9 @@ fn print_labels(
                             }
                             _ => false,
                         })
-                        .unwrap_or_else(|| label.span.file.content.len() - offset_line)
-                        + offset_line
-                        + 1
+                        .map_or(label.span.file.content.len(), |(index, _)| {
+                            offset_line + index
+                        })
                 };
 
                 let mut line = start_line;
@@ -10,7 +10,8 @@ pub use file::FileScope;
 
 use crate::result::{NixLabel, NixLabelKind, NixLabelMessage, NixSpan};
 use crate::{
-    builtins, flake, NixAttrSet, NixBacktrace, NixResult, NixValue, NixValueWrapped, NixVar,
+    builtins, flake, NixAttrSet, NixBacktrace, NixResult, NixSettings, NixValue, NixValueWrapped,
+    NixVar,
 };
 
 #[derive(Debug)]
@@ -30,7 +31,10 @@ impl Scope {
         }
 
         let mut globals = NixAttrSet::new();
-        let builtins = builtins::get_builtins();
+        let mut builtins = builtins::get_builtins();
+
+        NixSettings::current()
+            .insert_host_builtins(builtins.as_attr_set_mut().unwrap(), &mut globals);
 
         insert!(globals; abort = builtins::Abort::generate());
         insert!(globals; baseNameOf = builtins::BaseNameOf::generate());
@@ -100,11 +104,12 @@ impl Scope {
     }
 
     pub fn import_path(backtrace: &NixBacktrace, path: impl AsRef<Path>) -> NixResult {
-        let path = path.as_ref();
-
-        println!("Importing {path:#?}");
+        // Checked after normalization, so a symlinked `default.nix` cannot
+        // escape from the allowed paths
+        let path = FileScope::normalize_path(path);
+        let path = NixSettings::current().check_path(backtrace, path)?;
 
-        let (backtrace, result) = FileScope::get_file(Some(backtrace.clone()), path)?;
+        let (backtrace, result) = FileScope::get_file(Some(backtrace.clone()), &path)?;
 
         if path.file_name() == Some(OsStr::new("flake.nix")) {
             flake::resolve_flake(&backtrace, result)
@@ -152,8 +157,8 @@ impl Scope {
         value: NixValueWrapped,
         mut attr_path: impl Iterator<Item = ast::Attr>,
     ) -> NixResult<NixResult<NixValueWrapped>> {
-        if let Some(attr) = attr_path.next() {
-            let attr = self.resolve_attr(backtrace, &attr)?;
+        if let Some(attr_node) = attr_path.next() {
+            let attr = self.resolve_attr(backtrace, &attr_node)?;
 
             let set_value = match value.borrow().get(backtrace, &attr) {
                 Ok(v) => v,
@@ -165,7 +170,12 @@ impl Scope {
                 // as empty `AttrSet`
                 let (last, _) = value
                     .borrow_mut()
-                    .insert(attr, NixValue::AttrSet(NixAttrSet::new()).wrap_var())
+                    .insert(
+                        attr,
+                        NixValue::AttrSet(NixAttrSet::new())
+                            .wrap_var()
+                            .with_position(NixSpan::from_ast_node(&self.file, &attr_node)),
+                    )
                     .unwrap();
 
                 return self.resolve_attr_set_path(backtrace, last.resolve(backtrace)?, attr_path);
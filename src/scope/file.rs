@@ -6,8 +6,8 @@ use std::rc::Rc;
 use std::{fmt, fs};
 
 use crate::{
-    LazyNixValue, NixBacktrace, NixBacktraceKind, NixError, NixResult, NixSpan, NixValueWrapped,
-    NixVar,
+    LazyNixValue, NixAttrSet, NixBacktrace, NixBacktraceKind, NixError, NixResult, NixSpan,
+    NixValue, NixValueWrapped, NixVar,
 };
 
 use super::Scope;
@@ -31,7 +31,7 @@ impl fmt::Debug for FileScope {
 }
 
 impl FileScope {
-    fn normalize_path(path: impl AsRef<Path>) -> PathBuf {
+    pub fn normalize_path(path: impl AsRef<Path>) -> PathBuf {
         let mut path = path.as_ref().to_path_buf();
 
         if path.is_dir() {
@@ -69,7 +69,7 @@ impl FileScope {
                             content: fs::read_to_string(&path).unwrap(),
                             path,
                         })
-                        .raw_evaluate(backtrace)?;
+                        .raw_evaluate(backtrace, NixAttrSet::new())?;
 
                         e.insert((span, out.clone()));
 
@@ -84,15 +84,72 @@ impl FileScope {
         })
     }
 
+    /// Forgets the files already evaluated, so they are read again
+    pub fn clear_cache() {
+        FILE_CACHE.with(|file_cache| file_cache.borrow_mut().clear());
+    }
+
     pub fn repl_file(path: PathBuf, content: String) -> NixResult<(NixBacktrace, NixValueWrapped)> {
+        Self::eval_with_variables(path, content, NixAttrSet::new())
+    }
+
+    /// Like [`FileScope::repl_file`], with `variables` in scope
+    pub fn eval_with_variables(
+        path: PathBuf,
+        content: String,
+        variables: NixAttrSet,
+    ) -> NixResult<(NixBacktrace, NixValueWrapped)> {
+        Self::parse_with_variables(path, content, variables)
+            .and_then(|(backtrace, var)| Ok((backtrace.clone(), var.resolve(&backtrace)?)))
+    }
+
+    /// Like [`FileScope::eval_with_variables`], without evaluating it, so only
+    /// syntax errors are reported
+    pub fn parse_with_variables(
+        path: PathBuf,
+        content: String,
+        variables: NixAttrSet,
+    ) -> NixResult<(NixBacktrace, NixVar)> {
         Rc::new(FileScope { path, content })
-            .raw_evaluate(None.into())
-            .and_then(|r| Ok((r.0.clone(), r.2.resolve(&r.0)?)))
+            .raw_evaluate(None.into(), variables)
+            .map(|(backtrace, _, var)| (backtrace, var))
+    }
+
+    /// Evaluates `content` with the variables of `scope`, like an expression
+    /// written inside of it
+    pub fn eval_in_scope(
+        path: PathBuf,
+        content: String,
+        scope: &Rc<Scope>,
+    ) -> NixResult<(NixBacktrace, NixValueWrapped)> {
+        let file = Rc::new(FileScope { path, content });
+
+        let root = rnix::Root::parse(&file.content)
+            .ok()
+            .map_err(|error| NixError::from_parse_error(&file, error))?;
+
+        let span = Rc::new(NixSpan::from_ast_node(&file, &root));
+        let backtrace = NixBacktrace(span, None.into(), NixBacktraceKind::File);
+
+        // The spans of the expression are from the new file
+        let scope = Rc::new(Scope {
+            file,
+            variables: NixValue::AttrSet(NixAttrSet::new()).wrap(),
+            parent: Some(scope.clone()),
+            backtrace: None,
+        });
+
+        let value = scope
+            .visit_expr(&backtrace, rnix::ast::Expr::Root(root))?
+            .resolve(&backtrace)?;
+
+        Ok((backtrace, value))
     }
 
     fn raw_evaluate(
         self: Rc<Self>,
         backtrace: Rc<Option<NixBacktrace>>,
+        variables: NixAttrSet,
     ) -> NixResult<(NixBacktrace, Rc<NixSpan>, NixVar)> {
         let root = rnix::Root::parse(&self.content)
             .ok()
@@ -103,6 +160,10 @@ impl FileScope {
 
         let scope = Scope::new_with_builtins(self);
 
+        for (name, value) in variables {
+            scope.set_variable(name, value);
+        }
+
         let out =
             LazyNixValue::Pending(backtrace.clone(), scope, rnix::ast::Expr::Root(root)).wrap_var();
 
@@ -0,0 +1,155 @@
+mod debugger;
+mod interrupt;
+mod limits;
+
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::{NixAttrSet, NixBacktrace, NixHostBuiltin, NixLabelKind, NixLabelMessage, NixResult};
+
+pub use debugger::{NixDebugAction, NixDebugHandler, NixDebugger, NixFrame, NixStopReason};
+pub use interrupt::NixInterrupt;
+pub use limits::NixLimits;
+
+thread_local! {
+    static SETTINGS: RefCell<Rc<NixSettings>> = RefCell::default();
+}
+
+/// Settings shared by every evaluation running on the current thread
+#[derive(Clone, Debug, Default)]
+pub struct NixSettings {
+    /// Only allow reading paths inside of `allowed_paths`
+    pub restrict_eval: bool,
+    /// Path prefixes that can be read when `restrict_eval` is enabled
+    pub allowed_paths: Vec<PathBuf>,
+    /// Parse the dates and times of `builtins.fromTOML` as
+    /// `{ _type = "timestamp"; value = "..."; }`, like the
+    /// `parse-toml-timestamps` experimental feature of Nix
+    pub parse_toml_timestamps: bool,
+    pub limits: NixLimits,
+    pub interrupt: NixInterrupt,
+    /// Stops the evaluation at breakpoints and errors
+    pub debugger: Option<NixDebugger>,
+    /// Builtins defined by the host, and if they are also available
+    /// without the `builtins.` prefix
+    host_builtins: Vec<(NixHostBuiltin, bool)>,
+}
+
+impl NixSettings {
+    pub fn current() -> Rc<NixSettings> {
+        SETTINGS.with(|settings| settings.borrow().clone())
+    }
+
+    /// Runs `f` with the settings of the current thread without cloning them,
+    /// for the checks done on every expression
+    pub(crate) fn with<T>(f: impl FnOnce(&NixSettings) -> T) -> T {
+        SETTINGS.with(|settings| f(&settings.borrow()))
+    }
+
+    /// Replace the settings used by the evaluations of the current thread,
+    /// restarting the counters of the limits
+    pub fn install(self) {
+        Self::install_shared(Rc::new(self));
+    }
+
+    /// Like [`NixSettings::install`], the same settings can be resumed later
+    /// with [`NixSettings::resume`]
+    pub fn install_shared(this: Rc<Self>) {
+        SETTINGS.with(|settings| *settings.borrow_mut() = this);
+        NixLimits::reset();
+    }
+
+    /// Use `this` again to continue an evaluation started with it, without
+    /// restarting the counters of the limits if it's still installed
+    pub fn resume(this: &Rc<Self>) {
+        SETTINGS.with(|settings| {
+            if !Rc::ptr_eq(&settings.borrow(), this) {
+                *settings.borrow_mut() = this.clone();
+            }
+        });
+    }
+
+    /// Add a builtin defined by the host to `builtins`, and also to the
+    /// global scope if `global` is set
+    pub fn register_builtin(&mut self, builtin: NixHostBuiltin, global: bool) {
+        self.host_builtins.push((builtin, global));
+    }
+
+    /// Inserts the registered host builtins into the `builtins` set and the
+    /// global scope
+    pub fn insert_host_builtins(&self, builtins: &mut NixAttrSet, globals: &mut NixAttrSet) {
+        for (builtin, global) in &self.host_builtins {
+            builtin.insert_into(builtins);
+
+            if *global {
+                builtin.insert_into(globals);
+            }
+        }
+    }
+
+    /// Returns the path that should be read, or `None` if restricted mode
+    /// doesn't allow to read it.
+    ///
+    /// In restricted mode both `path` and the allowed prefixes are
+    /// canonicalized before comparing them, so `..` components and symlinks
+    /// cannot escape from an allowed prefix.
+    pub fn allowed_path(&self, path: impl AsRef<Path>) -> Option<PathBuf> {
+        let path = path.as_ref();
+
+        if !self.restrict_eval {
+            return Some(path.to_path_buf());
+        }
+
+        let path = canonicalize(path)?;
+
+        self.allowed_paths
+            .iter()
+            .filter_map(|allowed| canonicalize(allowed))
+            .any(|allowed| path.starts_with(allowed))
+            .then_some(path)
+    }
+
+    pub fn check_path(
+        &self,
+        backtrace: &NixBacktrace,
+        path: impl AsRef<Path>,
+    ) -> NixResult<PathBuf> {
+        let path = path.as_ref();
+
+        self.allowed_path(path).ok_or_else(|| {
+            backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::RestrictedPath,
+                format!(
+                    "Access to path '\x1b[1;95m{}\x1b[0m' is forbidden in restricted mode",
+                    path.display()
+                ),
+            )
+        })
+    }
+}
+
+/// Like [`Path::canonicalize`], but it also works for paths that don't exist
+/// yet by canonicalizing the deepest ancestor that exists
+fn canonicalize(path: &Path) -> Option<PathBuf> {
+    if let Ok(path) = path.canonicalize() {
+        return Some(path);
+    }
+
+    let mut rest = vec![];
+    let mut ancestor = path;
+
+    loop {
+        // `file_name` is `None` for `..`, which cannot be resolved
+        // without the directory existing
+        rest.push(ancestor.file_name()?);
+        ancestor = ancestor.parent()?;
+
+        if let Ok(mut out) = ancestor.canonicalize() {
+            out.extend(rest.iter().rev());
+
+            return Some(out);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::{NixBacktrace, NixError, NixResult, Scope};
+
+use super::NixSettings;
+
+/// Expression being evaluated, with the variables it can access
+#[derive(Clone, Debug)]
+pub struct NixFrame {
+    pub scope: Rc<Scope>,
+    pub backtrace: NixBacktrace,
+}
+
+/// Why the evaluation stopped
+#[derive(Clone, Copy, Debug)]
+pub enum NixStopReason<'a> {
+    /// A breakpoint set with [`NixDebugger::add_breakpoint`]
+    Breakpoint,
+    /// After [`NixDebugAction::Step`]
+    Step,
+    /// A call to `builtins.break`
+    Break,
+    /// The first expression failing with this error
+    Error(&'a NixError),
+}
+
+/// What the evaluation does after stopping
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NixDebugAction {
+    /// Until the next breakpoint, error or `builtins.break`
+    Continue,
+    /// Until the next expression
+    Step,
+}
+
+/// Called every time the evaluation stops
+pub trait NixDebugHandler {
+    /// `frames` are the expressions being evaluated, the innermost last. The
+    /// evaluation is paused until this returns, and evaluating expressions in
+    /// a frame doesn't stop again.
+    fn stop(
+        &self,
+        debugger: &NixDebugger,
+        reason: NixStopReason,
+        frames: &[NixFrame],
+    ) -> NixDebugAction;
+}
+
+/// Stops the evaluation at breakpoints, errors and calls to `builtins.break`,
+/// like `--debugger` of Nix
+#[derive(Clone)]
+pub struct NixDebugger(Rc<NixDebuggerState>);
+
+struct NixDebuggerState {
+    handler: Box<dyn NixDebugHandler>,
+    /// Files and lines where the evaluation stops
+    breakpoints: RefCell<Vec<(PathBuf, usize)>>,
+    frames: RefCell<Vec<NixFrame>>,
+    stepping: Cell<bool>,
+    /// Inside of the handler
+    stopped: Cell<bool>,
+    /// An error was already handled, and it's going up through the frames
+    unwinding: Cell<bool>,
+    /// Line of the last breakpoint, it stops only once per line
+    last_breakpoint: RefCell<Option<(PathBuf, usize)>>,
+}
+
+/// Removes the frame of an expression when dropped
+pub struct FrameGuard(NixDebugger);
+
+impl Drop for FrameGuard {
+    fn drop(&mut self) {
+        self.0 .0.frames.borrow_mut().pop();
+    }
+}
+
+impl fmt::Debug for NixDebugger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NixDebugger")
+            .field("breakpoints", &self.0.breakpoints.borrow())
+            .finish()
+    }
+}
+
+impl NixDebugger {
+    pub fn new(handler: impl NixDebugHandler + 'static) -> Self {
+        Self(Rc::new(NixDebuggerState {
+            handler: Box::new(handler),
+            breakpoints: RefCell::default(),
+            frames: RefCell::default(),
+            stepping: Cell::new(false),
+            stopped: Cell::new(false),
+            unwinding: Cell::new(false),
+            last_breakpoint: RefCell::default(),
+        }))
+    }
+
+    /// Stops before evaluating the expressions starting at `line` of `path`
+    pub fn add_breakpoint(&self, path: impl AsRef<Path>, line: usize) {
+        let path = path.as_ref().to_path_buf();
+        self.0.breakpoints.borrow_mut().push((path, line));
+    }
+
+    pub fn remove_breakpoint(&self, path: impl AsRef<Path>, line: usize) -> bool {
+        let mut breakpoints = self.0.breakpoints.borrow_mut();
+        let len = breakpoints.len();
+
+        breakpoints.retain(|(p, l)| !(p == path.as_ref() && *l == line));
+
+        breakpoints.len() != len
+    }
+
+    pub fn breakpoints(&self) -> Vec<(PathBuf, usize)> {
+        self.0.breakpoints.borrow().clone()
+    }
+
+    /// Called before evaluating an expression, stops at breakpoints and
+    /// steps. The frame is removed when the guard is dropped.
+    pub(crate) fn enter(&self, scope: &Rc<Scope>, backtrace: &NixBacktrace) -> Option<FrameGuard> {
+        let state = &self.0;
+
+        if state.stopped.get() {
+            return None;
+        }
+
+        state.frames.borrow_mut().push(NixFrame {
+            scope: scope.clone(),
+            backtrace: backtrace.clone(),
+        });
+
+        let span = &backtrace.0;
+        let location = (span.file.path.clone(), span.start.0);
+
+        let is_breakpoint = state.breakpoints.borrow().contains(&location);
+        let is_new_line = state.last_breakpoint.borrow().as_ref() != Some(&location);
+
+        if is_new_line {
+            *state.last_breakpoint.borrow_mut() = None;
+        }
+
+        if state.stepping.get() {
+            self.stop(NixStopReason::Step);
+        } else if is_breakpoint && is_new_line {
+            *state.last_breakpoint.borrow_mut() = Some(location);
+            self.stop(NixStopReason::Breakpoint);
+        }
+
+        Some(FrameGuard(self.clone()))
+    }
+
+    /// Called with the result of an expression, before removing its frame.
+    /// Stops at the innermost expression that failed.
+    pub(crate) fn leave<T>(&self, result: &NixResult<T>) {
+        if self.0.stopped.get() {
+            return;
+        }
+
+        match result {
+            Ok(_) => self.0.unwinding.set(false),
+            Err(error) => {
+                if !self.0.unwinding.replace(true) {
+                    self.stop(NixStopReason::Error(error));
+                }
+            }
+        }
+    }
+
+    /// Stops at a call to `builtins.break`
+    pub(crate) fn break_here() {
+        if let Some(debugger) = NixSettings::current().debugger.clone() {
+            if !debugger.0.stopped.get() {
+                debugger.stop(NixStopReason::Break);
+            }
+        }
+    }
+
+    fn stop(&self, reason: NixStopReason) {
+        self.0.stopped.set(true);
+
+        let action = {
+            let frames = self.0.frames.borrow();
+            self.0.handler.stop(self, reason, &frames)
+        };
+
+        self.0.stepping.set(action == NixDebugAction::Step);
+        self.0.stopped.set(false);
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::{NixBacktrace, NixLabelKind, NixLabelMessage, NixResult};
+
+use super::NixSettings;
+
+/// Cancellation token of the evaluation.
+///
+/// It can be cloned and sent to other threads, the evaluation stops with an
+/// error the next time it checks the token after [`NixInterrupt::interrupt`]
+/// was called.
+#[derive(Clone, Debug, Default)]
+pub struct NixInterrupt(Arc<AtomicBool>);
+
+impl NixInterrupt {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Allow evaluating again after an interruption
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    /// Fails if the evaluation of the current thread was interrupted
+    pub fn check(backtrace: &NixBacktrace) -> NixResult<()> {
+        NixSettings::with(|settings| settings.interrupt.check_token(backtrace))
+    }
+
+    /// Fails if this token was interrupted
+    pub(crate) fn check_token(&self, backtrace: &NixBacktrace) -> NixResult<()> {
+        if self.is_interrupted() {
+            Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Interrupted,
+                "Evaluation interrupted",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use crate::{NixBacktrace, NixError, NixLabelKind, NixLabelMessage, NixResult};
+
+/// How many expressions are visited between two checks of the clock
+const TIMEOUT_CHECK_INTERVAL: usize = 1024;
+
+thread_local! {
+    static COUNTERS: NixCounters = NixCounters::default();
+}
+
+/// Resource limits of an evaluation, `None` means unlimited
+#[derive(Clone, Copy, Debug)]
+pub struct NixLimits {
+    /// Maximum amount of nested expressions being evaluated at the same time.
+    ///
+    /// Every level takes a few kilobytes of stack, so the evaluation thread
+    /// should have a stack big enough for this depth. The default is enough
+    /// for a stack of 8 MiB, like the one of the main thread, even in debug
+    /// builds.
+    pub max_depth: Option<usize>,
+    /// Maximum amount of lazy values that can be forced
+    pub max_thunks: Option<usize>,
+    /// Maximum amount of values that can be allocated
+    pub max_values: Option<usize>,
+    /// Maximum wall-clock time since the settings were installed
+    pub timeout: Option<Duration>,
+}
+
+impl Default for NixLimits {
+    fn default() -> Self {
+        Self {
+            // Nix allows 10000, the CLI raises it with a bigger stack
+            max_depth: Some(1000),
+            max_thunks: None,
+            max_values: None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct NixCounters {
+    depth: Cell<usize>,
+    visited: Cell<usize>,
+    thunks: Cell<usize>,
+    values: Cell<usize>,
+    started: Cell<Option<Instant>>,
+}
+
+/// Decrements the evaluation depth when dropped
+pub struct DepthGuard(());
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        COUNTERS.with(|counters| counters.depth.set(counters.depth.get() - 1));
+    }
+}
+
+impl NixLimits {
+    /// Restart the counters of the current thread, like before a new
+    /// evaluation
+    pub fn reset() {
+        COUNTERS.with(|counters| {
+            counters.depth.set(0);
+            counters.visited.set(0);
+            counters.thunks.set(0);
+            counters.values.set(0);
+            counters.started.set(Some(Instant::now()));
+        })
+    }
+
+    /// Called before visiting an expression with the installed limits, the
+    /// returned guard should live until the expression is evaluated
+    pub(crate) fn enter(&self, backtrace: &NixBacktrace) -> NixResult<DepthGuard> {
+        let limits = self;
+
+        COUNTERS.with(|counters| {
+            let depth = counters.depth.get() + 1;
+
+            if limits.max_depth.is_some_and(|max| depth > max) {
+                return Err(limit_error(
+                    backtrace,
+                    "stack overflow (possible infinite recursion)",
+                ));
+            }
+
+            if limits
+                .max_values
+                .is_some_and(|max| counters.values.get() > max)
+            {
+                return Err(limit_error(backtrace, "Too many values allocated"));
+            }
+
+            let visited = counters.visited.get() + 1;
+            counters.visited.set(visited);
+
+            if let (Some(timeout), Some(started)) = (limits.timeout, counters.started.get()) {
+                if visited % TIMEOUT_CHECK_INTERVAL == 0 && started.elapsed() > timeout {
+                    return Err(limit_error(backtrace, "Evaluation timed out"));
+                }
+            }
+
+            counters.depth.set(depth);
+
+            Ok(DepthGuard(()))
+        })
+    }
+
+    /// Called before forcing a lazy value with the installed limits
+    pub(crate) fn force_thunk(&self, backtrace: &NixBacktrace) -> NixResult<()> {
+        let limits = self;
+
+        COUNTERS.with(|counters| {
+            let thunks = counters.thunks.get() + 1;
+            counters.thunks.set(thunks);
+
+            if limits.max_thunks.is_some_and(|max| thunks > max) {
+                Err(limit_error(backtrace, "Too many values forced"))
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Called for every allocated value, the limit is checked by
+    /// [`NixLimits::enter`] because allocations cannot fail
+    pub fn allocate() {
+        COUNTERS.with(|counters| counters.values.set(counters.values.get() + 1))
+    }
+}
+
+fn limit_error(backtrace: &NixBacktrace, message: &str) -> NixError {
+    backtrace.to_error(NixLabelKind::Error, NixLabelMessage::LimitExceeded, message)
+}
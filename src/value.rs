@@ -1,21 +1,24 @@
 mod lazy;
+mod print;
 mod var;
 
 use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fmt::{self, Write};
-use std::ops::Deref;
 use std::path::PathBuf;
 use std::rc::Rc;
 
 pub use lazy::LazyNixValue;
+pub use print::NixPrinter;
 pub use var::NixVar;
 
 use rnix::ast;
 
 use crate::builtins::NixBuiltin;
 use crate::scope::Scope;
-use crate::{NixBacktrace, NixError, NixResult};
+use crate::{
+    NixBacktrace, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixLimits, NixResult, NixSpan,
+};
 
 #[derive(Clone, PartialEq, Eq)]
 pub enum NixLambdaParam {
@@ -90,121 +93,17 @@ impl fmt::Debug for NixValue {
     }
 }
 
+/// Nix code of the value in a single line, or with [`NixPrinter::pretty`]
+/// with `{:#}`
 impl fmt::Display for NixValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            NixValue::AttrSet(set) => {
-                let width = f.width().unwrap_or_default();
-                let outside_pad = " ".repeat(width);
-
-                let width = width + 2;
-                let pad = " ".repeat(width);
-
-                f.write_char('{')?;
-
-                if f.alternate() {
-                    f.write_char('\n')?;
-                }
-
-                for (key, value) in set {
-                    let value = value.as_concrete().unwrap_or_else(|| {
-                        eprintln!("Can't display something unresolved, run `.resolve_set()` before display it");
-                        std::process::exit(1)
-                    });
-
-                    let value = value.as_ref().borrow();
-                    let value = value.deref();
-
-                    if f.alternate() {
-                        f.write_str(&pad)?;
-                    } else {
-                        f.write_char(' ')?;
-                    }
-
-                    f.write_str(key)?;
-                    f.write_str(" = ")?;
-
-                    if f.alternate() {
-                        f.write_fmt(format_args!("{value:#width$}"))?;
-                    } else {
-                        fmt::Display::fmt(value, f)?;
-                    }
-
-                    f.write_char(';')?;
-
-                    if f.alternate() {
-                        f.write_char('\n')?;
-                    }
-                }
-
-                if f.alternate() {
-                    f.write_str(&outside_pad)?;
-                } else {
-                    f.write_char(' ')?;
-                }
-
-                f.write_char('}')
-            }
-            NixValue::Bool(true) => f.write_str("true"),
-            NixValue::Bool(false) => f.write_str("false"),
-            NixValue::Float(val) => f.write_str(&val.to_string()),
-            NixValue::Int(val) => f.write_str(&val.to_string()),
-            NixValue::Lambda(NixLambda::Apply(..)) => f.write_str("<lamda>"),
-            NixValue::Lambda(NixLambda::Builtin(builtin)) => fmt::Display::fmt(builtin, f),
-            NixValue::List(list) => {
-                let width = f.width().unwrap_or_default();
-                let outside_pad = " ".repeat(width);
-
-                let width = width + 2;
-                let pad = " ".repeat(width);
-
-                f.write_char('[')?;
-
-                if f.alternate() {
-                    f.write_char('\n')?;
-                }
-
-                for value in &*list.0 {
-                    let value = value.as_concrete().unwrap_or_else(|| {
-                        eprintln!("Can't display something unresolved, run `.resolve_set()` before display it");
-                        std::process::exit(1)
-                    });
-                    let value = value.as_ref().borrow();
-                    let value = value.deref();
-
-                    if f.alternate() {
-                        f.write_str(&pad)?;
-                    } else {
-                        f.write_char(' ')?;
-                    }
-
-                    if f.alternate() {
-                        f.write_fmt(format_args!("{value:#width$}"))?;
-                    } else {
-                        fmt::Display::fmt(value, f)?;
-                    }
-
-                    if f.alternate() {
-                        f.write_char('\n')?;
-                    }
-                }
-
-                if f.alternate() {
-                    f.write_str(&outside_pad)?;
-                } else {
-                    f.write_char(' ')?;
-                }
+        let printer = if f.alternate() {
+            NixPrinter::pretty()
+        } else {
+            NixPrinter::default()
+        };
 
-                f.write_char(']')
-            }
-            NixValue::Null => f.write_str("null"),
-            NixValue::Path(path) => f.write_fmt(format_args!("{}", path.display())),
-            NixValue::String(s) => {
-                f.write_char('"')?;
-                f.write_str(s)?;
-                f.write_char('"')
-            }
-        }
+        f.write_str(&printer.print(self))
     }
 }
 
@@ -235,7 +134,19 @@ impl NixValue {
             (Self::Int(v1), Self::Float(v2)) => Ok(*v1 as f64 == *v2),
             // Functions are incomparable.
             (Self::Lambda(..), Self::Lambda(..)) => Ok(false),
-            (Self::List(v1), Self::List(v2)) => Ok(v1 == v2),
+            (Self::List(v1), Self::List(v2)) => {
+                if v1.0.len() != v2.0.len() {
+                    return Ok(false);
+                }
+
+                for (a, b) in v1.0.iter().zip(v2.0.iter()) {
+                    if !a.try_eq(b, backtrace)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
             (Self::Null, Self::Null) => Ok(true),
             (Self::Path(v1), Self::Path(v2)) => Ok(v1 == v2),
             (Self::String(v1), Self::String(v2)) => Ok(v1 == v2),
@@ -246,11 +157,16 @@ impl NixValue {
     }
 
     pub fn wrap(self) -> NixValueWrapped {
+        NixLimits::allocate();
+
         Rc::new(RefCell::new(self))
     }
 
     pub fn wrap_var(self) -> NixVar {
-        NixVar(Rc::new(RefCell::new(LazyNixValue::Concrete(self.wrap()))))
+        NixVar(
+            Rc::new(RefCell::new(LazyNixValue::Concrete(self.wrap()))),
+            None,
+        )
     }
 
     pub fn get(&self, backtrace: &NixBacktrace, attr: &String) -> Result<Option<NixVar>, NixError> {
@@ -405,6 +321,62 @@ impl NixValue {
             None
         }
     }
+
+    /// Calls `this` with the values of `args` that it takes if it's a function
+    /// with a pattern, like `autoCallFunction` of Nix. The arguments that
+    /// aren't in `args` must have a default value.
+    pub fn auto_call(
+        this: &NixValueWrapped,
+        backtrace: &NixBacktrace,
+        args: &NixAttrSet,
+    ) -> NixResult {
+        let (lambda, argument) = {
+            let value = this.borrow();
+
+            let Some(lambda @ NixLambda::Apply(scope, NixLambdaParam::Pattern(pattern), _)) =
+                value.as_lambda()
+            else {
+                return Ok(this.clone());
+            };
+
+            let mut argument = NixAttrSet::new();
+
+            for entry in pattern.pat_entries() {
+                let name = entry.ident().unwrap().to_string();
+
+                if let Some(var) = args.get(&name) {
+                    argument.insert(name, var.clone());
+                } else if entry.default().is_none() {
+                    let span = Rc::new(NixSpan::from_ast_node(&scope.file, &entry));
+                    let label = NixLabel::new(
+                        span,
+                        NixLabelMessage::Custom("Argument without a value".to_owned()),
+                        NixLabelKind::Error,
+                    );
+
+                    return Err(backtrace.to_labeled_error(
+                        vec![label],
+                        format!(
+                            "Cannot call the function automatically, pass '{name}' with \
+                             '--arg {name} <expr>' or '--argstr {name} <string>'"
+                        ),
+                    ));
+                }
+            }
+
+            if pattern.ellipsis_token().is_some() {
+                for (name, var) in args {
+                    argument.entry(name.clone()).or_insert_with(|| var.clone());
+                }
+            }
+
+            (lambda.clone(), argument)
+        };
+
+        lambda
+            .call(backtrace, NixValue::AttrSet(argument).wrap_var())?
+            .resolve(backtrace)
+    }
 }
 
 impl PartialEq for NixLambda {
@@ -502,6 +474,6 @@ impl From<NixValue> for NixVar {
 
 impl From<NixValueWrapped> for NixVar {
     fn from(value: NixValueWrapped) -> Self {
-        Self(Rc::new(RefCell::new(LazyNixValue::Concrete(value))))
+        Self(Rc::new(RefCell::new(LazyNixValue::Concrete(value))), None)
     }
 }
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
@@ -6,8 +7,8 @@ use std::rc::Rc;
 use rnix::ast;
 
 use crate::{
-    NixBacktrace, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult, NixSpan,
-    NixValueWrapped, NixVar, Scope,
+    NixBacktrace, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult, NixSettings,
+    NixSpan, NixValueWrapped, NixVar, Scope,
 };
 
 use super::{NixAttrSet, NixLambda, NixValue};
@@ -16,10 +17,7 @@ use super::{NixAttrSet, NixLambda, NixValue};
 pub enum LazyNixValue {
     Concrete(NixValueWrapped),
     Pending(NixBacktrace, Rc<Scope>, ast::Expr),
-    Eval(
-        NixBacktrace,
-        Rc<RefCell<Option<Box<dyn FnOnce(&NixBacktrace) -> NixResult>>>>,
-    ),
+    Eval(NixBacktrace, Rc<dyn Fn(&NixBacktrace) -> NixResult>),
     /// Partial resolve for update operator (`<expr> // <expr>`)
     UpdateResolve {
         lhs: NixValueWrapped,
@@ -76,11 +74,8 @@ impl LazyNixValue {
 }
 
 impl LazyNixValue {
-    pub fn new_eval(
-        backtrace: NixBacktrace,
-        fun: Box<dyn FnOnce(&NixBacktrace) -> NixResult>,
-    ) -> Self {
-        LazyNixValue::Eval(backtrace, Rc::new(RefCell::new(Option::Some(fun))))
+    pub fn new_eval(backtrace: NixBacktrace, fun: Box<dyn Fn(&NixBacktrace) -> NixResult>) -> Self {
+        LazyNixValue::Eval(backtrace, Rc::from(fun))
     }
 
     pub fn new_callback_eval(backtrace: &NixBacktrace, callback: NixLambda, value: NixVar) -> Self {
@@ -91,34 +86,36 @@ impl LazyNixValue {
                 LazyNixValue::new_eval(
                     NixBacktrace::new_none(span.clone(), Some(backtrace.clone())),
                     Box::new(move |backtrace| {
-                        let scope = scope.new_child();
+                        let scope = scope.clone().new_child();
 
                         match param {
-                            crate::NixLambdaParam::Ident(ident) => {
-                                scope.set_variable(ident, value);
+                            crate::NixLambdaParam::Ident(ref ident) => {
+                                scope.set_variable(ident.clone(), value.clone());
                             }
                             crate::NixLambdaParam::Pattern(_) => {
                                 return Err(crate::NixError::todo(
-                                    span,
+                                    span.clone(),
                                     "Pattern lambda param",
                                     Some((&*backtrace).clone()),
                                 ))
                             }
                         };
 
-                        scope.visit_expr(backtrace, expr)?.resolve(backtrace)
+                        scope
+                            .visit_expr(backtrace, expr.clone())?
+                            .resolve(backtrace)
                     }),
                 )
             }
             NixLambda::Builtin(builtin) => LazyNixValue::new_eval(
                 backtrace.clone(),
-                Box::new(move |backtrace| builtin.run(backtrace, value)),
+                Box::new(move |backtrace| builtin.run(backtrace, value.clone())),
             ),
         }
     }
 
     pub fn wrap_var(self) -> NixVar {
-        NixVar(Rc::new(RefCell::new(self)))
+        NixVar(Rc::new(RefCell::new(self)), None)
     }
 
     pub fn as_concrete(&self) -> Option<NixValueWrapped> {
@@ -131,6 +128,18 @@ impl LazyNixValue {
         }
     }
 
+    /// Backtrace of the expression that defines the value, if it's not
+    /// resolved yet
+    pub fn backtrace(&self) -> Option<NixBacktrace> {
+        match self {
+            LazyNixValue::Concrete(_) => None,
+            LazyNixValue::Pending(backtrace, ..)
+            | LazyNixValue::Eval(backtrace, _)
+            | LazyNixValue::UpdateResolve { backtrace, .. }
+            | LazyNixValue::Resolving(backtrace) => Some(backtrace.clone()),
+        }
+    }
+
     pub fn resolve(this: &Rc<RefCell<Self>>, backtrace: &NixBacktrace) -> NixResult {
         if let LazyNixValue::Concrete(value) = &*this.borrow() {
             return Ok(value.clone());
@@ -163,8 +172,23 @@ impl LazyNixValue {
             }
         };
 
+        NixSettings::with(|settings| {
+            settings.interrupt.check_token(backtrace)?;
+            settings.limits.force_thunk(backtrace)
+        })?;
+
         let old = this.replace(LazyNixValue::Resolving(backtrace.clone()));
+        let result = Self::resolve_old(this, old.clone(), backtrace);
+
+        // A failed value can be resolved again, like after `builtins.tryEval`
+        if result.is_err() {
+            this.replace(old);
+        }
+
+        result
+    }
 
+    fn resolve_old(this: &Rc<RefCell<Self>>, old: Self, backtrace: &NixBacktrace) -> NixResult {
         match old {
             LazyNixValue::Concrete(..) | LazyNixValue::Resolving(..) => unreachable!(),
             LazyNixValue::UpdateResolve {
@@ -249,10 +273,7 @@ impl LazyNixValue {
                 Ok(value)
             }
             LazyNixValue::Eval(_, eval) => {
-                let value = eval
-                    .borrow_mut()
-                    .take()
-                    .expect("Eval cannot be called twice")(backtrace)?;
+                let value = eval(backtrace)?;
 
                 *this.borrow_mut().deref_mut() = LazyNixValue::Concrete(value.clone());
 
@@ -265,36 +286,87 @@ impl LazyNixValue {
         this: &Rc<RefCell<Self>>,
         recursive: bool,
         backtrace: &NixBacktrace,
+    ) -> NixResult {
+        let depth = if recursive { None } else { Some(1) };
+
+        Self::resolve_limited(this, depth, None, backtrace)
+    }
+
+    /// Resolves `depth` levels of sets and lists, all of them if it's `None`,
+    /// and only the first `items` values of each one
+    pub fn resolve_limited(
+        this: &Rc<RefCell<Self>>,
+        depth: Option<usize>,
+        items: Option<usize>,
+        backtrace: &NixBacktrace,
+    ) -> NixResult {
+        Self::resolve_limited_seen(this, depth, items, backtrace, &mut HashSet::new(), None)
+    }
+
+    /// Like [`LazyNixValue::resolve_limited`], but the errors of the values
+    /// inside are added to `errors` by the pointer of their variable instead
+    /// of being returned
+    pub(crate) fn resolve_limited_errors(
+        this: &Rc<RefCell<Self>>,
+        depth: Option<usize>,
+        items: Option<usize>,
+        backtrace: &NixBacktrace,
+        errors: &mut HashMap<*const (), String>,
+    ) -> NixResult {
+        Self::resolve_limited_seen(
+            this,
+            depth,
+            items,
+            backtrace,
+            &mut HashSet::new(),
+            Some(errors),
+        )
+    }
+
+    /// `seen` has the sets and lists already resolved, so cycles like
+    /// `rec { a = { inherit a; }; }` are only resolved once
+    fn resolve_limited_seen(
+        this: &Rc<RefCell<Self>>,
+        depth: Option<usize>,
+        items: Option<usize>,
+        backtrace: &NixBacktrace,
+        seen: &mut HashSet<*const RefCell<NixValue>>,
+        mut errors: Option<&mut HashMap<*const (), String>>,
     ) -> NixResult {
         let value = Self::resolve(this, backtrace)?;
 
-        if value.borrow().is_attr_set() {
-            let values = if let Some(set) = value.borrow().as_attr_set() {
-                set.values().cloned().collect::<Vec<_>>()
-            } else {
-                unreachable!()
-            };
+        if depth == Some(0) || !seen.insert(Rc::as_ptr(&value)) {
+            return Ok(value);
+        }
 
-            for var in values {
-                if recursive {
-                    var.resolve_set(true, backtrace)?;
-                } else {
-                    var.resolve(backtrace)?;
+        let values = match &*value.borrow() {
+            NixValue::AttrSet(set) => set.values().cloned().collect::<Vec<_>>(),
+            NixValue::List(list) => list.0.to_vec(),
+            _ => Vec::new(),
+        };
+
+        let depth = depth.map(|depth| depth - 1);
+
+        for var in values.iter().take(items.unwrap_or(usize::MAX)) {
+            let result = var.resolve(backtrace).and_then(|_| {
+                Self::resolve_limited_seen(
+                    &var.0,
+                    depth,
+                    items,
+                    backtrace,
+                    seen,
+                    errors.as_deref_mut(),
+                )
+            });
+
+            match (result, errors.as_deref_mut()) {
+                (Err(err), Some(errors)) => {
+                    errors.insert(Rc::as_ptr(&var.0) as *const (), err.message);
+                }
+                (result, _) => {
+                    result?;
                 }
             }
-        } else if let Some(list) = value.borrow().as_list() {
-            list.0
-                .iter()
-                .map(|var| {
-                    if recursive {
-                        var.resolve_set(true, backtrace)?;
-                    } else {
-                        var.resolve(backtrace)?;
-                    }
-
-                    Ok(())
-                })
-                .collect::<Result<(), _>>()?;
         }
 
         Ok(value)
@@ -0,0 +1,383 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::rc::Rc;
+
+use rnix::ast;
+use rowan::ast::AstNode;
+
+use crate::{LazyNixValue, NixBacktrace, NixResult, NixSpan, NixValueWrapped, NixVar};
+
+use super::{NixLambda, NixValue};
+
+/// Prints values as Nix code that evaluates to the same value.
+///
+/// Sets and lists reached again, like in a `rec` cycle, are `«repeated»`,
+/// functions are `«lambda @ file:line:col»` and values that aren't evaluated
+/// yet are `«thunk»`. With [`NixPrinter::print_limited`] the values that fail
+/// are `«error: message»`, like in `nix repl`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NixPrinter {
+    /// Spaces of each level of nesting, with 0 everything is in a single line
+    pub indent: usize,
+    /// Sets and lists longer than this are split in multiple lines, if
+    /// `indent` isn't 0
+    pub width: usize,
+}
+
+impl Default for NixPrinter {
+    /// In a single line
+    fn default() -> Self {
+        Self {
+            indent: 0,
+            width: 80,
+        }
+    }
+}
+
+impl NixPrinter {
+    /// Two spaces of indentation and 80 columns
+    pub fn pretty() -> Self {
+        Self {
+            indent: 2,
+            width: 80,
+        }
+    }
+
+    pub fn print(&self, value: &NixValue) -> String {
+        // The root can be repeated too, like in `let x = { a = x; }; in x`
+        let mut seen = HashSet::from([value as *const NixValue as *const ()]);
+        let doc = Doc::from_value(value, &mut seen, &HashMap::new());
+
+        let mut out = String::new();
+        self.write(&doc, &mut out, 0, 0);
+
+        out
+    }
+
+    /// Forces `depth` levels of sets and lists of `var` and the first `items`
+    /// values of each one before printing it, see
+    /// [`LazyNixValue::resolve_limited`]. Only an error of `var` itself is
+    /// returned
+    pub fn print_limited(
+        &self,
+        var: &NixVar,
+        depth: Option<usize>,
+        items: Option<usize>,
+        backtrace: &NixBacktrace,
+    ) -> NixResult<String> {
+        let mut errors = HashMap::new();
+        let value =
+            LazyNixValue::resolve_limited_errors(&var.0, depth, items, backtrace, &mut errors)?;
+        let value = value.borrow();
+
+        let mut seen = HashSet::from([&*value as *const NixValue as *const ()]);
+        let doc = Doc::from_value(&value, &mut seen, &errors);
+
+        let mut out = String::new();
+        self.write(&doc, &mut out, 0, 0);
+
+        Ok(out)
+    }
+
+    pub fn print_var(&self, var: &NixVar) -> String {
+        match var.as_concrete() {
+            Some(value) => self.print(&value.borrow()),
+            None => "«thunk»".to_owned(),
+        }
+    }
+
+    /// Writes `doc` starting at `column`, returns the column where it ends
+    fn write(&self, doc: &Doc, out: &mut String, level: usize, column: usize) -> usize {
+        match doc {
+            Doc::Text(text) => {
+                out.push_str(text);
+                column + text.chars().count()
+            }
+            Doc::Concat(docs) => docs
+                .iter()
+                .fold(column, |column, doc| self.write(doc, out, level, column)),
+            Doc::Block { .. } if self.indent == 0 || column + doc.width() <= self.width => {
+                let flat = doc.flat();
+                out.push_str(&flat);
+                column + flat.chars().count()
+            }
+            Doc::Block { open, items, close } => {
+                let pad = " ".repeat((level + 1) * self.indent);
+
+                out.push_str(open);
+
+                for item in items {
+                    out.push('\n');
+                    out.push_str(&pad);
+                    self.write(item, out, level + 1, pad.len());
+                }
+
+                let pad = " ".repeat(level * self.indent);
+
+                out.push('\n');
+                out.push_str(&pad);
+                out.push_str(close);
+
+                pad.len() + close.len()
+            }
+        }
+    }
+}
+
+/// Messages of the values that failed, by the pointer of their variable
+type Errors = HashMap<*const (), String>;
+
+/// Layout of a value, blocks are in a single line or one item per line
+enum Doc {
+    Text(String),
+    Concat(Vec<Doc>),
+    Block {
+        open: &'static str,
+        items: Vec<Doc>,
+        close: &'static str,
+    },
+}
+
+impl Doc {
+    fn from_var(var: &NixVar, seen: &mut HashSet<*const ()>, errors: &Errors) -> Self {
+        if let Some(message) = errors.get(&(Rc::as_ptr(&var.0) as *const ())) {
+            return Doc::Text(format!("«error: {message}»"));
+        }
+
+        match var.as_concrete() {
+            Some(value) => Self::from_wrapped(&value, seen, errors),
+            None => Doc::Text("«thunk»".to_owned()),
+        }
+    }
+
+    fn from_wrapped(
+        value: &NixValueWrapped,
+        seen: &mut HashSet<*const ()>,
+        errors: &Errors,
+    ) -> Self {
+        let value_ref = value.borrow();
+
+        if matches!(&*value_ref, NixValue::AttrSet(_) | NixValue::List(_))
+            && !seen.insert(value.as_ptr() as *const ())
+        {
+            return Doc::Text("«repeated»".to_owned());
+        }
+
+        Self::from_value(&value_ref, seen, errors)
+    }
+
+    fn from_value(value: &NixValue, seen: &mut HashSet<*const ()>, errors: &Errors) -> Self {
+        match value {
+            NixValue::AttrSet(set) => {
+                if let Some(drv_path) = derivation_path(value) {
+                    return Doc::Text(format!("«derivation {drv_path}»"));
+                }
+
+                let items = set
+                    .iter()
+                    .map(|(name, var)| {
+                        Doc::Concat(vec![
+                            Doc::Text(format!("{} = ", attr_name(name))),
+                            Doc::from_var(var, seen, errors),
+                            Doc::Text(";".to_owned()),
+                        ])
+                    })
+                    .collect();
+
+                Doc::Block {
+                    open: "{",
+                    items,
+                    close: "}",
+                }
+            }
+            NixValue::List(list) => {
+                let items = list
+                    .0
+                    .iter()
+                    .map(|var| {
+                        // `[ -1 ]` isn't valid
+                        let is_negative =
+                            var.as_concrete()
+                                .is_some_and(|value| match *value.borrow() {
+                                    NixValue::Int(int) => int < 0,
+                                    NixValue::Float(float) => float.is_sign_negative(),
+                                    _ => false,
+                                });
+
+                        let item = Doc::from_var(var, seen, errors);
+
+                        if is_negative {
+                            Doc::Concat(vec![
+                                Doc::Text("(".to_owned()),
+                                item,
+                                Doc::Text(")".to_owned()),
+                            ])
+                        } else {
+                            item
+                        }
+                    })
+                    .collect();
+
+                Doc::Block {
+                    open: "[",
+                    items,
+                    close: "]",
+                }
+            }
+            NixValue::Bool(bool) => Doc::Text(bool.to_string()),
+            NixValue::Float(float) => Doc::Text(float_literal(*float)),
+            NixValue::Int(int) => Doc::Text(int.to_string()),
+            NixValue::Lambda(NixLambda::Apply(scope, _, body)) => {
+                let lambda = body.syntax().parent().and_then(ast::Lambda::cast);
+
+                Doc::Text(match lambda {
+                    Some(lambda) => {
+                        let span = NixSpan::from_ast_node(&scope.file, &lambda);
+
+                        format!(
+                            "«lambda @ {}:{}:{}»",
+                            scope.file.path.display(),
+                            span.start.0,
+                            span.start.1 + 1
+                        )
+                    }
+                    None => "«lambda»".to_owned(),
+                })
+            }
+            NixValue::Lambda(NixLambda::Builtin(builtin)) => Doc::Text(if builtin.applied() > 0 {
+                format!("«partially applied primop {}»", builtin.get_name())
+            } else {
+                format!("«primop {}»", builtin.get_name())
+            }),
+            NixValue::Null => Doc::Text("null".to_owned()),
+            NixValue::Path(path) => Doc::Text(path_literal(path)),
+            NixValue::String(string) => Doc::Text(string_literal(string)),
+        }
+    }
+
+    /// Width in a single line
+    fn width(&self) -> usize {
+        match self {
+            Doc::Text(text) => text.chars().count(),
+            Doc::Concat(docs) => docs.iter().map(Doc::width).sum(),
+            Doc::Block { open, items, close } => {
+                open.len()
+                    + items.iter().map(|item| item.width() + 1).sum::<usize>()
+                    + 1
+                    + close.len()
+            }
+        }
+    }
+
+    fn flat(&self) -> String {
+        match self {
+            Doc::Text(text) => text.clone(),
+            Doc::Concat(docs) => docs.iter().map(Doc::flat).collect(),
+            Doc::Block { open, items, close } => {
+                let mut out = open.to_string();
+
+                for item in items {
+                    out.push(' ');
+                    out.push_str(&item.flat());
+                }
+
+                out.push(' ');
+                out.push_str(close);
+                out
+            }
+        }
+    }
+}
+
+/// `drvPath` of a derivation, if it's already evaluated
+fn derivation_path(value: &NixValue) -> Option<String> {
+    let set = value.as_attr_set()?;
+
+    let ty = set.get("type")?.as_concrete()?;
+    if ty.borrow().as_string()? != "derivation" {
+        return None;
+    }
+
+    let drv_path = set.get("drvPath")?.as_concrete()?;
+    let drv_path = drv_path.borrow().as_string()?.clone();
+
+    Some(drv_path)
+}
+
+/// Like `isValidIdentifier` of Nix, other names are quoted
+fn attr_name(name: &str) -> String {
+    const KEYWORDS: [&str; 9] = [
+        "if", "then", "else", "assert", "with", "let", "in", "rec", "inherit",
+    ];
+
+    let mut chars = name.chars();
+
+    let is_identifier = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '\''))
+        && !KEYWORDS.contains(&name);
+
+    if is_identifier {
+        name.to_owned()
+    } else {
+        string_literal(name)
+    }
+}
+
+fn string_literal(string: &str) -> String {
+    let mut out = String::with_capacity(string.len() + 2);
+    out.push('"');
+
+    let mut chars = string.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+/// Shortest literal that is still a float, like `1.0` or `1.5e-7`
+fn float_literal(float: f64) -> String {
+    if !float.is_finite() {
+        return float.to_string();
+    }
+
+    // `{:?}` always has a `.` or an exponent, but Nix needs a `.` before it
+    let mut literal = format!("{float:?}");
+
+    if let Some(exponent) = literal.find('e') {
+        if !literal[..exponent].contains('.') {
+            literal.insert_str(exponent, ".0");
+        }
+    }
+
+    literal
+}
+
+fn path_literal(path: &Path) -> String {
+    let path = path.display().to_string();
+
+    let is_literal = path.starts_with('/')
+        && !path.ends_with('/')
+        && !path.contains("//")
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '.' | '_' | '-' | '+'));
+
+    if is_literal {
+        path
+    } else {
+        format!("(/. + {})", string_literal(&path))
+    }
+}
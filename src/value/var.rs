@@ -3,12 +3,14 @@ use std::fmt;
 use std::ops::Deref;
 use std::rc::Rc;
 
-use crate::{NixBacktrace, NixResult};
+use crate::{NixBacktrace, NixResult, NixSpan};
 
 use super::{LazyNixValue, NixValueWrapped};
 
+/// Value shared by every reference to it, the second field is where it's
+/// defined, like the name of an attribute
 #[derive(Clone)]
-pub struct NixVar(pub Rc<RefCell<LazyNixValue>>);
+pub struct NixVar(pub Rc<RefCell<LazyNixValue>>, pub Option<Rc<NixSpan>>);
 
 impl fmt::Debug for NixVar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -35,10 +37,25 @@ impl NixVar {
         LazyNixValue::try_eq(&self.0, &rhs.0, backtrace)
     }
 
+    pub fn with_position(self, position: NixSpan) -> Self {
+        Self(self.0, Some(Rc::new(position)))
+    }
+
+    /// Where the value is defined, if it's known
+    pub fn position(&self) -> Option<&Rc<NixSpan>> {
+        self.1.as_ref()
+    }
+
     pub fn as_concrete(&self) -> Option<NixValueWrapped> {
         self.0.borrow().as_concrete()
     }
 
+    /// Backtrace of the expression that defines the value, if it's not
+    /// resolved yet
+    pub fn backtrace(&self) -> Option<NixBacktrace> {
+        self.0.borrow().backtrace()
+    }
+
     pub fn resolve(&self, backtrace: &NixBacktrace) -> NixResult {
         if let LazyNixValue::Concrete(value) = &*self.0.borrow() {
             return Ok(value.clone());
@@ -57,6 +74,16 @@ impl NixVar {
         LazyNixValue::resolve_set(&self.0, recursive, backtrace)
     }
 
+    /// See [`LazyNixValue::resolve_limited`]
+    pub fn resolve_limited(
+        &self,
+        depth: Option<usize>,
+        items: Option<usize>,
+        backtrace: &NixBacktrace,
+    ) -> NixResult {
+        LazyNixValue::resolve_limited(&self.0, depth, items, backtrace)
+    }
+
     // pub fn resolve_map<T>(
     //     &self,
     //     backtrace: Rc<NixBacktrace>,
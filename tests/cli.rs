@@ -0,0 +1,71 @@
+//! Tests of the options of `nix-compiler` to evaluate a file or an expression.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Runs `nix-compiler` with `args`, and returns what it printed to stdout
+fn run(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+        .args(args)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn strict() {
+    let expr = "{ a = { b = 1; }; }";
+
+    assert_eq!(
+        run(&["--depth", "1", "-e", expr]),
+        "{ a = { b = «thunk»; }; }\n"
+    );
+    assert_eq!(
+        run(&["--depth", "1", "--strict", "-e", expr]),
+        "{ a = { b = 1; }; }\n"
+    );
+    assert_eq!(run(&["--strict", "-e", expr]), "{ a = { b = 1; }; }\n");
+}
+
+#[test]
+fn depth_errors() {
+    // Only the values that fail are errors, like in `nix repl`
+    let expr = "{ a = throw \"x\"; b = 1; c = { d = throw \"y\"; }; }";
+
+    assert_eq!(
+        run(&["--depth", "1", "-e", expr]),
+        "{ a = «error: Throwing: x»; b = 1; c = { d = «thunk»; }; }\n"
+    );
+    assert_eq!(
+        run(&["--depth", "2", "-e", expr]),
+        "{ a = «error: Throwing: x»; b = 1; c = { d = «error: Throwing: y»; }; }\n"
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+        .args(["--strict", "-e", expr])
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success(), "{output:?}");
+}
+
+#[test]
+fn nix_instantiate() {
+    // The options of `nix-instantiate --eval --strict --json`, with a file
+    // and with an expression
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("nix-instantiate.nix");
+    fs::write(&file, "{ a = [ 1 \"b\" ]; c = null; }").unwrap();
+
+    assert_eq!(
+        run(&["--eval", "--strict", "--json", file.to_str().unwrap()]),
+        "{\"a\":[1,\"b\"],\"c\":null}\n"
+    );
+    assert_eq!(
+        run(&["--eval", "--strict", "--json", "-E", "{ a = 1 + 1; }"]),
+        "{\"a\":2}\n"
+    );
+}
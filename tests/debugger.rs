@@ -0,0 +1,58 @@
+//! Tests of `--debugger`, the commands are written to its stdin.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs `--debugger` on `code` with `input`, and returns what it printed
+fn debug(name: &str, code: &str, input: &str) -> String {
+    let file = std::env::temp_dir().join(format!("nix-compiler-debugger-{name}.nix"));
+    fs::write(&file, code).unwrap();
+
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+        .arg("--debugger")
+        .arg(&file)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    debugger
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = debugger.wait_with_output().unwrap();
+    fs::remove_file(&file).unwrap();
+
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
+
+#[test]
+fn inspect() {
+    let output = debug(
+        "inspect",
+        "let f = x: builtins.break (x + 1); in f 2",
+        "x\n:c\n",
+    );
+
+    assert!(output.contains("Stopped at builtins.break"), "{output}");
+    assert!(output.contains("\n2\n"), "{output}");
+    assert!(output.contains("\n3\n"), "{output}");
+}
+
+#[test]
+fn inspect_error() {
+    // The failed value is evaluated again when it's used after the break
+    let output = debug(
+        "inspect-error",
+        "let f = x: let y = x.missing; in builtins.break (y + 1); in f { }",
+        "y\n:c\n",
+    );
+
+    assert!(!output.contains("Infinite recursion"), "{output}");
+    assert!(output.contains("Attribute missing"), "{output}");
+}
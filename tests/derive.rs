@@ -0,0 +1,207 @@
+//! Tests of `#[derive(FromNix, IntoNix)]`, values are created with `nix!` and
+//! converted back to check that both derives agree.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use nix_compiler::{nix, FromNix, IntoNix, NixError, NixValue};
+
+#[derive(Debug, PartialEq, FromNix, IntoNix)]
+#[nix(rename_all = "camelCase")]
+struct Service {
+    service_name: String,
+    #[nix(rename = "port")]
+    listen_port: u16,
+    #[nix(default)]
+    workers: u32,
+    #[nix(default = "default_user")]
+    user: String,
+    group: Option<String>,
+    args: Vec<String>,
+    env: BTreeMap<String, String>,
+    root: PathBuf,
+    mode: Mode,
+    backends: Vec<Backend>,
+}
+
+fn default_user() -> String {
+    "nobody".to_owned()
+}
+
+#[derive(Debug, PartialEq, FromNix, IntoNix)]
+#[nix(rename_all = "lowercase")]
+enum Mode {
+    Debug,
+    Release,
+}
+
+#[derive(Debug, PartialEq, FromNix, IntoNix)]
+enum Backend {
+    #[nix(rename = "unix")]
+    Socket(PathBuf),
+    #[nix(rename_all = "camelCase")]
+    Tcp { host_name: String, port: u16 },
+}
+
+#[derive(Debug, FromNix)]
+struct Port {
+    #[allow(dead_code)]
+    port: u16,
+}
+
+/// Nix code that the first label of `error` points to
+fn label_text(error: &NixError) -> &str {
+    let span = &error.labels[0].span;
+    let start = span.start.2 + span.start.1;
+    let end = span.end.2 + span.end.1 + 1;
+
+    &span.file.content[start..end]
+}
+
+#[test]
+fn from_nix() {
+    let service = nix! {
+        {
+            serviceName = "web";
+            port = 8080;
+            group = "www";
+            args = [ "-v" "--color" ];
+            env = { HOME = "/var/www"; };
+            root = /srv/www;
+            mode = "release";
+            backends = [ { unix = /run/web.sock; } { Tcp = { hostName = "localhost"; port = 80; }; } ];
+        }
+    }
+    .eval_as::<Service>()
+    .unwrap();
+
+    assert_eq!(
+        service,
+        Service {
+            service_name: "web".to_owned(),
+            listen_port: 8080,
+            workers: 0,
+            user: "nobody".to_owned(),
+            group: Some("www".to_owned()),
+            args: vec!["-v".to_owned(), "--color".to_owned()],
+            env: BTreeMap::from([("HOME".to_owned(), "/var/www".to_owned())]),
+            root: PathBuf::from("/srv/www"),
+            mode: Mode::Release,
+            backends: vec![
+                Backend::Socket(PathBuf::from("/run/web.sock")),
+                Backend::Tcp {
+                    host_name: "localhost".to_owned(),
+                    port: 80,
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn optional_fields() {
+    let service = nix! {
+        {
+            serviceName = "web";
+            port = 8080;
+            workers = 4;
+            user = "www";
+            group = null;
+            args = [ ];
+            env = { };
+            root = /srv/www;
+            mode = "debug";
+            backends = [ ];
+        }
+    }
+    .eval_as::<Service>()
+    .unwrap();
+
+    assert_eq!(service.workers, 4);
+    assert_eq!(service.user, "www");
+    assert_eq!(service.group, None);
+    assert_eq!(service.mode, Mode::Debug);
+}
+
+#[test]
+fn into_nix() {
+    let service = Service {
+        service_name: "web".to_owned(),
+        listen_port: 8080,
+        workers: 2,
+        user: "nobody".to_owned(),
+        group: None,
+        args: vec!["-v".to_owned()],
+        env: BTreeMap::from([("HOME".to_owned(), "/var/www".to_owned())]),
+        root: PathBuf::from("/srv/www"),
+        mode: Mode::Debug,
+        backends: vec![
+            Backend::Socket(PathBuf::from("/run/web.sock")),
+            Backend::Tcp {
+                host_name: "localhost".to_owned(),
+                port: 80,
+            },
+        ],
+    };
+
+    assert_eq!(
+        service.into_nix().to_string(),
+        "{ args = [ \"-v\" ]; backends = [ { unix = /run/web.sock; } \
+         { Tcp = { hostName = \"localhost\"; port = 80; }; } ]; env = { HOME = \"/var/www\"; }; \
+         group = null; mode = \"debug\"; port = 8080; root = /srv/www; serviceName = \"web\"; \
+         user = \"nobody\"; workers = 2; }"
+    );
+}
+
+#[test]
+fn round_trip() {
+    let backends = vec![
+        Backend::Socket(PathBuf::from("/run/web.sock")),
+        Backend::Tcp {
+            host_name: "localhost".to_owned(),
+            port: 80,
+        },
+    ];
+
+    let value: NixValue = backends.into_nix();
+    let converted = nix! { #{value} }.eval_as::<Vec<Backend>>().unwrap();
+
+    assert_eq!(
+        converted,
+        vec![
+            Backend::Socket(PathBuf::from("/run/web.sock")),
+            Backend::Tcp {
+                host_name: "localhost".to_owned(),
+                port: 80,
+            },
+        ]
+    );
+}
+
+#[test]
+fn type_error() {
+    let error = nix! { { port = "8080"; } }.eval_as::<Port>().unwrap_err();
+
+    assert_eq!(error.message, "expected an integer but got string");
+    assert_eq!(label_text(&error), "\"8080\"");
+
+    let error = nix! { { port = 65536; } }.eval_as::<Port>().unwrap_err();
+    assert_eq!(label_text(&error), "65536");
+}
+
+#[test]
+fn missing_attribute() {
+    let error = nix! { { } }.eval_as::<Port>().unwrap_err();
+
+    assert!(error.message.contains("port"), "{}", error.message);
+}
+
+#[test]
+fn unknown_variant() {
+    let error = nix! { [ "debug" "fast" ] }
+        .eval_as::<Vec<Mode>>()
+        .unwrap_err();
+
+    assert!(error.message.contains("fast"), "{}", error.message);
+    assert_eq!(label_text(&error), "\"fast\"");
+}
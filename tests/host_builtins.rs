@@ -0,0 +1,92 @@
+//! Tests of the builtins defined by the host with
+//! `NixSettings::register_builtin`.
+
+use std::env;
+
+use nix_compiler::{
+    FileScope, LazyNixValue, NixBacktrace, NixError, NixHostBuiltin, NixPrinter, NixSettings,
+    NixValue,
+};
+
+/// Evaluates `expr` with the host builtins of [`install`], and prints it
+fn eval(expr: &str) -> Result<String, NixError> {
+    let (backtrace, value) =
+        FileScope::repl_file(env::current_dir().unwrap().join("«test»"), expr.to_owned())?;
+
+    NixPrinter::default().print_limited(
+        &LazyNixValue::Concrete(value).wrap_var(),
+        None,
+        None,
+        &backtrace,
+    )
+}
+
+/// `greet` is global, the other ones are only inside of `builtins`
+fn install() {
+    let mut settings = NixSettings::default();
+
+    settings.register_builtin(
+        NixHostBuiltin::from_fn("greet", |_: &NixBacktrace, name: String| {
+            Ok(NixValue::String(format!("Hello {name}!")).wrap())
+        }),
+        true,
+    );
+    settings.register_builtin(
+        NixHostBuiltin::from_fn("ourCompany.add", |_: &NixBacktrace, a: i64, b: i64| {
+            Ok(NixValue::Int(a + b).wrap())
+        }),
+        false,
+    );
+    settings.register_builtin(
+        NixHostBuiltin::from_fn(
+            "ourCompany.digits",
+            |_: &NixBacktrace, a: i64, b: i64, c: i64| {
+                Ok(NixValue::Int(a * 100 + b * 10 + c).wrap())
+            },
+        ),
+        false,
+    );
+
+    settings.install();
+}
+
+#[test]
+fn register() {
+    install();
+
+    assert_eq!(
+        eval(r#"[ (greet "a") (builtins.greet "b") (builtins.ourCompany.add 1 2) ]"#).unwrap(),
+        r#"[ "Hello a!" "Hello b!" 3 ]"#
+    );
+
+    let error = eval("ourCompany.add 1 2").unwrap_err();
+    assert!(error.message.contains("ourCompany"), "{error}");
+}
+
+#[test]
+fn currying() {
+    install();
+
+    assert_eq!(
+        eval(
+            "let
+              inherit (builtins.ourCompany) add digits;
+              inc = add 1;
+              digits12 = digits 1 2;
+            in [ (inc 2) (inc 3) (digits12 3) (digits12 4) ((digits 5) 6 7) ]"
+        )
+        .unwrap(),
+        "[ 3 4 123 124 567 ]"
+    );
+}
+
+#[test]
+fn type_error() {
+    install();
+
+    let error = eval(r#"builtins.ourCompany.digits 1 "2" 3"#).unwrap_err();
+    assert!(
+        error.message.contains("expected an integer but got string"),
+        "{error}"
+    );
+}
@@ -0,0 +1,367 @@
+//! Tests of `nix-compiler lsp` with a scripted client, every test starts a
+//! server and talks to it over stdio like an editor.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{json, Value};
+
+const URI: &str = "file:///tmp/lsp%20test.nix";
+
+struct Client {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    last_id: u64,
+    /// Notifications received while waiting for a response
+    notifications: Vec<Value>,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut server = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        Self {
+            stdin: server.stdin.take().unwrap(),
+            stdout: BufReader::new(server.stdout.take().unwrap()),
+            server,
+            last_id: 0,
+            notifications: Vec::new(),
+        }
+    }
+
+    /// Starts a server, initializes it and opens a document with `text`
+    fn with_document(text: &str) -> Self {
+        let mut client = Self::start();
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client.open(text);
+        client
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = None;
+
+        loop {
+            let mut line = String::new();
+            assert_ne!(
+                self.stdout.read_line(&mut line).unwrap(),
+                0,
+                "Server exited"
+            );
+
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = Some(value.parse().unwrap());
+            }
+        }
+
+        let mut body = vec![0; length.expect("Missing Content-Length")];
+        self.stdout.read_exact(&mut body).unwrap();
+
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Whole response, with `result` or `error`
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.last_id += 1;
+        let id = self.last_id;
+
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+
+        loop {
+            let message = self.receive();
+
+            if message["id"] == id {
+                return message;
+            }
+
+            self.notifications.push(message);
+        }
+    }
+
+    fn open(&mut self, text: &str) {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": URI, "languageId": "nix", "version": 1, "text": text },
+            }),
+        );
+    }
+
+    /// Diagnostics of the next `publishDiagnostics`
+    fn diagnostics(&mut self) -> Vec<Value> {
+        let message = match self.notifications.pop() {
+            Some(message) => message,
+            None => self.receive(),
+        };
+
+        assert_eq!(message["method"], "textDocument/publishDiagnostics");
+        assert_eq!(message["params"]["uri"], URI);
+
+        message["params"]["diagnostics"].as_array().unwrap().clone()
+    }
+
+    fn at(&mut self, method: &str, position: Value) -> Value {
+        let response = self.request(
+            method,
+            json!({ "textDocument": { "uri": URI }, "position": position }),
+        );
+
+        assert!(response.get("error").is_none(), "{response}");
+
+        response["result"].clone()
+    }
+
+    fn labels(&mut self, position: Value) -> Vec<String> {
+        self.at("textDocument/completion", position)["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_owned())
+            .collect()
+    }
+
+    fn exit(mut self) -> i32 {
+        self.notify("exit", Value::Null);
+        self.server.wait().unwrap().code().unwrap()
+    }
+}
+
+/// Position of the character `offset` bytes after the first `needle`, the
+/// text before it must be ASCII
+fn position(text: &str, needle: &str, offset: usize) -> Value {
+    let idx = text.find(needle).expect(needle) + offset;
+    let line = text[..idx].matches('\n').count();
+    let character = idx - text[..idx].rfind('\n').map_or(0, |idx| idx + 1);
+
+    json!({ "line": line, "character": character })
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+#[test]
+fn lifecycle() {
+    let mut client = Client::start();
+
+    let response = client.request("textDocument/hover", json!({}));
+    assert_eq!(response["error"]["code"], -32002);
+
+    let response = client.request("initialize", json!({ "capabilities": {} }));
+    let capabilities = &response["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(
+        capabilities["completionProvider"]["triggerCharacters"],
+        json!(["."])
+    );
+
+    let response = client.request("textDocument/formatting", json!({}));
+    assert_eq!(response["error"]["code"], -32601);
+
+    let response = client.request("shutdown", Value::Null);
+    assert_eq!(response["result"], Value::Null);
+
+    assert_eq!(client.exit(), 0);
+}
+
+#[test]
+fn exit_without_shutdown() {
+    let mut client = Client::start();
+    client.request("initialize", json!({ "capabilities": {} }));
+
+    assert_eq!(client.exit(), 1);
+}
+
+#[test]
+fn diagnostics() {
+    // The columns are in UTF-16, `😀` is 2 units
+    let mut client = Client::with_document("let a = \"😀\"; in a + b");
+
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["message"], "Variable 'b' not found");
+    assert_eq!(diagnostics[0]["range"], range((0, 21), (0, 22)));
+    assert_eq!(diagnostics[0]["severity"], 1);
+
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [{ "text": "{ a = 1;\n  b = ; }" }],
+        }),
+    );
+
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "Unexpected token ';', expected one of '(', 'rec', '{', '[', '\"', '<identifier>'"
+    );
+    assert_eq!(diagnostics[0]["range"], range((1, 6), (1, 7)));
+
+    // Variables of `with` are only known when evaluating
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": URI, "version": 3 },
+            "contentChanges": [{ "text": "with builtins; x: x + length [ ] + y" }],
+        }),
+    );
+
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    client.notify(
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+}
+
+#[test]
+fn definition() {
+    let text = "\
+let
+  x = 1;
+  f = y: { a, b ? x, ... }@args: [ x y a b args ];
+  s = rec { p = x; q = p; };
+  inner = let inherit x; in x;
+in
+s.q";
+
+    let mut client = Client::with_document(text);
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    let definition = |client: &mut Client, needle, offset| {
+        let result = client.at("textDocument/definition", position(text, needle, offset));
+        assert_eq!(result["uri"], URI, "{needle}");
+        result["range"].clone()
+    };
+
+    // Let
+    assert_eq!(definition(&mut client, "[ x", 2), range((1, 2), (1, 3)));
+    // Lambda arguments, with and without a pattern
+    assert_eq!(definition(&mut client, "x y", 2), range((2, 6), (2, 7)));
+    assert_eq!(definition(&mut client, "y a", 2), range((2, 11), (2, 12)));
+    assert_eq!(definition(&mut client, "a b", 2), range((2, 14), (2, 15)));
+    assert_eq!(
+        definition(&mut client, "b args", 2),
+        range((2, 27), (2, 31))
+    );
+    // Default values see the outer variables
+    assert_eq!(definition(&mut client, "? x", 2), range((1, 2), (1, 3)));
+    // Recursive sets
+    assert_eq!(definition(&mut client, "q = p", 4), range((3, 12), (3, 13)));
+    // `inherit x;` refers to the outer `x`, and defines a new one
+    assert_eq!(
+        definition(&mut client, "inherit x", 8),
+        range((1, 2), (1, 3))
+    );
+    assert_eq!(definition(&mut client, "in x;", 3), range((4, 22), (4, 23)));
+    assert_eq!(definition(&mut client, "s.q", 0), range((3, 2), (3, 3)));
+
+    // Attribute names and globals aren't variables of the document
+    let result = client.at("textDocument/definition", position(text, "s.q", 2));
+    assert_eq!(result, Value::Null);
+
+    let result = client.at("textDocument/definition", position(text, "let", 0));
+    assert_eq!(result, Value::Null);
+}
+
+#[test]
+fn hover() {
+    let text = "\
+let
+  x = 1 + 2;
+  s = rec { p = x + 3; q = [ p ]; };
+  f = { a }: a;
+in
+builtins.length s.q";
+
+    let mut client = Client::with_document(text);
+    client.diagnostics();
+
+    let hover = |client: &mut Client, needle, offset| {
+        let result = client.at("textDocument/hover", position(text, needle, offset));
+        assert_eq!(result["contents"]["kind"], "markdown", "{needle}");
+        result["contents"]["value"].as_str().unwrap().to_owned()
+    };
+
+    assert_eq!(
+        hover(&mut client, "x =", 0),
+        "`x`: let binding\n\n```nix\n3\n```\n\nType: `int`"
+    );
+    assert_eq!(
+        hover(&mut client, "p ]", 0),
+        "`p`: attribute of a recursive set\n\n```nix\n6\n```\n\nType: `int`"
+    );
+    assert_eq!(
+        hover(&mut client, "s.q", 0),
+        "`s`: let binding\n\n```nix\n{ p = 6; q = [ «thunk» ]; }\n```\n\nType: `set`"
+    );
+    assert_eq!(
+        hover(&mut client, "s.q", 2),
+        "```nix\n[ 6 ]\n```\n\nType: `list`"
+    );
+    assert_eq!(hover(&mut client, "a;", 0), "`a`: function argument");
+    assert_eq!(
+        hover(&mut client, "f =", 0),
+        "`f`: let binding\n\nType: `lambda`"
+    );
+    assert!(hover(&mut client, "length", 0).starts_with("```nix\nbuiltins.length list\n```"));
+
+    let result = client.at("textDocument/hover", position(text, "x =", 0));
+    assert_eq!(result["range"], range((1, 2), (1, 3)));
+}
+
+#[test]
+fn completion() {
+    let text = "\
+let
+  set = { foo = 1; bar.baz = 2; };
+  value = 1;
+in
+[ builtins.ma set. set.bar. va ]";
+
+    let mut client = Client::with_document(text);
+    client.diagnostics();
+
+    assert_eq!(
+        client.labels(position(text, "ma set", 2)),
+        ["map", "mapAttrs", "match"]
+    );
+    assert_eq!(client.labels(position(text, "set. ", 4)), ["bar", "foo"]);
+    assert_eq!(client.labels(position(text, "bar. ", 4)), ["baz"]);
+    assert_eq!(client.labels(position(text, "va ]", 2)), ["value"]);
+
+    let labels = client.labels(position(text, "[ ", 2));
+    assert!(labels.contains(&"builtins".to_owned()));
+    assert!(labels.contains(&"set".to_owned()));
+    assert!(labels.contains(&"value".to_owned()));
+}
@@ -0,0 +1,57 @@
+//! Tests of `nix!`, the expressions that compile are evaluated, and the ones
+//! in `tests/nix_macro/*.rs` must fail with the error in the `.stderr` file
+//! next to them.
+
+use nix_compiler::nix;
+
+#[test]
+fn eval() {
+    assert_eq!(nix! { 1 + 2 }.eval_as::<i64>().unwrap(), 3);
+    assert_eq!(
+        nix! { let a = { b = "c"; }; in a.b }
+            .eval_as::<String>()
+            .unwrap(),
+        "c"
+    );
+}
+
+#[test]
+fn splices() {
+    let name = "world";
+    let list = vec![1, 2, 3];
+
+    assert_eq!(
+        nix! { "hello " + #{name} }.eval_as::<String>().unwrap(),
+        "hello world"
+    );
+    assert_eq!(
+        nix! { builtins.length #{list} }.eval_as::<i64>().unwrap(),
+        3
+    );
+}
+
+#[test]
+fn spacing() {
+    let expr = nix! { let a-b = 1; in a-b };
+
+    assert_eq!(expr.source().trim(), "let a-b = 1; in a-b");
+    assert_eq!(expr.eval_as::<i64>().unwrap(), 1);
+}
+
+#[test]
+fn comments() {
+    let expr = nix! { 1 /* comment */ + 2 };
+
+    assert_eq!(expr.source().trim(), "1  + 2");
+    assert_eq!(expr.eval_as::<i64>().unwrap(), 3);
+
+    assert_eq!(
+        nix! { "http://example.com" }.eval_as::<String>().unwrap(),
+        "http://example.com"
+    );
+}
+
+#[test]
+fn compile_errors() {
+    trybuild::TestCases::new().compile_fail("tests/nix_macro/*.rs");
+}
@@ -0,0 +1,5 @@
+use nix_compiler::nix;
+
+fn main() {
+    nix! { let a = 1; a };
+}
@@ -0,0 +1,7 @@
+use nix_compiler::nix;
+
+fn main() {
+    nix! {
+        { a = 1; } // { b = 2; }
+    };
+}
@@ -0,0 +1,76 @@
+//! Tests of `--plugin`, the plugin in `tests/plugin` is built with the same
+//! compiler, and with `--features wrong-version` to check that it's rejected.
+
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use nix_compiler::plugin::{NixPlugin, NixPluginError, CORE_VERSION};
+
+/// Builds the test plugin and copies it to `name`, returns its path
+fn build_plugin(name: &str, features: &[&str]) -> PathBuf {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("plugin");
+
+    // Same versions of the dependencies, newer ones can need a newer compiler
+    fs::copy(
+        manifest_dir.join("Cargo.lock"),
+        manifest_dir.join("tests/plugin/Cargo.lock"),
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO"))
+        .arg("build")
+        .arg("--manifest-path")
+        .arg(manifest_dir.join("tests/plugin/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .arg("--features")
+        .arg(features.join(","))
+        .status()
+        .unwrap();
+    assert!(status.success(), "Cannot build the test plugin");
+
+    let library = format!("{DLL_PREFIX}nix_compiler_test_plugin{DLL_SUFFIX}");
+    let path = target_dir.join(format!("{name}{DLL_SUFFIX}"));
+    fs::copy(target_dir.join("debug").join(library), &path).unwrap();
+
+    path
+}
+
+#[test]
+fn plugin() {
+    // Both are built in the same test, so they don't build the same crate at
+    // the same time
+    let plugin = build_plugin("plugin", &[]);
+    let wrong_version = build_plugin("wrong-version", &["wrong-version"]);
+
+    assert_eq!(NixPlugin::load(&plugin).unwrap().namespace(), "testPlugin");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+        .arg("--plugin")
+        .arg(&plugin)
+        .arg("-e")
+        .arg("builtins.testPlugin.greet \"world\"")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\"Hello world!\"\n"
+    );
+
+    let error = NixPlugin::load(&wrong_version).err().unwrap();
+
+    assert!(matches!(error, NixPluginError::CoreVersion { .. }));
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "Plugin {} was built against nix-compiler 0.0.0, but {} is running",
+            wrong_version.display(),
+            CORE_VERSION.to_str().unwrap()
+        )
+    );
+}
@@ -0,0 +1,28 @@
+use nix_compiler::plugin::NixPluginRegistrar;
+use nix_compiler::{NixBacktrace, NixHostBuiltin, NixValue};
+
+fn register(registrar: &mut NixPluginRegistrar) {
+    registrar.register(NixHostBuiltin::from_fn(
+        "greet",
+        |_: &NixBacktrace, name: String| Ok(NixValue::String(format!("Hello {name}!")).wrap()),
+    ));
+}
+
+#[cfg(not(feature = "wrong-version"))]
+nix_compiler::declare_plugin!("testPlugin", register);
+
+#[cfg(feature = "wrong-version")]
+unsafe extern "C" fn register_c(registrar: *mut NixPluginRegistrar) {
+    register(unsafe { &mut *registrar })
+}
+
+#[cfg(feature = "wrong-version")]
+#[no_mangle]
+pub static NIX_PLUGIN_DECLARATION: nix_compiler::plugin::NixPluginDeclaration =
+    nix_compiler::plugin::NixPluginDeclaration {
+        abi_version: nix_compiler::plugin::NIX_PLUGIN_ABI_VERSION,
+        rustc_version: nix_compiler::plugin::RUSTC_VERSION.as_ptr(),
+        core_version: c"0.0.0".as_ptr(),
+        namespace: c"testPlugin".as_ptr(),
+        register: register_c,
+    };
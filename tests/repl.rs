@@ -0,0 +1,42 @@
+//! Tests of `nix-compiler repl`, the lines are written to its stdin.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs the REPL with `input`, and returns what it printed
+fn repl(input: &str) -> String {
+    let mut repl = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = repl.wait_with_output().unwrap();
+
+    String::from_utf8_lossy(&output.stdout).into_owned() + &String::from_utf8_lossy(&output.stderr)
+}
+
+#[test]
+fn bindings() {
+    let output = repl("x = 1\ny = x + 1\ny\n:q\n");
+
+    assert!(output.contains("\n2\n"), "{output}");
+}
+
+#[test]
+fn lazy_bindings() {
+    // The binding fails when it's used, and the other ones still work
+    let output = repl("x = { a = 1; }.b\ny = 2\ny\nx\n:q\n");
+
+    assert!(output.contains("\n2\n"), "{output}");
+    assert_eq!(output.matches("Attribute missing").count(), 1, "{output}");
+    assert!(!output.contains("not found"), "{output}");
+}
@@ -0,0 +1,54 @@
+//! Golden tests of `--xml` and `builtins.toXML`, the output of every
+//! `tests/xml/<name>.nix` must be the same as `tests/xml/<name>.xml`.
+//!
+//! `@DIR@` in the expected output is the directory of the tests, and the files
+//! starting with `to-xml` are printed with `--raw` instead of `--xml`.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+#[test]
+fn golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/xml");
+
+    let mut files = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "nix"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    assert!(!files.is_empty(), "No tests in {}", dir.display());
+
+    for file in files {
+        let name = file.file_stem().unwrap().to_string_lossy();
+        let mode = if name.starts_with("to-xml") {
+            "--raw"
+        } else {
+            "--xml"
+        };
+
+        let expected = fs::read_to_string(file.with_extension("xml"))
+            .unwrap()
+            .replace("@DIR@", &dir.display().to_string());
+
+        let output = Command::new(env!("CARGO_BIN_EXE_nix-compiler"))
+            .arg(mode)
+            .arg(&file)
+            .output()
+            .unwrap();
+
+        assert!(
+            output.status.success(),
+            "{name} failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "Output of {name} differs"
+        );
+    }
+}
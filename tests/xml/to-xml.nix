@@ -0,0 +1,6 @@
+# `builtins.toXML` doesn't have positions
+builtins.toXML {
+  list = [ 1 "two" null ];
+  function = { a, b }: a;
+  string = "multi\nline";
+}
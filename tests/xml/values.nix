@@ -0,0 +1,22 @@
+# Every type of value with the positions of `--xml`
+let
+  hello = {
+    type = "derivation";
+    name = "hello";
+    drvPath = "/nix/store/00000000000000000000000000000000-hello.drv";
+    outPath = "/nix/store/00000000000000000000000000000000-hello";
+  };
+in {
+  int = 1;
+  float = [ 1.5 0.1 123456.789 1.0e20 0.00001 ];
+  bool = true;
+  null = null;
+  string = "<a href=\"x\">&</a>\n";
+  nested.attrs = { };
+  inherit hello;
+  repeated = [ hello hello ];
+  function = x: x;
+  pattern = args@{ b, a ? 1, ... }: a;
+  builtin = builtins.map;
+  partial = builtins.map (x: x);
+}